@@ -0,0 +1,69 @@
+// Embeds a paste on another site:
+//   <script src="https://paste.example.com/assets/embed.js" data-paste="<url>"></script>
+// The paste is rendered into a shadow root in place of the script tag, so the page's styles and the paste's don't affect each other
+(function () {
+    const script = document.currentScript;
+    const paste = script && script.dataset.paste;
+    if (!paste) {
+        return;
+    }
+    const origin = new URL(script.src).origin;
+    // Pastes in namespaces are embedded as `<namespace>/<url>`
+    const path = '/' + paste.split('/').map(encodeURIComponent).join('/');
+    const style = `
+        :host { display: block; margin: 1em 0; }
+        .pasties-embed {
+            font-family: Inter, system-ui, sans-serif;
+            line-height: 1.5;
+            color: #fefefe;
+            background-color: hsl(0, 0%, 7%);
+            border-radius: 0.2rem;
+            overflow: hidden;
+        }
+        article { padding: 1.25rem; overflow-x: auto; }
+        article > :first-child { margin-top: 0; }
+        article > :last-child { margin-bottom: 0; }
+        a { color: inherit; }
+        pre, code { font-family: "JetBrains Mono", monospace; }
+        code { background-color: hsl(0, 0%, 14%); padding: 0.1rem 0.3rem; border-radius: 0.1rem; }
+        pre { overflow-x: auto; }
+        pre code { padding: 0; background: none; }
+        table { border-collapse: collapse; }
+        th, td { border: 1px solid hsl(0, 0%, 20%); padding: 0.5rem; text-align: left; }
+        img { max-width: 100%; }
+        footer {
+            padding: 0.5rem 1.25rem;
+            font-size: 0.85em;
+            color: hsl(0, 0%, 70%);
+            background-color: hsl(0, 0%, 14%);
+        }
+        .paste-font-serif article { font-family: Georgia, serif; }
+        .paste-font-mono article { font-family: "JetBrains Mono", monospace; }
+        .paste-wrap pre { white-space: pre-wrap; word-break: break-word; }
+    `;
+    const host = document.createElement('div');
+    host.className = 'pasties-embed-host';
+    script.replaceWith(host);
+    const root = host.attachShadow({ mode: 'open' });
+    fetch(origin + path + '/fragment', { headers: { Accept: 'application/json' } })
+        .then(response => response.ok ? response.json() : Promise.reject(response.status))
+        .then(fragment => {
+            root.innerHTML = '<style>' + style + '</style>' + fragment.html;
+            // Links and images in pastes are relative to the instance, not to the embedding page
+            root.querySelectorAll('[href^="/"], [src^="/"]').forEach(element => {
+                for (const attribute of ['href', 'src']) {
+                    const value = element.getAttribute(attribute);
+                    if (value && value.startsWith('/') && !value.startsWith('//')) {
+                        element.setAttribute(attribute, origin + value);
+                    }
+                }
+            });
+            root.querySelectorAll('article a').forEach(link => link.target = '_blank');
+        })
+        .catch(() => {
+            const link = document.createElement('a');
+            link.href = origin + path;
+            link.textContent = 'View the paste on ' + new URL(origin).host;
+            root.replaceChildren(link);
+        });
+})();
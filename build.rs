@@ -0,0 +1,24 @@
+//! Embeds the commit pasties was built from, for `/meta/version`
+//! `PASTIES_COMMIT` can be set when building outside of a git checkout, e.g. from a release tarball
+
+use std::{env, process::Command};
+
+fn main() {
+    println!("cargo:rerun-if-env-changed=PASTIES_COMMIT");
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs/heads");
+    let commit = env::var("PASTIES_COMMIT").ok().or_else(|| {
+        let output = Command::new("git")
+            .args(["rev-parse", "--short", "HEAD"])
+            .output()
+            .ok()?;
+        match output.status.success() {
+            true => Some(String::from_utf8_lossy(&output.stdout).trim().to_string()),
+            false => None,
+        }
+    });
+    println!(
+        "cargo:rustc-env=PASTIES_COMMIT={}",
+        commit.unwrap_or("unknown".to_string())
+    );
+}
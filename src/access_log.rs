@@ -0,0 +1,167 @@
+//! `access_log` writes one line per request in a standard format, separate from the application logs, for tools like fail2ban and traffic analysers
+
+use std::{
+    fs::OpenOptions,
+    io::{self, Write},
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use axum::{
+    body::HttpBody,
+    extract::{ConnectInfo, Request, State},
+    http::{header, HeaderMap, HeaderName},
+    middleware::Next,
+    response::Response,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{config::Config, request_id, utility};
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AccessLogFormat {
+    /// The Apache/nginx "combined" format, followed by the latency in milliseconds
+    #[default]
+    Combined,
+    /// One JSON object per line
+    Json,
+}
+
+/// A single request as recorded in the access log
+#[derive(Serialize)]
+struct AccessLogEntry {
+    timestamp:  i64,
+    ip:         String,
+    method:     String,
+    path:       String,
+    protocol:   String,
+    status:     u16,
+    bytes:      Option<u64>,
+    latency_ms: u128,
+    referer:    Option<String>,
+    user_agent: Option<String>,
+    request_id: Option<String>,
+}
+
+#[derive(Clone)]
+pub struct AccessLog {
+    format:  AccessLogFormat,
+    writer:  Arc<Mutex<Box<dyn Write + Send>>>,
+    /// Leaves client addresses out of the log, see `Config::privacy_mode`
+    privacy: bool,
+}
+
+impl AccessLog {
+    /// Opens the access log described by `[access_log]`, or returns `None` if it is not enabled
+    /// **Panics** if the log file cannot be opened
+    pub fn from_config(config: &Config) -> Option<Self> {
+        let access_log = config.access_log.as_ref()?;
+        let writer: Box<dyn Write + Send> = match &access_log.path {
+            Some(path) => match OpenOptions::new().create(true).append(true).open(path) {
+                Ok(file) => Box::new(file),
+                Err(e) => panic!("Failed to open the access log at {path}:\n    {e}"),
+            },
+            None => Box::new(io::stdout()),
+        };
+        Some(Self {
+            format:  access_log.format,
+            writer:  Arc::new(Mutex::new(writer)),
+            privacy: config.privacy_mode,
+        })
+    }
+
+    fn write(&self, entry: AccessLogEntry) {
+        let line = match self.format {
+            AccessLogFormat::Combined => format!(
+                "{} - - [{}] \"{} {} {}\" {} {} \"{}\" \"{}\" {}",
+                entry.ip,
+                utility::clf_timestamp(entry.timestamp),
+                escape(&entry.method),
+                escape(&entry.path),
+                entry.protocol,
+                entry.status,
+                entry.bytes.map_or("-".to_string(), |b| b.to_string()),
+                escape(entry.referer.as_deref().unwrap_or("-")),
+                escape(entry.user_agent.as_deref().unwrap_or("-")),
+                entry.latency_ms
+            ),
+            AccessLogFormat::Json => match serde_json::to_string(&entry) {
+                Ok(line) => line,
+                Err(_) => return,
+            },
+        };
+        let mut writer = match self.writer.lock() {
+            Ok(writer) => writer,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        if let Err(e) = writeln!(writer, "{line}").and_then(|_| writer.flush()) {
+            tracing::warn!("Failed to write to the access log: {e}");
+        }
+    }
+}
+
+/// Escapes quotes, backslashes and control characters as `\xHH`, like nginx does, so clients can't end a quoted field or a line early
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' | '\\' => escaped.push_str(&format!("\\x{:02X}", c as u32)),
+            c if c.is_control() => {
+                let mut bytes = [0; 4];
+                for byte in c.encode_utf8(&mut bytes).bytes() {
+                    escaped.push_str(&format!("\\x{byte:02X}"));
+                }
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Middleware that records every request once its response is ready
+pub async fn log_access(
+    State(access_log): State<AccessLog>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let start = Instant::now();
+    let referer = header_value(request.headers(), header::REFERER);
+    let user_agent = header_value(request.headers(), header::USER_AGENT);
+    let method = request.method().to_string();
+    let path = request
+        .uri()
+        .path_and_query()
+        .map_or(request.uri().path().to_string(), |p| p.to_string());
+    let protocol = format!("{:?}", request.version());
+
+    let response = next.run(request).await;
+
+    access_log.write(AccessLogEntry {
+        timestamp: utility::unix_timestamp(),
+        // Connections over unix sockets have no peer address
+        ip: match connect_info {
+            Some(ConnectInfo(address)) if !access_log.privacy => address.ip().to_string(),
+            _ => "-".to_string(),
+        },
+        method,
+        path,
+        protocol,
+        status: response.status().as_u16(),
+        bytes: response.body().size_hint().exact(),
+        latency_ms: start.elapsed().as_millis(),
+        referer,
+        user_agent,
+        request_id: request_id::current(),
+    });
+    response
+}
+
+fn header_value(headers: &HeaderMap, name: HeaderName) -> Option<String> {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+}
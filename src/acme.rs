@@ -0,0 +1,43 @@
+//! `acme` serves pasties over TLS with certificates that are provisioned and renewed automatically through ACME (e.g. Let's Encrypt), for deployments without a reverse proxy
+//! Certificates are validated with the TLS-ALPN-01 challenge, so only the TLS port needs to be reachable
+
+use axum::Router;
+use futures_util::StreamExt;
+use rustls_acme::caches::DirCache;
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::TcpListenerStream;
+
+use crate::{config::AcmeConfig, server::Transport};
+
+/// The protocols offered to clients during the TLS handshake, HTTP/2 only if `[server] http2` is on
+const ALPN_PROTOCOLS: [&[u8]; 2] = [b"h2", b"http/1.1"];
+
+/// Accepts TLS connections on `listener` until the process is stopped, and serves `app` on each of them
+/// Certificates are cached in the configured directory, so restarts don't count against the provider's rate limits
+pub async fn serve(listener: TcpListener, config: AcmeConfig, app: Router, transport: Transport) {
+    let mut incoming = rustls_acme::AcmeConfig::new(&config.domains)
+        .contact(config.contact.iter().map(|email| format!("mailto:{email}")))
+        .cache(DirCache::new(config.cache_dir))
+        .directory_lets_encrypt(!config.staging)
+        .tokio_incoming(
+            TcpListenerStream::new(listener),
+            ALPN_PROTOCOLS
+                .iter()
+                .filter(|protocol| transport.http2() || **protocol != b"h2")
+                .map(|protocol| protocol.to_vec())
+                .collect(),
+        );
+    loop {
+        let permit = transport.admit().await;
+        let Some(connection) = incoming.next().await else {
+            break;
+        };
+        match connection {
+            Ok(tls) => {
+                let address = tls.get_ref().get_ref().0.get_ref().peer_addr().ok();
+                transport.serve_connection(tls, address, app.clone(), permit);
+            }
+            Err(e) => tracing::debug!("TLS handshake failed: {e}"),
+        }
+    }
+}
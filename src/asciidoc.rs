@@ -0,0 +1,23 @@
+//! `asciidoc` renders AsciiDoc pastes, only compiled with the `asciidoc` feature
+//! Documents are parsed in asciidork's secure mode, so pastes cannot include files from the server
+
+use asciidork_core::JobSettings;
+use asciidork_parser::{prelude::*, Parser};
+
+use crate::{render::Renderer, utility};
+
+pub struct AsciiDocRenderer;
+
+impl Renderer for AsciiDocRenderer {
+    fn render(&self, content: &str) -> String {
+        let bump = Bump::new();
+        let mut parser = Parser::from_str(content, SourceFile::Tmp, &bump);
+        parser.apply_job_settings(JobSettings::embedded());
+        let html = match parser.parse() {
+            Ok(result) => asciidork_dr_html_backend::convert(result.document).ok(),
+            Err(_) => None,
+        };
+        // Content that fails to parse is still worth showing as-is
+        html.unwrap_or_else(|| format!("<pre>{}</pre>", utility::escape_html(content)))
+    }
+}
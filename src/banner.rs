@@ -0,0 +1,116 @@
+//! `banner` shows an announcement set by the admins at the top of every page, for maintenance windows and the like
+//! The banner is stored in the database and kept in memory by the `Manager`, and made available to templates for the duration of each request
+
+use std::sync::{Arc, RwLock};
+
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{database::BannerEntry, markdown, model::Manager, utility};
+
+/// How prominently the banner is shown
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Info,
+    Warning,
+    Critical,
+}
+
+impl Severity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Critical => "critical",
+        }
+    }
+
+    fn parse(severity: &str) -> Self {
+        match severity {
+            "warning" => Severity::Warning,
+            "critical" => Severity::Critical,
+            _ => Severity::Info,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Banner {
+    /// The announcement, as markdown
+    pub message:  String,
+    pub severity: Severity,
+    /// The unix timestamp the banner is hidden at, if it expires
+    pub expires:  Option<i64>,
+}
+
+impl Banner {
+    /// The message rendered as HTML. Only admins can set it, so it is trusted like the meta pages are
+    pub fn html(&self) -> String {
+        markdown::render_markdown(&self.message)
+    }
+
+    fn is_active(&self) -> bool {
+        self.expires
+            .is_none_or(|expires| expires > utility::unix_timestamp())
+    }
+}
+
+impl From<BannerEntry> for Banner {
+    fn from(entry: BannerEntry) -> Self {
+        Self {
+            message:  entry.message,
+            severity: Severity::parse(&entry.severity),
+            expires:  entry.expires,
+        }
+    }
+}
+
+impl From<&Banner> for BannerEntry {
+    fn from(banner: &Banner) -> Self {
+        Self {
+            message:  banner.message.clone(),
+            severity: banner.severity.as_str().to_string(),
+            expires:  banner.expires,
+        }
+    }
+}
+
+/// The banner currently set, shared between a manager's clones so changes show up everywhere at once
+#[derive(Clone, Default)]
+pub struct CurrentBanner(Arc<RwLock<Option<Banner>>>);
+
+impl CurrentBanner {
+    pub fn new(banner: Option<Banner>) -> Self {
+        Self(Arc::new(RwLock::new(banner)))
+    }
+
+    /// The banner, unless none is set or it has expired
+    pub fn get(&self) -> Option<Banner> {
+        // A panic while holding the lock can't leave the banner half-written, so poisoning is ignored
+        let banner = self.0.read().unwrap_or_else(|e| e.into_inner());
+        banner.as_ref().filter(|banner| banner.is_active()).cloned()
+    }
+
+    pub fn set(&self, banner: Option<Banner>) {
+        *self.0.write().unwrap_or_else(|e| e.into_inner()) = banner;
+    }
+}
+
+tokio::task_local! {
+    static BANNER: Option<Banner>;
+}
+
+/// Returns the banner to show on the page currently being rendered, if called from within a request
+pub fn current() -> Option<Banner> {
+    BANNER.try_with(|banner| banner.clone()).ok().flatten()
+}
+
+/// Middleware that makes the current banner available to the templates rendered for the rest of the request
+pub async fn provide_banner(State(manager): State<Manager>, req: Request, next: Next) -> Response {
+    BANNER.scope(manager.banner(), next.run(req)).await
+}
@@ -0,0 +1,66 @@
+//! `cli` parses the command-line arguments pasties is started with
+
+use std::env;
+
+use crate::migrate::Source;
+
+const USAGE: &str = "Usage: pasties [--dev]\n       pasties backup <path>\n       pasties doctor\n       pasties migrate --from <microbin|opengist|privatebin> --path <data>";
+
+#[derive(Debug, Clone, Default)]
+pub struct Args {
+    /// Development mode: assets are re-read from disk on every request and responses are marked as uncacheable. Templates are compiled in by askama and aren't reloaded
+    pub dev:     bool,
+    /// A task to run instead of the server
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Command {
+    /// Writes a consistent snapshot of the database to a new file at the path, which is safe while the server runs
+    Backup(String),
+    /// Checks the configuration, database and files, and reports what would keep the server from starting
+    Doctor,
+    /// Imports the pastes of another pastebin from its data at `path`
+    Migrate { from: Source, path: String },
+}
+
+impl Args {
+    /// Parses the process' arguments
+    /// **Panics** on unknown arguments, so that typos don't silently start a differently configured server
+    pub fn parse() -> Self {
+        let mut args = Self::default();
+        let mut arguments = env::args().skip(1);
+        // `migrate` takes its options in any order, so the command is only built once all arguments are read
+        let mut migrate = false;
+        let mut from = None;
+        let mut path = None;
+        while let Some(arg) = arguments.next() {
+            match arg.as_str() {
+                "--dev" => args.dev = true,
+                "backup" if args.command.is_none() => match arguments.next() {
+                    Some(path) => args.command = Some(Command::Backup(path)),
+                    None => panic!("Missing the path to write the backup to\n{USAGE}"),
+                },
+                "doctor" if args.command.is_none() => args.command = Some(Command::Doctor),
+                "migrate" if args.command.is_none() => migrate = true,
+                "--from" if migrate => match arguments.next().map(|from| from.parse()) {
+                    Some(Ok(source)) => from = Some(source),
+                    Some(Err(e)) => panic!("{e}\n{USAGE}"),
+                    None => panic!("Missing the pastebin to migrate from\n{USAGE}"),
+                },
+                "--path" if migrate => match arguments.next() {
+                    Some(data) => path = Some(data),
+                    None => panic!("Missing the path to the data to migrate\n{USAGE}"),
+                },
+                _ => panic!("Unknown argument: {arg}\n{USAGE}"),
+            }
+        }
+        if migrate {
+            match (from, path) {
+                (Some(from), Some(path)) => args.command = Some(Command::Migrate { from, path }),
+                _ => panic!("Migrating needs both --from and --path\n{USAGE}"),
+            }
+        }
+        args
+    }
+}
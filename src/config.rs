@@ -0,0 +1,463 @@
+//! `config` loads the instance configuration from a TOML file
+//! Every setting is optional, so pasties runs with sensible defaults when no file is present
+
+use std::{env, fs, io::ErrorKind};
+
+use serde::Deserialize;
+
+use crate::{
+    access_log::AccessLogFormat, filters::FilterAction, links::LinkAction, logging::LogOutput,
+    render::DefaultFormat, sso::SsoRequirement, webhooks::WebhookKind,
+};
+
+/// The path the configuration is read from, unless overridden by the `PASTIES_CONFIG` environment variable
+const DEFAULT_CONFIG_PATH: &str = "./pasties.toml";
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Config {
+    /// Addresses to serve on, see `server::bind` for the accepted forms
+    pub listen:            Vec<String>,
+    pub server:            ServerConfig,
+    pub error_reporting:   Option<ErrorReportingConfig>,
+    pub access_log:        Option<AccessLogConfig>,
+    pub logging:           LoggingConfig,
+    pub acme:              Option<AcmeConfig>,
+    /// Minifies rendered HTML before it is sent
+    pub minify_html:       bool,
+    /// The URL pasties is publicly reachable at, e.g. `https://paste.example.com`, for responses that contain absolute links
+    pub base_url:          Option<String>,
+    pub termbin:           Option<TermbinConfig>,
+    pub gemini:            Option<GeminiConfig>,
+    pub rate_limit:        Option<RateLimitConfig>,
+    /// Token for the admin endpoints under `/api/admin`, sent as `Authorization: Bearer <token>`. The endpoints are disabled without one
+    pub admin_token:       Option<String>,
+    /// Chat services notified about new pastes
+    pub webhooks:          Vec<WebhookConfig>,
+    /// Keeps client IP addresses out of everything pasties stores or writes: the access log omits them and the rate limiter only keeps salted hashes
+    pub privacy_mode:      bool,
+    /// Rejects pastes that look like they contain credentials, instead of only warning about them
+    pub block_secrets:     bool,
+    pub link_safety:       Option<LinkSafetyConfig>,
+    /// Route external links in rendered pastes through the `/out` interstitial
+    pub link_interstitial: bool,
+    pub image_proxy:       Option<ImageProxyConfig>,
+    /// The format of pastes that don't specify one and whose format can't be detected: `markdown` or `plain`
+    pub default_format:    DefaultFormat,
+    /// Lowercases the URLs of new pastes, so URLs differing only in case can't be told apart
+    pub lowercase_urls:    bool,
+    pub meta:              MetaConfig,
+    pub jobs:              JobsConfig,
+    pub database:          DatabaseConfig,
+    /// Teams hosted on the instance, each with a URL space of its own under `/<name>`
+    pub namespaces:        Vec<NamespaceConfig>,
+    pub sso:               Option<SsoConfig>,
+    pub private:           Option<PrivateConfig>,
+    /// Anonymous clients can only read pastes, creating and changing them requires an API key, the admin token or an `[sso]` session
+    pub read_only:         bool,
+    /// Keys for creating and changing pastes on a read-only instance, sent as `Authorization: Bearer <key>`
+    pub api_keys:          Vec<String>,
+    /// Content policies new and updated pastes are checked against
+    pub filters:           Vec<FilterConfig>,
+    pub ipfs:              Option<IpfsConfig>,
+    /// The furthest in the future pastes can expire, in seconds. Longer expiries and extensions are shortened to it
+    pub max_expiry:        Option<i64>,
+    /// Counts the domains of the sites paste pages are opened from, which owners can look up
+    pub record_referrers:  bool,
+    pub mirror:            Option<MirrorConfig>,
+    /// Accepts changes pushed by another instance's `[mirror]` to `/api/mirror`, if they are signed with this secret
+    pub mirror_secret:     Option<String>,
+    /// Serves pastes over WebDAV at `/dav/`, see `Config::serves_webdav` for the default
+    pub webdav:            Option<bool>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            listen:            vec!["127.0.0.1:7878".to_string()],
+            server:            ServerConfig::default(),
+            error_reporting:   None,
+            access_log:        None,
+            logging:           LoggingConfig::default(),
+            acme:              None,
+            minify_html:       false,
+            base_url:          None,
+            termbin:           None,
+            gemini:            None,
+            rate_limit:        None,
+            admin_token:       None,
+            webhooks:          Vec::new(),
+            privacy_mode:      false,
+            block_secrets:     false,
+            link_safety:       None,
+            link_interstitial: false,
+            image_proxy:       None,
+            default_format:    DefaultFormat::default(),
+            lowercase_urls:    false,
+            meta:              MetaConfig::default(),
+            jobs:              JobsConfig::default(),
+            database:          DatabaseConfig::default(),
+            namespaces:        Vec::new(),
+            sso:               None,
+            private:           None,
+            read_only:         false,
+            api_keys:          Vec::new(),
+            filters:           Vec::new(),
+            ipfs:              None,
+            max_expiry:        None,
+            record_referrers:  true,
+            mirror:            None,
+            mirror_secret:     None,
+            webdav:            None,
+        }
+    }
+}
+
+/// Settings for forwarding panics and server errors to an external backend
+#[derive(Deserialize, Debug, Clone)]
+pub struct ErrorReportingConfig {
+    /// URL that receives a JSON report via `POST` for every panic or 5xx response
+    pub endpoint: String,
+}
+
+/// Settings for the access log, which is only written if this section is present
+#[derive(Deserialize, Debug, Clone)]
+pub struct AccessLogConfig {
+    #[serde(default)]
+    pub format: AccessLogFormat,
+    /// File to append the log to, or stdout if unset
+    pub path:   Option<String>,
+}
+
+/// Settings for the HTTP connections of every listener
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ServerConfig {
+    /// Whether clients may speak HTTP/2, negotiated during the TLS handshake or with prior knowledge on plain connections
+    pub http2:            bool,
+    /// How long a connection waits for its next request, and a client may take to send a request's headers, in seconds. 0 closes HTTP/1 connections after every response
+    pub keep_alive_secs:  u64,
+    /// The most connections open at once, across all listeners. Further connections wait to be accepted until others close
+    pub max_connections:  Option<usize>,
+    /// The largest a request's headers may be, in bytes
+    pub max_header_bytes: Option<usize>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            http2:            true,
+            keep_alive_secs:  30,
+            max_connections:  None,
+            max_header_bytes: None,
+        }
+    }
+}
+
+/// Settings for the application logs
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct LoggingConfig {
+    /// Where logs are written to: `stdout`, `syslog` or `journald`
+    pub output:        LogOutput,
+    /// Database calls taking at least this many milliseconds are logged as warnings, 0 turns this off
+    pub slow_query_ms: u64,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            output:        LogOutput::default(),
+            slow_query_ms: 250,
+        }
+    }
+}
+
+/// Pages the instance presents itself with under `/meta`, each one is only served if its file is set
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct MetaConfig {
+    /// Markdown file served at `/meta/about`
+    pub about:   Option<String>,
+    /// Markdown file served at `/meta/rules`
+    pub rules:   Option<String>,
+    /// Markdown file served at `/meta/terms`, with the instance's terms of service
+    pub terms:   Option<String>,
+    /// Markdown file served at `/meta/privacy`, with the instance's privacy policy
+    pub privacy: Option<String>,
+}
+
+/// Settings for the background worker that runs deferred jobs, like deleting expired pastes
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct JobsConfig {
+    /// How many jobs may run at the same time
+    pub concurrency: usize,
+}
+
+impl Default for JobsConfig {
+    fn default() -> Self {
+        Self { concurrency: 4 }
+    }
+}
+
+/// Settings for the connections to `main.db`
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct DatabaseConfig {
+    /// Read-only connections that paste views and listings are served from, so they don't wait for writes. 0 reads through the same connections as writes
+    pub read_connections: u32,
+}
+
+/// A content policy, see the `filters` module. It matches if either its pattern or one of its words is found
+#[derive(Deserialize, Debug, Clone)]
+pub struct FilterConfig {
+    /// Named in the errors clients get, e.g. `doxxing`
+    pub name:    String,
+    /// A regular expression, in the syntax of the `regex` crate
+    pub pattern: Option<String>,
+    /// Words and phrases, matched case-insensitively as whole words
+    #[serde(default)]
+    pub words:   Vec<String>,
+    #[serde(default)]
+    pub action:  FilterAction,
+}
+
+/// A namespace, whose pastes are served under `/<name>/<url>` and don't collide with pastes elsewhere
+#[derive(Deserialize, Debug, Clone)]
+pub struct NamespaceConfig {
+    pub name:               String,
+    /// Token for the namespace's admin endpoints under `/<name>/api/admin`, which are disabled without one
+    pub admin_token:        Option<String>,
+    /// The longest a paste in the namespace can be, in bytes. The instance-wide limit still applies
+    pub max_content_length: Option<usize>,
+    /// How many pastes the namespace can hold
+    pub max_pastes:         Option<i64>,
+    /// How many bytes of content the namespace's pastes can hold together
+    pub max_bytes:          Option<i64>,
+}
+
+/// Settings for signing in through an OpenID Connect provider, which is only required if this section is present
+#[derive(Deserialize, Debug, Clone)]
+pub struct SsoConfig {
+    /// The provider's issuer URL, its endpoints are discovered from `<issuer>/.well-known/openid-configuration`
+    pub issuer:        String,
+    pub client_id:     String,
+    /// Only needed for confidential clients, public clients are secured by PKCE alone
+    pub client_secret: Option<String>,
+    /// What requires signing in: `create` or `view`
+    #[serde(default)]
+    pub require:       SsoRequirement,
+    #[serde(default = "default_sso_scopes")]
+    pub scopes:        String,
+    /// The ID token claim listing the user's groups
+    #[serde(default = "default_sso_groups_claim")]
+    pub groups_claim:  String,
+    /// Members of these groups can use the admin endpoints
+    #[serde(default)]
+    pub admin_groups:  Vec<String>,
+    /// Secret sessions are signed with. A random one is used if unset, which signs everyone out on restart
+    pub session_key:   Option<String>,
+}
+
+/// Settings for running as an internal tool, which requires authentication for every request if this section is present
+/// Sessions from `[sso]` and the admin token are accepted as well
+#[derive(Deserialize, Debug, Clone)]
+pub struct PrivateConfig {
+    /// Token shared by everyone allowed in, sent as `Authorization: Bearer <token>`
+    pub token: Option<String>,
+    /// Users accepted through HTTP basic authentication
+    #[serde(default)]
+    pub users: Vec<PrivateUser>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct PrivateUser {
+    pub username: String,
+    pub password: String,
+}
+
+/// Settings for serving over TLS with automatically provisioned certificates
+#[derive(Deserialize, Debug, Clone)]
+pub struct AcmeConfig {
+    /// Domains to request a certificate for
+    pub domains:   Vec<String>,
+    /// Email addresses the certificate authority may contact about expiring certificates
+    #[serde(default)]
+    pub contact:   Vec<String>,
+    /// Directory certificates and the account key are stored in
+    #[serde(default = "default_acme_cache_dir")]
+    pub cache_dir: String,
+    /// Uses Let's Encrypt's staging environment, for testing without hitting production rate limits
+    #[serde(default)]
+    pub staging:   bool,
+    /// Address the TLS listener is bound to, in addition to the plain `listen` addresses
+    #[serde(default = "default_acme_listen")]
+    pub listen:    String,
+}
+
+/// Settings for the termbin-style listener, which is only started if this section is present
+#[derive(Deserialize, Debug, Clone)]
+pub struct TermbinConfig {
+    /// Address plain TCP clients like `nc` connect to
+    #[serde(default = "default_termbin_listen")]
+    pub listen: String,
+}
+
+/// Settings for the Gemini server, which is only started if this section is present
+#[derive(Deserialize, Debug, Clone)]
+pub struct GeminiConfig {
+    #[serde(default = "default_gemini_listen")]
+    pub listen: String,
+    /// PEM file with the certificate chain, which may be self-signed
+    pub cert:   String,
+    /// PEM file with the certificate's private key
+    pub key:    String,
+}
+
+/// Settings for limiting API requests per client, which are only limited if this section is present
+#[derive(Deserialize, Debug, Clone)]
+pub struct RateLimitConfig {
+    /// How many requests a client may make per window
+    pub requests: u32,
+    /// Length of a window in seconds
+    #[serde(default = "default_rate_limit_window")]
+    pub window:   i64,
+}
+
+/// Settings for checking the links in rendered pastes, which are only checked if this section is present
+#[derive(Deserialize, Debug, Clone)]
+pub struct LinkSafetyConfig {
+    /// Hosts whose links are unsafe, subdomains included
+    #[serde(default)]
+    pub blocklist:         Vec<String>,
+    /// API key for Google Safe Browsing, links are only checked against the blocklist without one
+    pub safe_browsing_key: Option<String>,
+    /// What happens to unsafe links: `flag` marks them with a warning, `remove` drops their targets
+    #[serde(default)]
+    pub action:            LinkAction,
+}
+
+/// Settings for serving images in rendered pastes through `/proxy`, which are only proxied if this section is present
+#[derive(Deserialize, Debug, Clone)]
+pub struct ImageProxyConfig {
+    /// Secret that proxied URLs are signed with. Without one, a random key is used, and proxied URLs change on every restart
+    pub key:      Option<String>,
+    /// The largest image that is proxied, in bytes
+    #[serde(default = "default_image_proxy_max_size")]
+    pub max_size: usize,
+}
+
+/// Settings for publishing pastes to IPFS, which is only offered if this section is present
+#[derive(Deserialize, Debug, Clone)]
+pub struct IpfsConfig {
+    /// Base URL of the IPFS node's HTTP API, like `http://127.0.0.1:5001`
+    pub api:     String,
+    /// Gateway that published pastes are linked to, like `https://ipfs.io`
+    pub gateway: Option<String>,
+}
+
+/// Settings for pushing every change to another instance, which is only done if this section is present
+#[derive(Deserialize, Debug, Clone)]
+pub struct MirrorConfig {
+    /// Base URL of the downstream instance, like `https://standby.example.com`
+    pub url:    String,
+    /// Signs the pushed changes, the downstream instance has the same secret as its `mirror_secret`
+    pub secret: String,
+}
+
+/// A chat service notified about new pastes
+#[derive(Deserialize, Debug, Clone)]
+pub struct WebhookConfig {
+    /// The incoming webhook URL provided by the chat service
+    pub url:  String,
+    /// Which payload the service expects: `discord`, `slack` or `matrix`
+    #[serde(default)]
+    pub kind: WebhookKind,
+}
+
+fn default_sso_scopes() -> String {
+    "openid profile email".to_string()
+}
+
+fn default_sso_groups_claim() -> String {
+    "groups".to_string()
+}
+
+fn default_acme_cache_dir() -> String {
+    "./acme".to_string()
+}
+
+fn default_acme_listen() -> String {
+    "[::]:443".to_string()
+}
+
+fn default_termbin_listen() -> String {
+    "[::]:9999".to_string()
+}
+
+fn default_gemini_listen() -> String {
+    "[::]:1965".to_string()
+}
+
+fn default_rate_limit_window() -> i64 {
+    60
+}
+
+fn default_image_proxy_max_size() -> usize {
+    5 * 1024 * 1024
+}
+
+impl Config {
+    /// Whether the WebDAV routes are served. They are on by default, except on private and read-only instances, whose credentials take up the `Authorization` header paste passwords are sent in
+    pub fn serves_webdav(&self) -> bool {
+        self.webdav
+            .unwrap_or(self.private.is_none() && !self.read_only)
+    }
+
+    /// The URL absolute links are built from, without a trailing slash
+    /// Without a configured `base_url`, it is guessed from the request's `Host` header, or the first listen address
+    pub fn base_url(&self, host: Option<&str>) -> String {
+        let tcp_address = self.listen.iter().find(|entry| !entry.starts_with("unix:"));
+        match (&self.base_url, host, tcp_address) {
+            (Some(base_url), ..) => base_url.trim_end_matches('/').to_string(),
+            (None, Some(host), _) => format!("http://{host}"),
+            (None, None, Some(address)) => format!("http://{address}"),
+            (None, None, None) => "http://localhost".to_string(),
+        }
+    }
+
+    /// Reads the configuration file, falling back to the defaults if it does not exist
+    /// **Panics** if the file exists but cannot be read or parsed, as running with a half-applied configuration is worse than not starting
+    pub fn load() -> Self {
+        match Self::try_load() {
+            Ok(config) => config,
+            Err(e) => panic!("{e}"),
+        }
+    }
+
+    /// Reads the configuration file like `load`, but returns why it can't be read or parsed instead of panicking
+    pub fn try_load() -> Result<Self, String> {
+        let path = Self::path();
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => {
+                return Err(format!(
+                    "Failed to read the configuration at {path}:\n    {e}"
+                ))
+            }
+        };
+        match toml::from_str(&contents) {
+            Ok(config) => Ok(config),
+            Err(e) => Err(format!(
+                "Failed to parse the configuration at {path}:\n    {e}"
+            )),
+        }
+    }
+
+    /// The path the configuration is read from
+    pub fn path() -> String {
+        env::var("PASTIES_CONFIG").unwrap_or(DEFAULT_CONFIG_PATH.to_string())
+    }
+}
@@ -0,0 +1,57 @@
+//! `csp` sends a Content-Security-Policy with every page, so scripts and styles that pastes bring along as raw HTML aren't applied
+//! Pastes are rendered inside containers with `x-ignore` and `hx-disable`, so Alpine and htmx don't act on attributes in their HTML either
+//! Each response gets a random nonce, which templates put on their own inline scripts and styles through `csp::nonce()`
+
+use axum::{
+    extract::Request,
+    http::{header, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use rand::RngCore;
+
+tokio::task_local! {
+    static NONCE: String;
+}
+
+/// Returns the nonce of the page currently being rendered, empty if called from outside of a request
+pub fn nonce() -> String {
+    NONCE.try_with(|nonce| nonce.clone()).unwrap_or_default()
+}
+
+/// The policy for a page whose own inline scripts and styles carry the nonce
+/// Scripts are only allowed by their nonce, `'strict-dynamic'` makes browsers ignore host sources, so a paste can't load a script from the CDNs the page itself uses
+/// Alpine evaluates its attributes with `Function`, which needs `'unsafe-eval'`, templates put `x-ignore` on the paste's container so its attributes aren't evaluated. Images in pastes may come from anywhere, unless they are proxied
+fn policy(nonce: &str) -> String {
+    format!(
+        "default-src 'self'; \
+        script-src 'nonce-{nonce}' 'strict-dynamic' 'unsafe-eval'; \
+        style-src 'self' 'nonce-{nonce}' https://rsms.me https://fonts.googleapis.com; \
+        font-src 'self' https://rsms.me https://fonts.gstatic.com; \
+        img-src * data:; \
+        object-src 'none'; \
+        base-uri 'self'"
+    )
+}
+
+/// Middleware that makes a fresh nonce available to the templates rendered for the request, and sends the policy allowing it with HTML responses
+pub async fn apply_policy(req: Request, next: Next) -> Response {
+    let mut bytes = [0; 16];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let nonce = STANDARD.encode(bytes);
+    let mut response = NONCE.scope(nonce.clone(), next.run(req)).await;
+    let is_html = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/html"));
+    // Base64 only contains characters that are valid in headers
+    if is_html {
+        response.headers_mut().insert(
+            header::CONTENT_SECURITY_POLICY,
+            HeaderValue::from_str(&policy(&nonce)).unwrap(),
+        );
+    }
+    response
+}
@@ -1,8 +1,19 @@
 //! `database` a helper module for handling SQL queries via a connection pool to an SQLite database
 
-use sqlx::{Row, SqlitePool};
+use serde::Serialize;
+use sqlx::{
+    sqlite::{SqliteConnectOptions, SqlitePoolOptions, SqliteRow},
+    Connection, Row, SqliteConnection, SqlitePool,
+};
+use uuid::Uuid;
 
-use crate::model::{DatabasePaste, PartialDatabasePaste};
+use crate::{
+    model::{Autosave, ContentStats, DatabasePaste, PartialDatabasePaste, Referrer},
+    render, slow_queries,
+    style::PasteStyle,
+    utility,
+    validation::PasteContent,
+};
 
 #[derive(Debug)]
 pub enum DatabaseError {
@@ -11,14 +22,24 @@ pub enum DatabaseError {
     Update(sqlx::Error),
     Delete(sqlx::Error),
     BadRequest(sqlx::Error),
+    /// Another statement in the same transaction failed, so this one was rolled back
+    Transaction,
+    Backup(sqlx::Error),
 }
 
+/// The database file, relative to the working directory
+pub const DATABASE_FILE: &str = "main.db";
+
+/// The version of the schema `init_database` migrates to, stored as the database's `user_version`
+/// Raised whenever a table or column is added, so that a database written by a newer version of pasties can be told apart
+pub const SCHEMA_VERSION: i64 = 6;
+
 /// Connects to the database at `<project root>/main.db` and returns an `SqlitePool` for other database helper functions to use
 /// Also handles creating the schema for paste storage, if the table does not already exist
 /// **Panics** if anything goes wrong, as the lack of an `SqlitePool` is a non-recoverable error for pasties
 pub async fn init_database() -> SqlitePool {
     // Connect to the SQLite
-    let pool = match SqlitePool::connect("sqlite://main.db").await {
+    let pool = match SqlitePool::connect(&format!("sqlite://{DATABASE_FILE}")).await {
         Err(e) => panic!("Failed to connect to the database with the following error:\n    {e}"),
         Ok(pool) => pool,
     };
@@ -36,35 +57,359 @@ pub async fn init_database() -> SqlitePool {
     )
     .execute(&pool)
     .await;
-    match res {
-        Err(e) => panic!(
+    if let Err(e) = res {
+        panic!(
             "Failed to connect to the pastes table in the database with the following error:\n    {e}"
-        ),
-        Ok(_) => pool,
+        )
+    }
+    // Keys of create requests, so that retried requests don't create duplicates
+    let res = sqlx::query(
+        "create table if not exists idempotency_keys (
+            key         text primary key,
+            url         text,
+            fingerprint text,
+            created     integer
+         )",
+    )
+    .execute(&pool)
+    .await;
+    if let Err(e) = res {
+        panic!("Failed to create the idempotency_keys table with the following error:\n    {e}")
+    }
+    // Editor contents saved while a paste is being written, keyed by a token only the browser knows
+    let res = sqlx::query(
+        "create table if not exists autosaves (
+            token   text primary key,
+            content text,
+            format  text,
+            updated integer
+         )",
+    )
+    .execute(&pool)
+    .await;
+    if let Err(e) = res {
+        panic!("Failed to create the autosaves table with the following error:\n    {e}")
+    }
+    // Webhook messages waiting to be delivered, so they survive failed attempts and restarts
+    let res = sqlx::query(
+        "create table if not exists webhook_outbox (
+            id           integer primary key,
+            target       text,
+            body         text,
+            attempts     integer not null default 0,
+            next_attempt integer
+         )",
+    )
+    .execute(&pool)
+    .await;
+    if let Err(e) = res {
+        panic!("Failed to create the webhook_outbox table with the following error:\n    {e}")
+    }
+    // Deferred work, see the `jobs` module
+    let res = sqlx::query(
+        "create table if not exists jobs (
+            id         integer primary key,
+            payload    text,
+            status     text not null default 'pending',
+            attempts   integer not null default 0,
+            run_at     integer,
+            last_error text,
+            created    integer
+         )",
+    )
+    .execute(&pool)
+    .await;
+    if let Err(e) = res {
+        panic!("Failed to create the jobs table with the following error:\n    {e}")
+    }
+    // The site-wide announcement, see the `banner` module. There is at most one, with the ID 1
+    let res = sqlx::query(
+        "create table if not exists banner (
+            id       integer primary key check (id = 1),
+            message  text not null,
+            severity text not null,
+            expires  integer
+         )",
+    )
+    .execute(&pool)
+    .await;
+    if let Err(e) = res {
+        panic!("Failed to create the banner table with the following error:\n    {e}")
+    }
+    // Named, ordered groups of pastes
+    let res = sqlx::query(
+        "create table if not exists collections (
+            name         text primary key,
+            password     text,
+            date_created integer
+         )",
+    )
+    .execute(&pool)
+    .await;
+    if let Err(e) = res {
+        panic!("Failed to create the collections table with the following error:\n    {e}")
+    }
+    let res = sqlx::query(
+        "create table if not exists collection_pastes (
+            collection text,
+            url        text,
+            position   integer,
+            primary key (collection, url)
+         )",
+    )
+    .execute(&pool)
+    .await;
+    if let Err(e) = res {
+        panic!("Failed to create the collection_pastes table with the following error:\n    {e}")
+    }
+    // URLs claimed before their pastes are written, see `model::Manager::reserve_url`
+    let res = sqlx::query(
+        "create table if not exists reservations (
+            url      text primary key,
+            password text,
+            expires  integer
+         )",
+    )
+    .execute(&pool)
+    .await;
+    if let Err(e) = res {
+        panic!("Failed to create the reservations table with the following error:\n    {e}")
+    }
+    // The sites paste pages were opened from, see `referrers::domain`
+    let res = sqlx::query(
+        "create table if not exists referrers (
+            url    text,
+            domain text,
+            views  integer not null default 0,
+            primary key (url, domain)
+         )",
+    )
+    .execute(&pool)
+    .await;
+    if let Err(e) = res {
+        panic!("Failed to create the referrers table with the following error:\n    {e}")
+    }
+    // Columns added after the table was first introduced
+    add_column(
+        &pool,
+        "pastes",
+        "format",
+        "text not null default 'markdown'",
+    )
+    .await;
+    add_column(&pool, "pastes", "pinned", "integer not null default 0").await;
+    add_column(&pool, "pastes", "draft", "integer not null default 0").await;
+    add_column(&pool, "pastes", "views_remaining", "integer").await;
+    add_column(&pool, "pastes", "uuid", "text").await;
+    add_column(&pool, "pastes", "font", "text not null default 'sans'").await;
+    add_column(&pool, "pastes", "width", "text not null default 'normal'").await;
+    add_column(&pool, "pastes", "wrap", "integer not null default 0").await;
+    add_column(&pool, "pastes", "quarantined", "integer not null default 0").await;
+    add_column(&pool, "pastes", "ipfs_cid", "text").await;
+    add_column(&pool, "pastes", "date_locked", "integer").await;
+    add_column(&pool, "pastes", "owner", "text").await;
+    add_column(&pool, "pastes", "rendered_html", "text").await;
+    add_column(&pool, "pastes", "rendered_by", "text").await;
+    for column in ["word_count", "line_count", "reading_time"] {
+        add_column(&pool, "pastes", column, "integer not null default 0").await;
+    }
+    // Pastes are looked up by ID through `/p/<id>`
+    let res = sqlx::query("create index if not exists pastes_id on pastes (id)")
+        .execute(&pool)
+        .await;
+    if let Err(e) = res {
+        panic!("Failed to create the pastes_id index with the following error:\n    {e}")
+    }
+    backfill_uuids(&pool).await;
+    let res = sqlx::query("create unique index if not exists pastes_uuid on pastes (uuid)")
+        .execute(&pool)
+        .await;
+    if let Err(e) = res {
+        panic!("Failed to create the pastes_uuid index with the following error:\n    {e}")
+    }
+    // Signed-in users list the pastes they claimed
+    let res = sqlx::query("create index if not exists pastes_owner on pastes (owner)")
+        .execute(&pool)
+        .await;
+    if let Err(e) = res {
+        panic!("Failed to create the pastes_owner index with the following error:\n    {e}")
+    }
+    backfill_content_stats(&pool).await;
+    let res = sqlx::query(&format!("pragma user_version = {SCHEMA_VERSION}"))
+        .execute(&pool)
+        .await;
+    if let Err(e) = res {
+        panic!("Failed to record the schema version with the following error:\n    {e}")
+    }
+    pool
+}
+
+/// Opens `connections` read-only connections to the database, for reads that don't have to wait for writes
+/// The database is switched to write-ahead logging first, which lets readers and the writer work at the same time, and is kept by the file from then on
+/// **Panics** if the journal mode can't be changed or the connections can't be opened
+///
+/// **Arguments**
+/// * `pool`: the `&SqlitePool` writes go through
+/// * `connections`: how many read-only connections the returned pool holds at most
+pub async fn init_read_pool(pool: &SqlitePool, connections: u32) -> SqlitePool {
+    if let Err(e) = sqlx::query("pragma journal_mode = wal").execute(pool).await {
+        panic!("Failed to enable write-ahead logging with the following error:\n    {e}")
+    }
+    let options = SqliteConnectOptions::new()
+        .filename(DATABASE_FILE)
+        .read_only(true);
+    match SqlitePoolOptions::new()
+        .max_connections(connections)
+        .connect_with(options)
+        .await
+    {
+        Ok(pool) => pool,
+        Err(e) => panic!("Failed to open the read connections with the following error:\n    {e}"),
+    }
+}
+
+/// Reads the schema version of the database without changing it, which is 0 for databases last opened before versions were recorded
+pub async fn schema_version() -> Result<i64, DatabaseError> {
+    let options = SqliteConnectOptions::new()
+        .filename(DATABASE_FILE)
+        .read_only(true);
+    let mut connection = match SqliteConnection::connect_with(&options).await {
+        Ok(connection) => connection,
+        Err(e) => return Err(DatabaseError::Retrieval(e)),
+    };
+    let version = match sqlx::query("pragma user_version")
+        .fetch_one(&mut connection)
+        .await
+    {
+        Ok(row) => Ok(row.get(0)),
+        Err(e) => Err(DatabaseError::Retrieval(e)),
+    };
+    let _ = connection.close().await;
+    version
+}
+
+/// Gives pastes created before they had UUIDs one
+/// **Panics** if the pastes cannot be read or updated
+async fn backfill_uuids(pool: &SqlitePool) {
+    let query = "select primary_key from pastes where uuid is null";
+    let rows = match sqlx::query(query).fetch_all(pool).await {
+        Ok(rows) => rows,
+        Err(e) => panic!("Failed to read pastes without UUIDs:\n    {e}"),
+    };
+    for row in rows {
+        let primary_key: i64 = row.get("primary_key");
+        if let Err(e) = sqlx::query("update pastes set uuid = ? where primary_key = ?")
+            .bind(Uuid::new_v4().to_string())
+            .bind(primary_key)
+            .execute(pool)
+            .await
+        {
+            panic!("Failed to give a paste a UUID:\n    {e}")
+        }
+    }
+}
+
+/// Computes the content stats of pastes created before they were stored
+/// **Panics** if the pastes cannot be read or updated
+async fn backfill_content_stats(pool: &SqlitePool) {
+    let query = "select url, content from pastes where line_count = 0 and content != ''";
+    let rows = match sqlx::query(query).fetch_all(pool).await {
+        Ok(rows) => rows,
+        Err(e) => panic!("Failed to read pastes without content stats:\n    {e}"),
+    };
+    for row in rows {
+        let url: String = row.get("url");
+        let stats = ContentStats::of(row.get("content"));
+        let query =
+            "update pastes set word_count = ?, line_count = ?, reading_time = ? where url = ?";
+        if let Err(e) = sqlx::query(query)
+            .bind(stats.word_count)
+            .bind(stats.line_count)
+            .bind(stats.reading_time)
+            .bind(&url)
+            .execute(pool)
+            .await
+        {
+            panic!("Failed to store the content stats of {url}:\n    {e}")
+        }
+    }
+}
+
+/// Adds a column to an existing table, unless it is already there. Used to migrate databases created by older versions of pasties
+/// **Panics** if the schema cannot be inspected or altered
+async fn add_column(pool: &SqlitePool, table: &str, column: &str, definition: &str) {
+    let columns = match sqlx::query(&format!("pragma table_info({table})"))
+        .fetch_all(pool)
+        .await
+    {
+        Ok(rows) => rows,
+        Err(e) => panic!("Failed to inspect the {table} table:\n    {e}"),
+    };
+    if columns
+        .iter()
+        .any(|row| row.get::<String, _>("name") == column)
+    {
+        return;
+    }
+    if let Err(e) = sqlx::query(&format!(
+        "alter table {table} add column {column} {definition}"
+    ))
+    .execute(pool)
+    .await
+    {
+        panic!("Failed to add the {column} column to the {table} table:\n    {e}")
     }
 }
 
+const INSERT_QUERY: &str = "insert into pastes(
+    id,
+    url,
+    password,
+    content,
+    format,
+    date_published,
+    date_edited,
+    word_count,
+    line_count,
+    reading_time,
+    draft,
+    views_remaining,
+    uuid,
+    font,
+    width,
+    wrap,
+    quarantined,
+    rendered_html,
+    rendered_by
+) values (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)";
+
 /// Creates a new paste record in a database using the specified pool.
 ///
 /// **Arguments**
 /// * `pool`: an `&SqlitePool` reference
 /// * `paste`: a `DatabasePaste` struct to create a record of
 pub async fn insert_paste(pool: &SqlitePool, paste: DatabasePaste) -> Result<(), DatabaseError> {
-    let query = "insert into pastes(
-        id,
-        url,  
-        password,  
-        content,  
-        date_published,  
-        date_edited
-    ) values (?, ?, ?, ?, ?, ?)";
-    match sqlx::query(query)
+    let _timer = slow_queries::Timer::start("insert_paste");
+    match sqlx::query(INSERT_QUERY)
         .bind(paste.id)
         .bind(paste.url)
         .bind(paste.password_hash)
         .bind(paste.content)
+        .bind(paste.format)
         .bind(paste.date_published)
         .bind(paste.date_edited)
+        .bind(paste.stats.word_count)
+        .bind(paste.stats.line_count)
+        .bind(paste.stats.reading_time)
+        .bind(paste.draft)
+        .bind(paste.views_remaining)
+        .bind(paste.uuid)
+        .bind(paste.style.font.as_str())
+        .bind(paste.style.width.as_str())
+        .bind(paste.style.wrap)
+        .bind(paste.quarantined)
+        .bind(paste.rendered_html)
+        .bind(render::VERSION)
         .execute(pool)
         .await
     {
@@ -73,6 +418,126 @@ pub async fn insert_paste(pool: &SqlitePool, paste: DatabasePaste) -> Result<(),
     }
 }
 
+/// Replaces the paste with the same URL or UUID with a copy of another instance's paste, in a single transaction
+/// The copy keeps its ID, UUID and lock, so links to it and its hash keep working
+/// Returns false without changing anything if the stored paste was edited after the copy, which is then outdated
+///
+/// **Arguments**
+/// * `pool`: an `&SqlitePool` reference
+/// * `paste`: the `DatabasePaste` struct to create a record of
+pub async fn replace_paste(pool: &SqlitePool, paste: DatabasePaste) -> Result<bool, DatabaseError> {
+    let _timer = slow_queries::Timer::start("replace_paste");
+    let mut transaction = match pool.begin().await {
+        Ok(transaction) => transaction,
+        Err(e) => return Err(DatabaseError::Insert(e)),
+    };
+    let newer = sqlx::query("select 1 from pastes where (url = ? or uuid = ?) and date_edited > ?")
+        .bind(&paste.url)
+        .bind(&paste.uuid)
+        .bind(paste.date_edited)
+        .fetch_optional(&mut *transaction)
+        .await;
+    match newer {
+        Ok(Some(_)) => return Ok(false),
+        Ok(None) => {}
+        Err(e) => return Err(DatabaseError::Retrieval(e)),
+    }
+    let url = paste.url.clone();
+    let date_locked = paste.date_locked;
+    let result = sqlx::query("delete from pastes where url = ? or uuid = ?")
+        .bind(&paste.url)
+        .bind(&paste.uuid)
+        .execute(&mut *transaction)
+        .await;
+    if let Err(e) = result {
+        return Err(DatabaseError::Delete(e));
+    }
+    let result = sqlx::query(INSERT_QUERY)
+        .bind(paste.id)
+        .bind(paste.url)
+        .bind(paste.password_hash)
+        .bind(paste.content)
+        .bind(paste.format)
+        .bind(paste.date_published)
+        .bind(paste.date_edited)
+        .bind(paste.stats.word_count)
+        .bind(paste.stats.line_count)
+        .bind(paste.stats.reading_time)
+        .bind(paste.draft)
+        .bind(paste.views_remaining)
+        .bind(paste.uuid)
+        .bind(paste.style.font.as_str())
+        .bind(paste.style.width.as_str())
+        .bind(paste.style.wrap)
+        .bind(paste.quarantined)
+        .bind(paste.rendered_html)
+        .bind(render::VERSION)
+        .execute(&mut *transaction)
+        .await;
+    if let Err(e) = result {
+        return Err(DatabaseError::Insert(e));
+    }
+    let result = sqlx::query("update pastes set date_locked = ? where url = ?")
+        .bind(date_locked)
+        .bind(&url)
+        .execute(&mut *transaction)
+        .await;
+    if let Err(e) = result {
+        return Err(DatabaseError::Update(e));
+    }
+    match transaction.commit().await {
+        Ok(_) => Ok(true),
+        Err(e) => Err(DatabaseError::Insert(e)),
+    }
+}
+
+/// Creates several paste records in a single transaction, so that either all of them are created or none are
+///
+/// **Arguments**
+/// * `pool`: an `&SqlitePool` reference
+/// * `pastes`: the `DatabasePaste` structs to create records of
+pub async fn insert_pastes(
+    pool: &SqlitePool,
+    pastes: Vec<DatabasePaste>,
+) -> Result<(), DatabaseError> {
+    let _timer = slow_queries::Timer::start("insert_pastes");
+    let mut transaction = match pool.begin().await {
+        Ok(transaction) => transaction,
+        Err(e) => return Err(DatabaseError::Insert(e)),
+    };
+    for paste in pastes {
+        let result = sqlx::query(INSERT_QUERY)
+            .bind(paste.id)
+            .bind(paste.url)
+            .bind(paste.password_hash)
+            .bind(paste.content)
+            .bind(paste.format)
+            .bind(paste.date_published)
+            .bind(paste.date_edited)
+            .bind(paste.stats.word_count)
+            .bind(paste.stats.line_count)
+            .bind(paste.stats.reading_time)
+            .bind(paste.draft)
+            .bind(paste.views_remaining)
+            .bind(paste.uuid)
+            .bind(paste.style.font.as_str())
+            .bind(paste.style.width.as_str())
+            .bind(paste.style.wrap)
+            .bind(paste.quarantined)
+            .bind(paste.rendered_html)
+            .bind(render::VERSION)
+            .execute(&mut *transaction)
+            .await;
+        if let Err(e) = result {
+            return Err(DatabaseError::Insert(e));
+        }
+    }
+    match transaction.commit().await {
+        Ok(_) => Ok(()),
+        Err(e) => Err(DatabaseError::Insert(e)),
+    }
+}
+
 /// Updates a paste in a database using the specified pool.
 ///
 /// **Arguments**
@@ -83,20 +548,47 @@ pub async fn update_paste(
     url: String,
     paste: PartialDatabasePaste,
 ) -> Result<(), DatabaseError> {
-    let query =
-        "update pastes set url = ?, password = ?, content = ?, date_edited = ? where url = ?";
-    match sqlx::query(query)
+    let _timer = slow_queries::Timer::start("update_paste");
+    let query = "update pastes set url = ?, password = ?, content = ?, format = ?, date_edited = ?,
+        word_count = ?, line_count = ?, reading_time = ?, font = ?, width = ?, wrap = ?,
+        quarantined = ?, rendered_html = ?, rendered_by = ? where url = ?";
+    let new_url = paste.url.clone();
+    if let Err(e) = sqlx::query(query)
         .bind(paste.url)
         .bind(paste.password_hash)
-        .bind(paste.content)
+        .bind(paste.content.into_string())
+        .bind(paste.format)
         .bind(paste.date_edited)
-        .bind(url)
+        .bind(paste.stats.word_count)
+        .bind(paste.stats.line_count)
+        .bind(paste.stats.reading_time)
+        .bind(paste.style.font.as_str())
+        .bind(paste.style.width.as_str())
+        .bind(paste.style.wrap)
+        .bind(paste.quarantined)
+        .bind(paste.rendered_html)
+        .bind(render::VERSION)
+        .bind(&url)
         .execute(pool)
         .await
     {
-        Ok(_) => Ok(()),
-        Err(e) => Err(DatabaseError::Update(e)),
+        return Err(DatabaseError::Update(e));
+    }
+    // Renamed pastes stay in their collections, and keep their referrers
+    for query in [
+        "update collection_pastes set url = ? where url = ?",
+        "update referrers set url = ? where url = ?",
+    ] {
+        if let Err(e) = sqlx::query(query)
+            .bind(&new_url)
+            .bind(&url)
+            .execute(pool)
+            .await
+        {
+            return Err(DatabaseError::Update(e));
+        }
     }
+    Ok(())
 }
 
 /// Deletes a paste from a database using the specified pool. The identification of the paste happens through its URL, which is guaranteed to be unique by the `model` module
@@ -105,11 +597,20 @@ pub async fn update_paste(
 /// * `pool`: an `&SqlitePool` reference
 /// * `url`: a paste's custom URL that uniquely identifies it
 pub async fn delete_paste(pool: &SqlitePool, url: &String) -> Result<(), DatabaseError> {
+    let _timer = slow_queries::Timer::start("delete_paste");
     let query = "delete from pastes where url=?";
-    match sqlx::query(query).bind(url).execute(pool).await {
-        Ok(_) => Ok(()),
-        Err(e) => Err(DatabaseError::Delete(e)),
+    if let Err(e) = sqlx::query(query).bind(url).execute(pool).await {
+        return Err(DatabaseError::Delete(e));
     }
+    for query in [
+        "delete from collection_pastes where url=?",
+        "delete from referrers where url=?",
+    ] {
+        if let Err(e) = sqlx::query(query).bind(url).execute(pool).await {
+            return Err(DatabaseError::Delete(e));
+        }
+    }
+    Ok(())
 }
 
 /// Fetches a paste from a database using the specified pool. The identification of the paste happens through its URL, which is guaranteed to be unique by the `model` module
@@ -121,16 +622,1278 @@ pub async fn retrieve_paste(
     pool: &SqlitePool,
     url: &String,
 ) -> Result<DatabasePaste, DatabaseError> {
+    let _timer = slow_queries::Timer::start("retrieve_paste");
     let query = "select * from pastes where url=?1";
     match sqlx::query(query).bind(url).fetch_one(pool).await {
-        Ok(row) => Ok(DatabasePaste {
-            id:             row.get("id"),
-            url:            row.get("url"),
-            password_hash:  row.get("password"),
-            content:        row.get("content"),
-            date_published: row.get("date_published"),
-            date_edited:    row.get("date_edited"),
-        }),
+        Ok(row) => Ok(paste_from_row(&row)),
+        Err(e) => Err(DatabaseError::Retrieval(e)),
+    }
+}
+
+/// Looks up the current URL of the paste with the given ID, which stays the same when the paste is renamed
+///
+/// **Arguments**
+/// * `pool`: an `&SqlitePool` reference
+/// * `id`: a paste's internal ID
+pub async fn retrieve_url_by_id(pool: &SqlitePool, id: i64) -> Result<String, DatabaseError> {
+    let _timer = slow_queries::Timer::start("retrieve_url_by_id");
+    let query = "select url from pastes where id=?1";
+    match sqlx::query(query).bind(id).fetch_one(pool).await {
+        Ok(row) => Ok(row.get("url")),
+        Err(e) => Err(DatabaseError::Retrieval(e)),
+    }
+}
+
+/// Looks up the current URL of the paste with the given public UUID
+///
+/// **Arguments**
+/// * `pool`: an `&SqlitePool` reference
+/// * `uuid`: a paste's public UUID, hyphenated and lowercase
+pub async fn retrieve_url_by_uuid(pool: &SqlitePool, uuid: &str) -> Result<String, DatabaseError> {
+    let _timer = slow_queries::Timer::start("retrieve_url_by_uuid");
+    let query = "select url from pastes where uuid=?1";
+    match sqlx::query(query).bind(uuid).fetch_one(pool).await {
+        Ok(row) => Ok(row.get("url")),
+        Err(e) => Err(DatabaseError::Retrieval(e)),
+    }
+}
+
+/// Counts the pastes in a namespace and the bytes of their content, drafts included
+///
+/// **Arguments**
+/// * `pool`: an `&SqlitePool` reference
+/// * `namespace`: the namespace's name, or `None` for the pastes outside of namespaces
+pub async fn retrieve_usage(
+    pool: &SqlitePool,
+    namespace: Option<&str>,
+) -> Result<(i64, i64), DatabaseError> {
+    let _timer = slow_queries::Timer::start("retrieve_usage");
+    // Namespace names may contain `_`, which `like` would treat as a wildcard
+    let query = match namespace {
+        Some(_) => {
+            "select count(*) as pastes, coalesce(sum(length(cast(content as blob))), 0) as bytes
+             from pastes where substr(url, 1, length(?1) + 1) = ?1 || '/'"
+        }
+        None => {
+            "select count(*) as pastes, coalesce(sum(length(cast(content as blob))), 0) as bytes
+             from pastes where instr(url, '/') = 0"
+        }
+    };
+    match sqlx::query(query).bind(namespace).fetch_one(pool).await {
+        Ok(row) => Ok((row.get("pastes"), row.get("bytes"))),
+        Err(e) => Err(DatabaseError::Retrieval(e)),
+    }
+}
+
+/// Returns which of the given URLs belong to existing pastes
+///
+/// **Arguments**
+/// * `pool`: an `&SqlitePool` reference
+/// * `urls`: the paste URLs to look up
+pub async fn retrieve_existing_urls(
+    pool: &SqlitePool,
+    urls: &[String],
+) -> Result<Vec<String>, DatabaseError> {
+    let _timer = slow_queries::Timer::start("retrieve_existing_urls");
+    if urls.is_empty() {
+        return Ok(Vec::new());
+    }
+    let placeholders = vec!["?"; urls.len()].join(", ");
+    let query = format!(
+        "select url from pastes where draft = 0 and quarantined = 0 and url in ({placeholders})"
+    );
+    let mut query = sqlx::query(&query);
+    for url in urls {
+        query = query.bind(url);
+    }
+    match query.fetch_all(pool).await {
+        Ok(rows) => Ok(rows.iter().map(|row| row.get("url")).collect()),
+        Err(e) => Err(DatabaseError::Retrieval(e)),
+    }
+}
+
+/// Retrieves every pinned paste, the most recently published first
+/// Pastes with a view limit are left out, as showing them wouldn't count as a view
+///
+/// **Arguments**
+/// * `pool`: an `&SqlitePool` reference
+pub async fn retrieve_pinned_pastes(
+    pool: &SqlitePool,
+) -> Result<Vec<DatabasePaste>, DatabaseError> {
+    let _timer = slow_queries::Timer::start("retrieve_pinned_pastes");
+    let query = "select * from pastes where pinned = 1 and draft = 0 and quarantined = 0 and views_remaining is null
+        order by date_published desc";
+    match sqlx::query(query).fetch_all(pool).await {
+        Ok(rows) => Ok(rows.iter().map(paste_from_row).collect()),
+        Err(e) => Err(DatabaseError::Retrieval(e)),
+    }
+}
+
+/// Retrieves every paste claimed by a user, the most recently published first
+///
+/// **Arguments**
+/// * `pool`: an `&SqlitePool` reference
+/// * `owner`: the user's subject identifier at the SSO provider
+pub async fn retrieve_owned_pastes(
+    pool: &SqlitePool,
+    owner: &str,
+) -> Result<Vec<DatabasePaste>, DatabaseError> {
+    let _timer = slow_queries::Timer::start("retrieve_owned_pastes");
+    let query = "select * from pastes where owner = ? order by date_published desc";
+    match sqlx::query(query).bind(owner).fetch_all(pool).await {
+        Ok(rows) => Ok(rows.iter().map(paste_from_row).collect()),
+        Err(e) => Err(DatabaseError::Retrieval(e)),
+    }
+}
+
+/// Transfers a paste to a user, who can change it without its password from then on
+///
+/// **Arguments**
+/// * `pool`: an `&SqlitePool` reference
+/// * `url`: a paste's custom URL
+/// * `owner`: the user's subject identifier at the SSO provider
+pub async fn set_owner(pool: &SqlitePool, url: &str, owner: &str) -> Result<(), DatabaseError> {
+    let _timer = slow_queries::Timer::start("set_owner");
+    let query = "update pastes set owner = ? where url = ?";
+    match sqlx::query(query).bind(owner).bind(url).execute(pool).await {
+        Ok(_) => Ok(()),
+        Err(e) => Err(DatabaseError::Update(e)),
+    }
+}
+
+/// Counts a view of a paste referred from a domain. Domains beyond the first `limit` a paste was referred from aren't counted
+///
+/// **Arguments**
+/// * `pool`: an `&SqlitePool` reference
+/// * `url`: a paste's custom URL
+/// * `domain`: the referring domain, as returned by `referrers::domain`
+/// * `limit`: how many domains are kept per paste
+pub async fn count_referrer(
+    pool: &SqlitePool,
+    url: &str,
+    domain: &str,
+    limit: i64,
+) -> Result<(), DatabaseError> {
+    let _timer = slow_queries::Timer::start("count_referrer");
+    let query = "insert into referrers (url, domain, views)
+        select ?1, ?2, 1 where (select count(*) from referrers where url = ?1) < ?3
+        on conflict (url, domain) do update set views = views + 1";
+    match sqlx::query(query)
+        .bind(url)
+        .bind(domain)
+        .bind(limit)
+        .execute(pool)
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(e) => Err(DatabaseError::Insert(e)),
+    }
+}
+
+/// Retrieves the domains a paste was referred from the most, with their view counts
+///
+/// **Arguments**
+/// * `pool`: an `&SqlitePool` reference
+/// * `url`: a paste's custom URL
+/// * `limit`: how many domains to return
+pub async fn retrieve_referrers(
+    pool: &SqlitePool,
+    url: &str,
+    limit: i64,
+) -> Result<Vec<Referrer>, DatabaseError> {
+    let _timer = slow_queries::Timer::start("retrieve_referrers");
+    let query = "select domain, views from referrers where url = ?
+        order by views desc, domain limit ?";
+    match sqlx::query(query)
+        .bind(url)
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+    {
+        Ok(rows) => Ok(rows
+            .iter()
+            .map(|row| Referrer {
+                domain: row.get("domain"),
+                views:  row.get("views"),
+            })
+            .collect()),
         Err(e) => Err(DatabaseError::Retrieval(e)),
     }
 }
+
+/// Pins a paste to the homepage, or unpins it. Returns whether a paste with the URL exists
+///
+/// **Arguments**
+/// * `pool`: an `&SqlitePool` reference
+/// * `url`: a paste's custom URL
+/// * `pinned`: whether the paste should be pinned
+pub async fn set_pinned(pool: &SqlitePool, url: &str, pinned: bool) -> Result<bool, DatabaseError> {
+    let _timer = slow_queries::Timer::start("set_pinned");
+    let query = "update pastes set pinned = ? where url = ?";
+    match sqlx::query(query)
+        .bind(pinned)
+        .bind(url)
+        .execute(pool)
+        .await
+    {
+        Ok(result) => Ok(result.rows_affected() > 0),
+        Err(e) => Err(DatabaseError::Update(e)),
+    }
+}
+
+/// Quarantines a paste, or releases it from quarantine. Returns whether a paste with the URL exists
+///
+/// **Arguments**
+/// * `pool`: an `&SqlitePool` reference
+/// * `url`: a paste's custom URL
+/// * `quarantined`: whether the paste should be quarantined
+pub async fn set_quarantined(
+    pool: &SqlitePool,
+    url: &str,
+    quarantined: bool,
+) -> Result<bool, DatabaseError> {
+    let _timer = slow_queries::Timer::start("set_quarantined");
+    let query = "update pastes set quarantined = ? where url = ?";
+    match sqlx::query(query)
+        .bind(quarantined)
+        .bind(url)
+        .execute(pool)
+        .await
+    {
+        Ok(result) => Ok(result.rows_affected() > 0),
+        Err(e) => Err(DatabaseError::Update(e)),
+    }
+}
+
+/// Appends a chunk to a paste's content if the password matches and the result isn't longer than `max_length` bytes. Returns the paste with its new content, or `None` if nothing was appended
+/// The stored HTML is dropped along with the old content, until the new content is rendered
+///
+/// **Arguments**
+/// * `pool`: an `&SqlitePool` reference
+/// * `url`: a paste's custom URL
+/// * `password_hash`: the hash of the paste's password
+/// * `chunk`: the text to append
+/// * `max_length`: the longest the content may become, in bytes
+/// * `date_edited`: the unix timestamp of the edit
+pub async fn append_to_paste(
+    pool: &SqlitePool,
+    url: &str,
+    password_hash: &str,
+    chunk: &PasteContent,
+    max_length: usize,
+    date_edited: i64,
+) -> Result<Option<DatabasePaste>, DatabaseError> {
+    let _timer = slow_queries::Timer::start("append_to_paste");
+    let query = "update pastes set content = content || ?1, date_edited = ?2,
+            rendered_html = null, rendered_by = null
+        where url = ?3 and password = ?4 and date_locked is null
+            and length(cast(content as blob)) + length(cast(?1 as blob)) <= ?5
+        returning *";
+    match sqlx::query(query)
+        .bind(chunk.as_str())
+        .bind(date_edited)
+        .bind(url)
+        .bind(password_hash)
+        .bind(max_length as i64)
+        .fetch_optional(pool)
+        .await
+    {
+        Ok(row) => Ok(row.as_ref().map(paste_from_row)),
+        Err(e) => Err(DatabaseError::Update(e)),
+    }
+}
+
+/// Stores the content stats of a paste, unless its content has changed length since they were computed
+///
+/// **Arguments**
+/// * `pool`: an `&SqlitePool` reference
+/// * `url`: a paste's custom URL
+/// * `content_length`: the length in bytes of the content the stats were computed from
+/// * `stats`: the stats to store
+pub async fn update_content_stats(
+    pool: &SqlitePool,
+    url: &str,
+    content_length: usize,
+    stats: ContentStats,
+) -> Result<(), DatabaseError> {
+    let _timer = slow_queries::Timer::start("update_content_stats");
+    let query = "update pastes set word_count = ?, line_count = ?, reading_time = ?
+        where url = ? and length(cast(content as blob)) = ?";
+    match sqlx::query(query)
+        .bind(stats.word_count)
+        .bind(stats.line_count)
+        .bind(stats.reading_time)
+        .bind(url)
+        .bind(content_length as i64)
+        .execute(pool)
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(e) => Err(DatabaseError::Update(e)),
+    }
+}
+
+/// Stores a paste's rendered content, unless the content has changed since it was rendered
+///
+/// **Arguments**
+/// * `pool`: an `&SqlitePool` reference
+/// * `url`: a paste's custom URL
+/// * `content`: the content that was rendered
+/// * `rendered_html`: the rendered content, `None` if it is rendered on every view
+pub async fn set_rendered_html(
+    pool: &SqlitePool,
+    url: &str,
+    content: &str,
+    rendered_html: Option<String>,
+) -> Result<(), DatabaseError> {
+    let _timer = slow_queries::Timer::start("set_rendered_html");
+    let query =
+        "update pastes set rendered_html = ?, rendered_by = ? where url = ? and content = ?";
+    match sqlx::query(query)
+        .bind(rendered_html)
+        .bind(render::VERSION)
+        .bind(url)
+        .bind(content)
+        .execute(pool)
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(e) => Err(DatabaseError::Update(e)),
+    }
+}
+
+/// Retrieves pastes whose content wasn't rendered by this build yet, see `render::VERSION`
+///
+/// **Arguments**
+/// * `pool`: an `&SqlitePool` reference
+/// * `limit`: the most pastes to retrieve
+pub async fn retrieve_unrendered_pastes(
+    pool: &SqlitePool,
+    limit: i64,
+) -> Result<Vec<DatabasePaste>, DatabaseError> {
+    let _timer = slow_queries::Timer::start("retrieve_unrendered_pastes");
+    let query = "select * from pastes where rendered_by is not ? limit ?";
+    match sqlx::query(query)
+        .bind(render::VERSION)
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+    {
+        Ok(rows) => Ok(rows.iter().map(paste_from_row).collect()),
+        Err(e) => Err(DatabaseError::Retrieval(e)),
+    }
+}
+
+/// Counts a view of a paste with a view limit. Returns how many views are left, or `None` if there were none left to take
+///
+/// **Arguments**
+/// * `pool`: an `&SqlitePool` reference
+/// * `url`: a paste's custom URL
+pub async fn count_view(pool: &SqlitePool, url: &str) -> Result<Option<i64>, DatabaseError> {
+    let _timer = slow_queries::Timer::start("count_view");
+    let query = "update pastes set views_remaining = views_remaining - 1
+        where url = ? and views_remaining > 0
+        returning views_remaining";
+    match sqlx::query(query).bind(url).fetch_optional(pool).await {
+        Ok(row) => Ok(row.map(|row| row.get("views_remaining"))),
+        Err(e) => Err(DatabaseError::Update(e)),
+    }
+}
+
+/// Makes a draft public, setting its publication date to when it was published
+///
+/// **Arguments**
+/// * `pool`: an `&SqlitePool` reference
+/// * `url`: a paste's custom URL
+/// * `date_published`: the unix timestamp of the publication
+pub async fn publish_paste(
+    pool: &SqlitePool,
+    url: &str,
+    date_published: i64,
+) -> Result<(), DatabaseError> {
+    let _timer = slow_queries::Timer::start("publish_paste");
+    let query = "update pastes set draft = 0, date_published = ? where url = ?";
+    match sqlx::query(query)
+        .bind(date_published)
+        .bind(url)
+        .execute(pool)
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(e) => Err(DatabaseError::Update(e)),
+    }
+}
+
+/// Replaces the password hash of a paste, leaving everything else as it is
+///
+/// **Arguments**
+/// * `pool`: an `&SqlitePool` reference
+/// * `url`: a paste's custom URL
+/// * `password_hash`: the hash of the new password
+pub async fn set_password(
+    pool: &SqlitePool,
+    url: &str,
+    password_hash: &str,
+) -> Result<(), DatabaseError> {
+    let _timer = slow_queries::Timer::start("set_password");
+    let query = "update pastes set password = ? where url = ?";
+    match sqlx::query(query)
+        .bind(password_hash)
+        .bind(url)
+        .execute(pool)
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(e) => Err(DatabaseError::Update(e)),
+    }
+}
+
+/// Locks a paste, so that its content can't change anymore. Pastes that are already locked keep their date
+///
+/// **Arguments**
+/// * `pool`: an `&SqlitePool` reference
+/// * `url`: a paste's custom URL
+/// * `date_locked`: the unix timestamp the paste was locked at
+pub async fn lock_paste(
+    pool: &SqlitePool,
+    url: &str,
+    date_locked: i64,
+) -> Result<(), DatabaseError> {
+    let _timer = slow_queries::Timer::start("lock_paste");
+    let query = "update pastes set date_locked = ? where url = ? and date_locked is null";
+    match sqlx::query(query)
+        .bind(date_locked)
+        .bind(url)
+        .execute(pool)
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(e) => Err(DatabaseError::Update(e)),
+    }
+}
+
+/// Stores the CID a paste was published to IPFS under
+///
+/// **Arguments**
+/// * `pool`: an `&SqlitePool` reference
+/// * `url`: a paste's custom URL
+/// * `cid`: the CID returned by the IPFS node
+pub async fn set_ipfs_cid(pool: &SqlitePool, url: &str, cid: &str) -> Result<(), DatabaseError> {
+    let _timer = slow_queries::Timer::start("set_ipfs_cid");
+    let query = "update pastes set ipfs_cid = ? where url = ?";
+    match sqlx::query(query).bind(cid).bind(url).execute(pool).await {
+        Ok(_) => Ok(()),
+        Err(e) => Err(DatabaseError::Update(e)),
+    }
+}
+
+/// Writes a consistent snapshot of the database to a new file with `vacuum into`, which is safe while other connections write to it
+/// Fails if a non-empty file already exists at the path
+///
+/// **Arguments**
+/// * `pool`: an `&SqlitePool` reference
+/// * `path`: where the snapshot is written
+pub async fn backup(pool: &SqlitePool, path: &str) -> Result<(), DatabaseError> {
+    match sqlx::query("vacuum into ?").bind(path).execute(pool).await {
+        Ok(_) => Ok(()),
+        Err(e) => Err(DatabaseError::Backup(e)),
+    }
+}
+
+fn paste_from_row(row: &SqliteRow) -> DatabasePaste {
+    DatabasePaste {
+        id:              row.get("id"),
+        url:             row.get("url"),
+        password_hash:   row.get("password"),
+        content:         row.get("content"),
+        format:          row.get("format"),
+        date_published:  row.get("date_published"),
+        date_edited:     row.get("date_edited"),
+        stats:           ContentStats {
+            word_count:   row.get("word_count"),
+            line_count:   row.get("line_count"),
+            reading_time: row.get("reading_time"),
+        },
+        draft:           row.get("draft"),
+        views_remaining: row.get("views_remaining"),
+        uuid:            row.get("uuid"),
+        // Unknown options can only come from a newer version, and are shown with the defaults
+        style:           PasteStyle {
+            font:  row.get::<String, _>("font").parse().unwrap_or_default(),
+            width: row.get::<String, _>("width").parse().unwrap_or_default(),
+            wrap:  row.get("wrap"),
+        },
+        quarantined:     row.get("quarantined"),
+        ipfs_cid:        row.get("ipfs_cid"),
+        date_locked:     row.get("date_locked"),
+        owner:           row.get("owner"),
+        // HTML rendered by another build is ignored, its renderers may have worked differently
+        rendered_html:   match row.get::<Option<String>, _>("rendered_by").as_deref() {
+            Some(render::VERSION) => row.get("rendered_html"),
+            _ => None,
+        },
+    }
+}
+
+/// A create request that has already been handled, or is being handled, see `model::Manager::create_paste_idempotently`
+pub struct IdempotencyKey {
+    /// The created paste's URL, which is missing while the request that claimed the key is still creating it
+    pub url:         Option<String>,
+    pub fingerprint: String,
+}
+
+/// Claims an idempotency key for a request, forgetting all keys used before `since`
+/// Returns whether the key was claimed, which it isn't if another request already used it
+///
+/// **Arguments**
+/// * `pool`: an `&SqlitePool` reference
+/// * `key`: the key sent by the client, qualified by its namespace
+/// * `fingerprint`: the fingerprint of the request
+/// * `since`: the unix timestamp keys expire at
+pub async fn claim_idempotency_key(
+    pool: &SqlitePool,
+    key: &str,
+    fingerprint: &str,
+    since: i64,
+) -> Result<bool, DatabaseError> {
+    let _timer = slow_queries::Timer::start("claim_idempotency_key");
+    if let Err(e) = sqlx::query("delete from idempotency_keys where created < ?")
+        .bind(since)
+        .execute(pool)
+        .await
+    {
+        return Err(DatabaseError::Delete(e));
+    }
+    let query =
+        "insert into idempotency_keys(key, url, fingerprint, created) values (?, null, ?, ?)
+        on conflict(key) do nothing";
+    match sqlx::query(query)
+        .bind(key)
+        .bind(fingerprint)
+        .bind(utility::unix_timestamp())
+        .execute(pool)
+        .await
+    {
+        Ok(result) => Ok(result.rows_affected() == 1),
+        Err(e) => Err(DatabaseError::Insert(e)),
+    }
+}
+
+/// Looks up an idempotency key
+///
+/// **Arguments**
+/// * `pool`: an `&SqlitePool` reference
+/// * `key`: the key sent by the client, qualified by its namespace
+pub async fn retrieve_idempotency_key(
+    pool: &SqlitePool,
+    key: &str,
+) -> Result<Option<IdempotencyKey>, DatabaseError> {
+    let _timer = slow_queries::Timer::start("retrieve_idempotency_key");
+    let query = "select url, fingerprint from idempotency_keys where key = ?";
+    match sqlx::query(query).bind(key).fetch_optional(pool).await {
+        Ok(row) => Ok(row.map(|row| IdempotencyKey {
+            url:         row.get("url"),
+            fingerprint: row.get("fingerprint"),
+        })),
+        Err(e) => Err(DatabaseError::Retrieval(e)),
+    }
+}
+
+/// Remembers the paste created by the request that claimed an idempotency key
+///
+/// **Arguments**
+/// * `pool`: an `&SqlitePool` reference
+/// * `key`: the key sent by the client, qualified by its namespace
+/// * `url`: the created paste's URL
+pub async fn complete_idempotency_key(
+    pool: &SqlitePool,
+    key: &str,
+    url: &str,
+) -> Result<(), DatabaseError> {
+    let _timer = slow_queries::Timer::start("complete_idempotency_key");
+    let query = "update idempotency_keys set url = ? where key = ?";
+    match sqlx::query(query).bind(url).bind(key).execute(pool).await {
+        Ok(_) => Ok(()),
+        Err(e) => Err(DatabaseError::Update(e)),
+    }
+}
+
+/// Gives up a claimed idempotency key after creating its paste failed, so the request can be retried
+///
+/// **Arguments**
+/// * `pool`: an `&SqlitePool` reference
+/// * `key`: the key sent by the client, qualified by its namespace
+pub async fn release_idempotency_key(pool: &SqlitePool, key: &str) -> Result<(), DatabaseError> {
+    let _timer = slow_queries::Timer::start("release_idempotency_key");
+    let query = "delete from idempotency_keys where key = ? and url is null";
+    match sqlx::query(query).bind(key).execute(pool).await {
+        Ok(_) => Ok(()),
+        Err(e) => Err(DatabaseError::Delete(e)),
+    }
+}
+
+/// Reserves a URL until `expires`, forgetting all reservations that have expired before
+/// Returns whether the URL was reserved, which it isn't if someone else's reservation of it is still active
+///
+/// **Arguments**
+/// * `pool`: an `&SqlitePool` reference
+/// * `url`: the URL to reserve
+/// * `password_hash`: the hash of the password the paste has to be created with
+/// * `expires`: the unix timestamp the reservation expires at
+pub async fn insert_reservation(
+    pool: &SqlitePool,
+    url: &str,
+    password_hash: &str,
+    expires: i64,
+) -> Result<bool, DatabaseError> {
+    let _timer = slow_queries::Timer::start("insert_reservation");
+    if let Err(e) = sqlx::query("delete from reservations where expires <= ?")
+        .bind(utility::unix_timestamp())
+        .execute(pool)
+        .await
+    {
+        return Err(DatabaseError::Delete(e));
+    }
+    let query = "insert or ignore into reservations(url, password, expires) values (?, ?, ?)";
+    match sqlx::query(query)
+        .bind(url)
+        .bind(password_hash)
+        .bind(expires)
+        .execute(pool)
+        .await
+    {
+        Ok(result) => Ok(result.rows_affected() == 1),
+        Err(e) => Err(DatabaseError::Insert(e)),
+    }
+}
+
+/// Retrieves the password hash of a URL's reservation, if it hasn't expired
+///
+/// **Arguments**
+/// * `pool`: an `&SqlitePool` reference
+/// * `url`: the reserved URL
+pub async fn retrieve_reservation(
+    pool: &SqlitePool,
+    url: &str,
+) -> Result<Option<String>, DatabaseError> {
+    let _timer = slow_queries::Timer::start("retrieve_reservation");
+    let query = "select password from reservations where url = ? and expires > ?";
+    match sqlx::query(query)
+        .bind(url)
+        .bind(utility::unix_timestamp())
+        .fetch_optional(pool)
+        .await
+    {
+        Ok(row) => Ok(row.map(|row| row.get("password"))),
+        Err(e) => Err(DatabaseError::Retrieval(e)),
+    }
+}
+
+/// Removes a URL's reservation, once a paste has been created with it
+///
+/// **Arguments**
+/// * `pool`: an `&SqlitePool` reference
+/// * `url`: the reserved URL
+pub async fn delete_reservation(pool: &SqlitePool, url: &str) -> Result<(), DatabaseError> {
+    let _timer = slow_queries::Timer::start("delete_reservation");
+    match sqlx::query("delete from reservations where url = ?")
+        .bind(url)
+        .execute(pool)
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(e) => Err(DatabaseError::Delete(e)),
+    }
+}
+
+/// A webhook message waiting to be delivered, see `webhooks::Notifier`
+pub struct OutboxEntry {
+    pub id:       i64,
+    /// The webhook URL the message is posted to
+    pub target:   String,
+    /// The JSON payload
+    pub body:     String,
+    /// How many times delivering the message has failed
+    pub attempts: i64,
+}
+
+/// Queues a webhook message for delivery
+///
+/// **Arguments**
+/// * `pool`: an `&SqlitePool` reference
+/// * `target`: the webhook URL
+/// * `body`: the JSON payload
+/// * `next_attempt`: the unix timestamp the first attempt is due at
+pub async fn insert_outbox_entry(
+    pool: &SqlitePool,
+    target: &str,
+    body: &str,
+    next_attempt: i64,
+) -> Result<(), DatabaseError> {
+    let _timer = slow_queries::Timer::start("insert_outbox_entry");
+    let query = "insert into webhook_outbox(target, body, next_attempt) values (?, ?, ?)";
+    match sqlx::query(query)
+        .bind(target)
+        .bind(body)
+        .bind(next_attempt)
+        .execute(pool)
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(e) => Err(DatabaseError::Insert(e)),
+    }
+}
+
+/// Returns the webhook messages that are due for an attempt, oldest first
+///
+/// **Arguments**
+/// * `pool`: an `&SqlitePool` reference
+/// * `now`: the current unix timestamp
+/// * `limit`: the most messages returned
+pub async fn retrieve_due_outbox_entries(
+    pool: &SqlitePool,
+    now: i64,
+    limit: i64,
+) -> Result<Vec<OutboxEntry>, DatabaseError> {
+    let _timer = slow_queries::Timer::start("retrieve_due_outbox_entries");
+    let query = "select id, target, body, attempts from webhook_outbox
+        where next_attempt <= ? order by id limit ?";
+    match sqlx::query(query)
+        .bind(now)
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+    {
+        Ok(rows) => Ok(rows
+            .iter()
+            .map(|row| OutboxEntry {
+                id:       row.get("id"),
+                target:   row.get("target"),
+                body:     row.get("body"),
+                attempts: row.get("attempts"),
+            })
+            .collect()),
+        Err(e) => Err(DatabaseError::Retrieval(e)),
+    }
+}
+
+/// Schedules another attempt at delivering a webhook message after a failed one
+///
+/// **Arguments**
+/// * `pool`: an `&SqlitePool` reference
+/// * `id`: the message's ID
+/// * `attempts`: how many attempts have failed so far
+/// * `next_attempt`: the unix timestamp the next attempt is due at
+pub async fn reschedule_outbox_entry(
+    pool: &SqlitePool,
+    id: i64,
+    attempts: i64,
+    next_attempt: i64,
+) -> Result<(), DatabaseError> {
+    let _timer = slow_queries::Timer::start("reschedule_outbox_entry");
+    let query = "update webhook_outbox set attempts = ?, next_attempt = ? where id = ?";
+    match sqlx::query(query)
+        .bind(attempts)
+        .bind(next_attempt)
+        .bind(id)
+        .execute(pool)
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(e) => Err(DatabaseError::Update(e)),
+    }
+}
+
+/// Removes a webhook message that was delivered or given up on
+///
+/// **Arguments**
+/// * `pool`: an `&SqlitePool` reference
+/// * `id`: the message's ID
+pub async fn delete_outbox_entry(pool: &SqlitePool, id: i64) -> Result<(), DatabaseError> {
+    let _timer = slow_queries::Timer::start("delete_outbox_entry");
+    match sqlx::query("delete from webhook_outbox where id = ?")
+        .bind(id)
+        .execute(pool)
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(e) => Err(DatabaseError::Delete(e)),
+    }
+}
+
+/// A queued job as stored, see the `jobs` module
+#[derive(Serialize, Debug, Clone)]
+pub struct JobEntry {
+    pub id:         i64,
+    /// The job, serialized as JSON
+    pub payload:    String,
+    /// `pending`, `running` or `failed`
+    pub status:     String,
+    /// How many times running the job has failed
+    pub attempts:   i64,
+    /// The unix timestamp the job is due at
+    pub run_at:     i64,
+    pub last_error: Option<String>,
+}
+
+fn job_from_row(row: &SqliteRow) -> JobEntry {
+    JobEntry {
+        id:         row.get("id"),
+        payload:    row.get("payload"),
+        status:     row.get("status"),
+        attempts:   row.get("attempts"),
+        run_at:     row.get("run_at"),
+        last_error: row.get("last_error"),
+    }
+}
+
+/// Queues a job
+///
+/// **Arguments**
+/// * `pool`: an `&SqlitePool` reference
+/// * `payload`: the job, serialized as JSON
+/// * `run_at`: the unix timestamp the job is due at
+pub async fn insert_job(
+    pool: &SqlitePool,
+    payload: &str,
+    run_at: i64,
+) -> Result<(), DatabaseError> {
+    let _timer = slow_queries::Timer::start("insert_job");
+    let query = "insert into jobs(payload, run_at, created) values (?, ?, ?)";
+    match sqlx::query(query)
+        .bind(payload)
+        .bind(run_at)
+        .bind(utility::unix_timestamp())
+        .execute(pool)
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(e) => Err(DatabaseError::Insert(e)),
+    }
+}
+
+/// Retrieves when a pending job is due, if it is queued and hasn't started
+///
+/// **Arguments**
+/// * `pool`: an `&SqlitePool` reference
+/// * `payload`: the job, serialized as JSON
+pub async fn retrieve_pending_job_run_at(
+    pool: &SqlitePool,
+    payload: &str,
+) -> Result<Option<i64>, DatabaseError> {
+    let _timer = slow_queries::Timer::start("retrieve_pending_job_run_at");
+    let query = "select min(run_at) as run_at from jobs where payload = ? and status = 'pending'";
+    match sqlx::query(query).bind(payload).fetch_one(pool).await {
+        Ok(row) => Ok(row.get("run_at")),
+        Err(e) => Err(DatabaseError::Retrieval(e)),
+    }
+}
+
+/// Moves a pending job to a different time, returning whether such a job was pending
+///
+/// **Arguments**
+/// * `pool`: an `&SqlitePool` reference
+/// * `payload`: the job, serialized as JSON
+/// * `run_at`: the unix timestamp the job is now due at
+pub async fn reschedule_pending_job(
+    pool: &SqlitePool,
+    payload: &str,
+    run_at: i64,
+) -> Result<bool, DatabaseError> {
+    let _timer = slow_queries::Timer::start("reschedule_pending_job");
+    let query = "update jobs set run_at = ? where payload = ? and status = 'pending'";
+    match sqlx::query(query)
+        .bind(run_at)
+        .bind(payload)
+        .execute(pool)
+        .await
+    {
+        Ok(result) => Ok(result.rows_affected() > 0),
+        Err(e) => Err(DatabaseError::Update(e)),
+    }
+}
+
+/// Marks up to `limit` due jobs as running and returns them, so they aren't picked up twice
+///
+/// **Arguments**
+/// * `pool`: an `&SqlitePool` reference
+/// * `now`: the current unix timestamp
+/// * `limit`: the most jobs claimed
+pub async fn claim_due_jobs(
+    pool: &SqlitePool,
+    now: i64,
+    limit: i64,
+) -> Result<Vec<JobEntry>, DatabaseError> {
+    let _timer = slow_queries::Timer::start("claim_due_jobs");
+    let query = "update jobs set status = 'running' where id in (
+            select id from jobs where status = 'pending' and run_at <= ? order by run_at limit ?
+        ) returning *";
+    match sqlx::query(query)
+        .bind(now)
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+    {
+        Ok(rows) => Ok(rows.iter().map(job_from_row).collect()),
+        Err(e) => Err(DatabaseError::Update(e)),
+    }
+}
+
+/// Returns when the next pending job is due, if any are pending
+///
+/// **Arguments**
+/// * `pool`: an `&SqlitePool` reference
+pub async fn retrieve_next_job_due(pool: &SqlitePool) -> Result<Option<i64>, DatabaseError> {
+    let _timer = slow_queries::Timer::start("retrieve_next_job_due");
+    let query = "select min(run_at) as run_at from jobs where status = 'pending'";
+    match sqlx::query(query).fetch_one(pool).await {
+        Ok(row) => Ok(row.get("run_at")),
+        Err(e) => Err(DatabaseError::Retrieval(e)),
+    }
+}
+
+/// Queues jobs that were still running when pasties stopped again
+///
+/// **Arguments**
+/// * `pool`: an `&SqlitePool` reference
+pub async fn requeue_running_jobs(pool: &SqlitePool) -> Result<(), DatabaseError> {
+    let _timer = slow_queries::Timer::start("requeue_running_jobs");
+    let query = "update jobs set status = 'pending' where status = 'running'";
+    match sqlx::query(query).execute(pool).await {
+        Ok(_) => Ok(()),
+        Err(e) => Err(DatabaseError::Update(e)),
+    }
+}
+
+/// Records a failed run of a job, and either queues it again or marks it as failed for good
+///
+/// **Arguments**
+/// * `pool`: an `&SqlitePool` reference
+/// * `id`: the job's ID
+/// * `attempts`: how many runs have failed so far
+/// * `error`: why the run failed
+/// * `retry_at`: the unix timestamp of the next run, or `None` to give up on the job
+pub async fn record_job_failure(
+    pool: &SqlitePool,
+    id: i64,
+    attempts: i64,
+    error: &str,
+    retry_at: Option<i64>,
+) -> Result<(), DatabaseError> {
+    let _timer = slow_queries::Timer::start("record_job_failure");
+    let status = match retry_at {
+        Some(_) => "pending",
+        None => "failed",
+    };
+    let query = "update jobs set status = ?, attempts = ?, last_error = ?, run_at = coalesce(?, run_at) where id = ?";
+    match sqlx::query(query)
+        .bind(status)
+        .bind(attempts)
+        .bind(error)
+        .bind(retry_at)
+        .bind(id)
+        .execute(pool)
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(e) => Err(DatabaseError::Update(e)),
+    }
+}
+
+/// Removes a job that has run successfully
+///
+/// **Arguments**
+/// * `pool`: an `&SqlitePool` reference
+/// * `id`: the job's ID
+pub async fn delete_job(pool: &SqlitePool, id: i64) -> Result<(), DatabaseError> {
+    let _timer = slow_queries::Timer::start("delete_job");
+    match sqlx::query("delete from jobs where id = ?")
+        .bind(id)
+        .execute(pool)
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(e) => Err(DatabaseError::Delete(e)),
+    }
+}
+
+/// Returns every job that is pending, running or has failed, in the order they are due
+///
+/// **Arguments**
+/// * `pool`: an `&SqlitePool` reference
+pub async fn retrieve_jobs(pool: &SqlitePool) -> Result<Vec<JobEntry>, DatabaseError> {
+    let _timer = slow_queries::Timer::start("retrieve_jobs");
+    match sqlx::query("select * from jobs order by run_at")
+        .fetch_all(pool)
+        .await
+    {
+        Ok(rows) => Ok(rows.iter().map(job_from_row).collect()),
+        Err(e) => Err(DatabaseError::Retrieval(e)),
+    }
+}
+
+/// Counts the jobs of a kind that haven't finished yet, pending or running
+///
+/// **Arguments**
+/// * `pool`: an `&SqlitePool` reference
+/// * `kind`: the jobs' `kind`, as serialized
+pub async fn count_unfinished_jobs(pool: &SqlitePool, kind: &str) -> Result<i64, DatabaseError> {
+    let _timer = slow_queries::Timer::start("count_unfinished_jobs");
+    let query = "select count(*) from jobs
+        where json_extract(payload, '$.kind') = ? and status in ('pending', 'running')";
+    match sqlx::query(query).bind(kind).fetch_one(pool).await {
+        Ok(row) => Ok(row.get(0)),
+        Err(e) => Err(DatabaseError::Retrieval(e)),
+    }
+}
+
+/// The announcement banner as stored, see the `banner` module
+#[derive(Debug, Clone)]
+pub struct BannerEntry {
+    pub message:  String,
+    /// `info`, `warning` or `critical`
+    pub severity: String,
+    /// The unix timestamp the banner is hidden at
+    pub expires:  Option<i64>,
+}
+
+/// Returns the announcement banner, if one was set
+///
+/// **Arguments**
+/// * `pool`: an `&SqlitePool` reference
+pub async fn retrieve_banner(pool: &SqlitePool) -> Result<Option<BannerEntry>, DatabaseError> {
+    let _timer = slow_queries::Timer::start("retrieve_banner");
+    match sqlx::query("select * from banner where id = 1")
+        .fetch_optional(pool)
+        .await
+    {
+        Ok(row) => Ok(row.map(|row| BannerEntry {
+            message:  row.get("message"),
+            severity: row.get("severity"),
+            expires:  row.get("expires"),
+        })),
+        Err(e) => Err(DatabaseError::Retrieval(e)),
+    }
+}
+
+/// Sets the announcement banner, replacing the previous one
+///
+/// **Arguments**
+/// * `pool`: an `&SqlitePool` reference
+/// * `banner`: the new banner
+pub async fn set_banner(pool: &SqlitePool, banner: &BannerEntry) -> Result<(), DatabaseError> {
+    let _timer = slow_queries::Timer::start("set_banner");
+    let query = "insert or replace into banner(id, message, severity, expires) values (1, ?, ?, ?)";
+    match sqlx::query(query)
+        .bind(&banner.message)
+        .bind(&banner.severity)
+        .bind(banner.expires)
+        .execute(pool)
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(e) => Err(DatabaseError::Insert(e)),
+    }
+}
+
+/// Removes the announcement banner
+///
+/// **Arguments**
+/// * `pool`: an `&SqlitePool` reference
+pub async fn delete_banner(pool: &SqlitePool) -> Result<(), DatabaseError> {
+    let _timer = slow_queries::Timer::start("delete_banner");
+    match sqlx::query("delete from banner").execute(pool).await {
+        Ok(_) => Ok(()),
+        Err(e) => Err(DatabaseError::Delete(e)),
+    }
+}
+
+/// Creates a new, empty collection
+///
+/// **Arguments**
+/// * `pool`: an `&SqlitePool` reference
+/// * `name`: the collection's unique name
+/// * `password_hash`: the hash of the password that authorizes changes to the collection
+pub async fn insert_collection(
+    pool: &SqlitePool,
+    name: &str,
+    password_hash: &str,
+) -> Result<(), DatabaseError> {
+    let _timer = slow_queries::Timer::start("insert_collection");
+    let query = "insert into collections(name, password, date_created) values (?, ?, ?)";
+    match sqlx::query(query)
+        .bind(name)
+        .bind(password_hash)
+        .bind(utility::unix_timestamp())
+        .execute(pool)
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(e) => Err(DatabaseError::Insert(e)),
+    }
+}
+
+/// Retrieves the password hash of a collection, which fails if there is no collection with the name
+///
+/// **Arguments**
+/// * `pool`: an `&SqlitePool` reference
+/// * `name`: the collection's name
+pub async fn retrieve_collection_password(
+    pool: &SqlitePool,
+    name: &str,
+) -> Result<String, DatabaseError> {
+    let _timer = slow_queries::Timer::start("retrieve_collection_password");
+    let query = "select password from collections where name = ?";
+    match sqlx::query(query).bind(name).fetch_one(pool).await {
+        Ok(row) => Ok(row.get("password")),
+        Err(e) => Err(DatabaseError::Retrieval(e)),
+    }
+}
+
+/// Retrieves the pastes in a collection, in the order they were added
+/// Pastes with a view limit are left out, as their excerpts would be shown without counting a view
+///
+/// **Arguments**
+/// * `pool`: an `&SqlitePool` reference
+/// * `name`: the collection's name
+pub async fn retrieve_collection_pastes(
+    pool: &SqlitePool,
+    name: &str,
+) -> Result<Vec<DatabasePaste>, DatabaseError> {
+    let _timer = slow_queries::Timer::start("retrieve_collection_pastes");
+    let query = "select pastes.* from collection_pastes
+        join pastes on pastes.url = collection_pastes.url
+        where collection_pastes.collection = ? and pastes.draft = 0 and pastes.quarantined = 0
+            and pastes.views_remaining is null
+        order by collection_pastes.position";
+    match sqlx::query(query).bind(name).fetch_all(pool).await {
+        Ok(rows) => Ok(rows.iter().map(paste_from_row).collect()),
+        Err(e) => Err(DatabaseError::Retrieval(e)),
+    }
+}
+
+/// Adds a paste to the end of a collection, unless it is already in it
+///
+/// **Arguments**
+/// * `pool`: an `&SqlitePool` reference
+/// * `name`: the collection's name
+/// * `url`: the paste's URL
+pub async fn insert_collection_paste(
+    pool: &SqlitePool,
+    name: &str,
+    url: &str,
+) -> Result<(), DatabaseError> {
+    let _timer = slow_queries::Timer::start("insert_collection_paste");
+    let query = "insert or ignore into collection_pastes(collection, url, position)
+        select ?1, ?2, coalesce(max(position), 0) + 1 from collection_pastes where collection = ?1";
+    match sqlx::query(query).bind(name).bind(url).execute(pool).await {
+        Ok(_) => Ok(()),
+        Err(e) => Err(DatabaseError::Insert(e)),
+    }
+}
+
+/// Removes a paste from a collection. Returns whether it was in the collection
+///
+/// **Arguments**
+/// * `pool`: an `&SqlitePool` reference
+/// * `name`: the collection's name
+/// * `url`: the paste's URL
+pub async fn delete_collection_paste(
+    pool: &SqlitePool,
+    name: &str,
+    url: &str,
+) -> Result<bool, DatabaseError> {
+    let _timer = slow_queries::Timer::start("delete_collection_paste");
+    let query = "delete from collection_pastes where collection = ? and url = ?";
+    match sqlx::query(query).bind(name).bind(url).execute(pool).await {
+        Ok(result) => Ok(result.rows_affected() > 0),
+        Err(e) => Err(DatabaseError::Delete(e)),
+    }
+}
+
+/// Deletes autosaves that haven't been updated since the given time
+///
+/// **Arguments**
+/// * `pool`: an `&SqlitePool` reference
+/// * `since`: the unix timestamp older autosaves are deleted before
+async fn prune_autosaves(pool: &SqlitePool, since: i64) -> Result<(), DatabaseError> {
+    match sqlx::query("delete from autosaves where updated < ?")
+        .bind(since)
+        .execute(pool)
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(e) => Err(DatabaseError::Delete(e)),
+    }
+}
+
+/// Stores the editor contents under a token, replacing what was saved under it before. Expired autosaves are deleted first
+///
+/// **Arguments**
+/// * `pool`: an `&SqlitePool` reference
+/// * `token`: the client's autosave token
+/// * `autosave`: the contents to store
+/// * `since`: the unix timestamp older autosaves are deleted before
+pub async fn save_autosave(
+    pool: &SqlitePool,
+    token: &str,
+    autosave: &Autosave,
+    since: i64,
+) -> Result<(), DatabaseError> {
+    let _timer = slow_queries::Timer::start("save_autosave");
+    prune_autosaves(pool, since).await?;
+    let query = "insert into autosaves(token, content, format, updated) values (?, ?, ?, ?)
+        on conflict(token) do update set content = excluded.content, format = excluded.format, updated = excluded.updated";
+    match sqlx::query(query)
+        .bind(token)
+        .bind(&autosave.content)
+        .bind(&autosave.format)
+        .bind(autosave.updated)
+        .execute(pool)
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(e) => Err(DatabaseError::Insert(e)),
+    }
+}
+
+/// Retrieves the editor contents saved under a token, unless they have expired
+///
+/// **Arguments**
+/// * `pool`: an `&SqlitePool` reference
+/// * `token`: the client's autosave token
+/// * `since`: the unix timestamp older autosaves are deleted before
+pub async fn retrieve_autosave(
+    pool: &SqlitePool,
+    token: &str,
+    since: i64,
+) -> Result<Option<Autosave>, DatabaseError> {
+    let _timer = slow_queries::Timer::start("retrieve_autosave");
+    prune_autosaves(pool, since).await?;
+    let query = "select content, format, updated from autosaves where token = ?";
+    match sqlx::query(query).bind(token).fetch_optional(pool).await {
+        Ok(row) => Ok(row.map(|row| Autosave {
+            content: row.get("content"),
+            format:  row.get("format"),
+            updated: row.get("updated"),
+        })),
+        Err(e) => Err(DatabaseError::Retrieval(e)),
+    }
+}
+
+/// Deletes the editor contents saved under a token
+///
+/// **Arguments**
+/// * `pool`: an `&SqlitePool` reference
+/// * `token`: the client's autosave token
+pub async fn delete_autosave(pool: &SqlitePool, token: &str) -> Result<(), DatabaseError> {
+    let _timer = slow_queries::Timer::start("delete_autosave");
+    match sqlx::query("delete from autosaves where token = ?")
+        .bind(token)
+        .execute(pool)
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(e) => Err(DatabaseError::Delete(e)),
+    }
+}
@@ -0,0 +1,117 @@
+//! `detect` guesses the format of pastes that don't declare one, using cheap heuristics on their content
+//! Guesses err on the side of the default format, as rendering prose as anything else is worse than the other way around
+
+use serde_json::Value;
+
+/// The most lines looked at, so that detection stays cheap for large pastes
+const SAMPLE_LINES: usize = 200;
+
+/// Returns the name of the format `content` most likely is, or `None` if nothing stands out
+pub fn guess_format(content: &str) -> Option<&'static str> {
+    let trimmed = content.trim();
+    if trimmed.starts_with('{') || trimmed.starts_with('[') {
+        if let Ok(value) = serde_json::from_str::<Value>(trimmed) {
+            return match value.get("cells").is_some() && value.get("nbformat").is_some() {
+                true => Some("jupyter"),
+                false => Some("json"),
+            };
+        }
+    }
+    let lines: Vec<&str> = content
+        .lines()
+        .take(SAMPLE_LINES)
+        .filter(|line| !line.trim().is_empty())
+        .collect();
+    if is_org(&lines) {
+        return Some("org");
+    }
+    if is_asciidoc(&lines) {
+        return Some("asciidoc");
+    }
+    if let Some(format) = delimited_format(&lines) {
+        return Some(format);
+    }
+    if is_yaml(trimmed, &lines) {
+        return Some("yaml");
+    }
+    None
+}
+
+/// Org documents are recognized by their `#+KEYWORD:` lines and `#+BEGIN_` blocks, which markdown has no use for
+/// Returns the name of the format a file's extension stands for, e.g. for uploaded files
+pub fn format_of_file_name(file_name: &str) -> Option<&'static str> {
+    let (_, extension) = file_name.rsplit_once('.')?;
+    match extension.to_ascii_lowercase().as_str() {
+        "md" | "markdown" => Some("markdown"),
+        "txt" | "text" | "log" => Some("plain"),
+        "org" => Some("org"),
+        "ipynb" => Some("jupyter"),
+        "csv" => Some("csv"),
+        "tsv" => Some("tsv"),
+        "json" => Some("json"),
+        "yaml" | "yml" => Some("yaml"),
+        "adoc" | "asciidoc" => Some("asciidoc"),
+        _ => None,
+    }
+}
+
+fn is_org(lines: &[&str]) -> bool {
+    lines.iter().any(|line| {
+        let line = line.trim_start().to_ascii_uppercase();
+        line.starts_with("#+BEGIN_")
+            || line.starts_with("#+TITLE:")
+            || line.starts_with("#+AUTHOR:")
+    })
+}
+
+/// AsciiDoc documents are recognized by a `= Title` header followed by `:attribute:` lines, or by `[source,...]` blocks
+fn is_asciidoc(lines: &[&str]) -> bool {
+    let has_header = lines.first().is_some_and(|line| line.starts_with("= "))
+        && lines.get(1).is_some_and(|line| line.starts_with(':'));
+    has_header || lines.iter().any(|line| line.starts_with("[source,"))
+}
+
+/// Tables have at least two rows, and every row has the same, non-zero number of separators
+fn delimited_format(lines: &[&str]) -> Option<&'static str> {
+    if lines.len() < 2 {
+        return None;
+    }
+    [('\t', "tsv"), (',', "csv")]
+        .into_iter()
+        .find(|(delimiter, _)| {
+            let columns = lines[0].matches(*delimiter).count();
+            columns > 0
+                && lines
+                    .iter()
+                    .all(|line| line.matches(*delimiter).count() == columns)
+        })
+        .map(|(_, format)| format)
+}
+
+/// YAML has to parse as a mapping or sequence, and every top-level line has to look like a key or a list item
+/// Prose with a colon in it parses as a mapping too, which is why the lines are checked as well
+fn is_yaml(trimmed: &str, lines: &[&str]) -> bool {
+    let top_level = lines
+        .iter()
+        .filter(|line| !line.starts_with([' ', '\t', '#']) && *line != &"---");
+    let mut count = 0;
+    for line in top_level {
+        count += 1;
+        let is_item = line.starts_with("- ") || *line == "-";
+        let is_key = line.split_once(':').is_some_and(|(key, value)| {
+            !key.is_empty()
+                && key
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || "_-.".contains(c))
+                && (value.is_empty() || value.starts_with(' '))
+        });
+        if !is_item && !is_key {
+            return false;
+        }
+    }
+    (count >= 2 || trimmed.starts_with("---"))
+        && matches!(
+            serde_yaml::from_str::<Value>(trimmed),
+            Ok(Value::Object(_) | Value::Array(_))
+        )
+}
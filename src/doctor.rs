@@ -0,0 +1,185 @@
+//! `doctor` checks that the instance can start: that its configuration is consistent, the database is usable and the files it needs can be read and written
+//! The checks run at startup, so that misconfigurations stop pasties with an explanation, and with `pasties doctor`, which reports them without starting
+
+use std::{
+    fs::{self, OpenOptions},
+    path::Path,
+    process,
+};
+
+use crate::{
+    config::Config,
+    database::{self, DATABASE_FILE, SCHEMA_VERSION},
+    filters, mirror, private, read_only,
+    routing::{self, dav, pages::ASSET_DIR},
+    server, utility,
+};
+
+/// The assets the templates link to, relative to the assets directory
+const REQUIRED_ASSETS: [&str; 3] = ["style.css", "embed.js", "images/logo.png"];
+
+/// The problems found by the checks
+/// Errors keep pasties from starting, warnings are only logged
+#[derive(Debug, Default)]
+pub struct Report {
+    pub errors:   Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+impl Report {
+    fn error(&mut self, result: Result<(), String>) {
+        if let Err(e) = result {
+            self.errors.push(e)
+        }
+    }
+}
+
+/// Runs every check against a loaded configuration
+pub async fn check(config: &Config) -> Report {
+    let mut report = Report::default();
+    check_config(config, &mut report);
+    check_database(&mut report).await;
+    check_files(config, &mut report);
+    report
+}
+
+/// Runs `pasties doctor`, printing the problems and exiting with status 1 if there are errors
+pub async fn run() {
+    let config = match Config::try_load() {
+        Ok(config) => config,
+        Err(e) => {
+            println!("error: {e}");
+            process::exit(1)
+        }
+    };
+    let report = check(&config).await;
+    for error in &report.errors {
+        println!("error: {error}");
+    }
+    for warning in &report.warnings {
+        println!("warning: {warning}");
+    }
+    match (report.errors.len(), report.warnings.len()) {
+        (0, 0) => println!(
+            "No problems found with the configuration at {}",
+            Config::path()
+        ),
+        (0, warnings) => println!("{warnings} warning(s), pasties can start"),
+        (errors, _) => {
+            println!("{errors} error(s), pasties won't start until they are fixed");
+            process::exit(1)
+        }
+    }
+}
+
+/// Settings that can be parsed, but not served together
+fn check_config(config: &Config, report: &mut Report) {
+    report.error(private::check_config(config));
+    report.error(read_only::check_config(config));
+    report.error(mirror::check_config(config));
+    report.error(server::check_config(config));
+    report.error(dav::check_config(config));
+    report.error(filters::check_patterns(&config.filters));
+    for namespace in &config.namespaces {
+        report.error(routing::check_namespace_name(&namespace.name));
+    }
+}
+
+/// Whether the database exists, and was written by a version of pasties this build can migrate from
+async fn check_database(report: &mut Report) {
+    if !Path::new(DATABASE_FILE).exists() {
+        report.errors.push(format!(
+            "There is no database at {DATABASE_FILE} in the working directory, create an empty one with `touch {DATABASE_FILE}`"
+        ));
+        return;
+    }
+    match database::schema_version().await {
+        Ok(version) if version > SCHEMA_VERSION => report.errors.push(format!(
+            "{DATABASE_FILE} has schema version {version}, but this build of pasties only knows up to version {SCHEMA_VERSION}. Upgrade pasties, or restore a backup taken before the upgrade"
+        )),
+        Ok(version) if version < SCHEMA_VERSION => report.warnings.push(format!(
+            "{DATABASE_FILE} has schema version {version}, and will be migrated to version {SCHEMA_VERSION} on startup. Take a backup first with `pasties backup <path>`"
+        )),
+        Ok(_) => {}
+        Err(e) => report
+            .errors
+            .push(format!("Failed to read {DATABASE_FILE}:\n    {e:?}")),
+    }
+}
+
+/// Files pasties reads or writes while it runs
+fn check_files(config: &Config, report: &mut Report) {
+    // SQLite keeps its journal next to the database, so the directory has to be writable as well
+    report.error(check_writable_directory(Path::new(".")));
+    if Path::new(DATABASE_FILE).exists() {
+        report.error(check_writable_file(DATABASE_FILE));
+    }
+    if let Some(path) = config.access_log.as_ref().and_then(|log| log.path.as_ref()) {
+        report.error(check_writable_file(path));
+    }
+    if let Some(gemini) = &config.gemini {
+        report.error(check_readable(&gemini.cert));
+        report.error(check_readable(&gemini.key));
+    }
+    // Meta pages whose files can't be read are only missing, like unset ones
+    let meta = &config.meta;
+    for path in [&meta.about, &meta.rules, &meta.terms, &meta.privacy]
+        .into_iter()
+        .flatten()
+    {
+        if let Err(e) = check_readable(path) {
+            report.warnings.push(e);
+        }
+    }
+    for asset in REQUIRED_ASSETS {
+        let path = Path::new(ASSET_DIR).join(asset);
+        if !path.is_file() {
+            let hint = match asset {
+                "style.css" => ", compile it with `sass assets/style.sass assets/style.css`",
+                _ => ", pasties has to run from the directory that contains `assets/`",
+            };
+            report.warnings.push(format!(
+                "The asset {} is missing, so pages will be served without it{hint}",
+                path.display()
+            ));
+        }
+    }
+}
+
+fn check_readable(path: &str) -> Result<(), String> {
+    match fs::File::open(path) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Failed to read {path}:\n    {e}")),
+    }
+}
+
+/// Checks that a file can be appended to, or created if it doesn't exist, without changing it
+fn check_writable_file(path: &str) -> Result<(), String> {
+    let file = Path::new(path);
+    if !file.exists() {
+        let directory = match file.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        return check_writable_directory(directory);
+    }
+    match OpenOptions::new().append(true).open(file) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Failed to open {path} for writing:\n    {e}")),
+    }
+}
+
+/// Checks that files can be created in a directory, by creating and removing one
+fn check_writable_directory(directory: &Path) -> Result<(), String> {
+    let probe = directory.join(format!(".pasties-doctor-{}", utility::random_string()));
+    match fs::File::create(&probe) {
+        Ok(_) => {
+            let _ = fs::remove_file(&probe);
+            Ok(())
+        }
+        Err(e) => Err(format!(
+            "Failed to create files in {}:\n    {e}",
+            directory.display()
+        )),
+    }
+}
@@ -0,0 +1,57 @@
+//! `events` broadcasts what happens to pastes, so subsystems like webhooks can react without being called from `Manager`'s CRUD methods
+//! Subscribers get their own receiver through `EventBus::subscribe` and handle events on their own task
+
+use tokio::sync::broadcast::{self, Receiver, Sender};
+
+/// How many events are buffered for slow subscribers, before the oldest ones are dropped for them
+const CAPACITY: usize = 1024;
+
+#[derive(Clone, Debug)]
+pub enum PasteEvent {
+    Created {
+        url:    String,
+        /// Drafts and quarantined pastes are hidden until they are published or released
+        hidden: bool,
+    },
+    /// A draft was made public
+    Published {
+        url: String,
+    },
+    Updated {
+        previous_url: String,
+        url:          String,
+    },
+    Deleted {
+        url: String,
+    },
+    /// Something other than the content changed, like the password or whether the paste is locked or quarantined
+    Changed {
+        url: String,
+    },
+    Viewed {
+        url: String,
+    },
+}
+
+#[derive(Clone)]
+pub struct EventBus {
+    sender: Sender<PasteEvent>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        let (sender, _) = broadcast::channel(CAPACITY);
+        Self { sender }
+    }
+}
+
+impl EventBus {
+    /// Sends an event to every current subscriber. Without subscribers, it is dropped
+    pub fn publish(&self, event: PasteEvent) {
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> Receiver<PasteEvent> {
+        self.sender.subscribe()
+    }
+}
@@ -0,0 +1,64 @@
+//! `excerpt` turns a paste's content into plain text for exports, and shortens it into a summary for listings and link previews
+use pulldown_cmark::{Event, Parser, TagEnd};
+
+/// The longest an excerpt can be, in characters, not counting the ellipsis
+pub const EXCERPT_LENGTH: usize = 200;
+
+/// Returns the start of a paste's content as a single line of plain text, cut at a word boundary
+/// Markdown is stripped of its syntax first, other formats are only collapsed onto one line
+pub fn excerpt(format: &str, content: &str) -> String {
+    let text = match format {
+        "markdown" => markdown_text(content),
+        _ => content.to_string(),
+    };
+    let mut excerpt = String::new();
+    for word in text.split_whitespace() {
+        let length = excerpt.chars().count() + word.chars().count() + 1;
+        if length > EXCERPT_LENGTH {
+            if excerpt.is_empty() {
+                excerpt.extend(word.chars().take(EXCERPT_LENGTH));
+            }
+            excerpt.push('…');
+            return excerpt;
+        }
+        if !excerpt.is_empty() {
+            excerpt.push(' ');
+        }
+        excerpt.push_str(word);
+    }
+    excerpt
+}
+
+/// Returns a paste's whole content as plain text, markdown stripped of its syntax and other formats as they are
+pub fn plain_text(format: &str, content: &str) -> String {
+    match format {
+        "markdown" => format!("{}\n", markdown_text(content).trim_end()),
+        _ => content.to_string(),
+    }
+}
+
+/// The text of a markdown document, keeping its line breaks, with blocks separated by blank lines
+fn markdown_text(markdown: &str) -> String {
+    let mut text = String::new();
+    for event in Parser::new(markdown) {
+        match event {
+            Event::Text(t) | Event::Code(t) => text.push_str(&t),
+            Event::SoftBreak
+            | Event::HardBreak
+            // Code blocks already end with a line break, and lists end after their last item's
+            | Event::End(
+                TagEnd::Item
+                | TagEnd::List(_)
+                | TagEnd::CodeBlock
+                | TagEnd::TableHead
+                | TagEnd::TableRow,
+            ) => text.push('\n'),
+            Event::End(TagEnd::Paragraph | TagEnd::Heading(_) | TagEnd::Table) => {
+                text.push_str("\n\n")
+            }
+            Event::End(TagEnd::TableCell) => text.push('\t'),
+            _ => (),
+        }
+    }
+    text
+}
@@ -0,0 +1,122 @@
+//! `filters` checks new and updated pastes against the content policies in `[[filters]]`, as a first line of defense against doxxing and spam
+//! Each policy matches a regular expression, a list of words, or both. Matching pastes are either rejected with an error naming the policy,
+//! or quarantined: they are kept, but only shown to whoever has their password until an admin releases them
+
+use std::sync::Arc;
+
+use regex::{Regex, RegexBuilder};
+use serde::Deserialize;
+
+use crate::config::FilterConfig;
+
+/// What happens to pastes that match a filter
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum FilterAction {
+    /// The paste isn't created or changed
+    #[default]
+    Reject,
+    /// The paste is kept, but hidden until an admin releases it
+    Quarantine,
+}
+
+struct Filter {
+    name:    String,
+    action:  FilterAction,
+    pattern: Option<Regex>,
+    /// The word list as one case-insensitive pattern matching whole words
+    words:   Option<Regex>,
+}
+
+impl Filter {
+    fn matches(&self, content: &str) -> bool {
+        self.pattern
+            .iter()
+            .chain(&self.words)
+            .any(|regex| regex.is_match(content))
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct ContentFilters {
+    filters: Arc<Vec<Filter>>,
+}
+
+/// Checks that the patterns of all filters are valid regular expressions
+pub fn check_patterns(configs: &[FilterConfig]) -> Result<(), String> {
+    for config in configs {
+        if let Some(Err(e)) = config.pattern.as_ref().map(|pattern| Regex::new(pattern)) {
+            return Err(format!(
+                "Failed to compile the pattern of the \"{}\" filter:\n    {e}",
+                config.name
+            ));
+        }
+    }
+    Ok(())
+}
+
+impl ContentFilters {
+    /// Compiles the configured filters
+    /// **Panics** if a pattern isn't a valid regular expression, as running without a policy the operator relies on is worse than not starting
+    pub fn new(configs: &[FilterConfig]) -> Self {
+        let filters = configs
+            .iter()
+            .map(|config| {
+                let pattern = config
+                    .pattern
+                    .as_ref()
+                    .map(|pattern| match Regex::new(pattern) {
+                        Ok(regex) => regex,
+                        Err(e) => panic!(
+                            "Failed to compile the pattern of the \"{}\" filter:\n    {e}",
+                            config.name
+                        ),
+                    });
+                let words = match config.words.is_empty() {
+                    true => None,
+                    false => {
+                        let alternatives: Vec<String> = config
+                            .words
+                            .iter()
+                            .map(|word| regex::escape(word))
+                            .collect();
+                        let pattern = format!(r"\b(?:{})\b", alternatives.join("|"));
+                        // Escaped words always make a valid pattern
+                        Some(
+                            RegexBuilder::new(&pattern)
+                                .case_insensitive(true)
+                                .build()
+                                .unwrap(),
+                        )
+                    }
+                };
+                Filter {
+                    name: config.name.clone(),
+                    action: config.action,
+                    pattern,
+                    words,
+                }
+            })
+            .collect();
+        Self {
+            filters: Arc::new(filters),
+        }
+    }
+
+    /// The name of the first rejecting policy the content violates
+    pub fn rejects(&self, content: &str) -> Option<&str> {
+        self.first_match(content, FilterAction::Reject)
+    }
+
+    /// The name of the first quarantining policy the content violates
+    pub fn quarantines(&self, content: &str) -> Option<&str> {
+        self.first_match(content, FilterAction::Quarantine)
+    }
+
+    fn first_match(&self, content: &str, action: FilterAction) -> Option<&str> {
+        self.filters
+            .iter()
+            .find(|filter| filter.action == action && filter.matches(content))
+            .map(|filter| filter.name.as_str())
+    }
+}
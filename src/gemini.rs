@@ -0,0 +1,241 @@
+//! `gemini` serves pastes over the Gemini protocol, as gemtext, and accepts short pastes through Gemini's input prompts
+//! Gemini requires TLS, clients trust certificates on first use, so a self-signed certificate is enough
+
+use std::{fs, sync::Arc, time::Duration};
+
+use percent_encoding::percent_decode_str;
+use tokio::{
+    io::{AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::TcpListener,
+    time,
+};
+use tokio_rustls::{
+    rustls::{
+        crypto::ring,
+        pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer},
+        ServerConfig,
+    },
+    TlsAcceptor,
+};
+use url::Url;
+
+use crate::{
+    config::GeminiConfig,
+    model::{Manager, NewPasteData, PasteError, PasteReturn},
+    utility,
+};
+
+/// The longest request the protocol allows, a URL of 1024 bytes followed by CRLF
+const MAX_REQUEST_LENGTH: usize = 1026;
+
+/// How long a client has to send its request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Binds the Gemini listener and loads its certificate
+/// **Panics** if the listener cannot be bound or the certificate cannot be loaded, like the HTTP listeners
+pub async fn bind(config: &GeminiConfig) -> (TcpListener, TlsAcceptor) {
+    let certificates: Vec<CertificateDer> = match CertificateDer::pem_file_iter(&config.cert)
+        .and_then(|certificates| certificates.collect())
+    {
+        Ok(certificates) => certificates,
+        Err(e) => panic!(
+            "Failed to load the certificate at {}:\n    {e}",
+            config.cert
+        ),
+    };
+    let key = match fs::read(&config.key) {
+        Ok(key) => match PrivateKeyDer::from_pem_slice(&key) {
+            Ok(key) => key,
+            Err(e) => panic!("Failed to parse the key at {}:\n    {e}", config.key),
+        },
+        Err(e) => panic!("Failed to read the key at {}:\n    {e}", config.key),
+    };
+    let tls = match ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .and_then(|builder| {
+            builder
+                .with_no_client_auth()
+                .with_single_cert(certificates, key)
+        }) {
+        Ok(tls) => tls,
+        Err(e) => panic!("Failed to set up TLS for Gemini:\n    {e}"),
+    };
+    match TcpListener::bind(&config.listen).await {
+        Ok(listener) => (listener, TlsAcceptor::from(Arc::new(tls))),
+        Err(e) => panic!("Failed to bind to {}:\n    {e}", config.listen),
+    }
+}
+
+/// Accepts connections until the process is stopped
+pub async fn serve(listener: TcpListener, acceptor: TlsAcceptor, manager: Manager) {
+    if let Ok(address) = listener.local_addr() {
+        tracing::info!("Starting Gemini server at {address}!");
+    }
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(e) => {
+                tracing::warn!("Failed to accept a Gemini connection: {e}");
+                time::sleep(Duration::from_secs(1)).await;
+                continue;
+            }
+        };
+        let acceptor = acceptor.clone();
+        let manager = manager.clone();
+        tokio::spawn(async move {
+            match acceptor.accept(stream).await {
+                Ok(stream) => handle(stream, manager).await,
+                Err(e) => tracing::debug!("TLS handshake failed: {e}"),
+            }
+        });
+    }
+}
+
+async fn handle<S>(mut stream: S, manager: Manager)
+where
+    S: AsyncReadExt + AsyncWrite + Unpin,
+{
+    let response = match time::timeout(REQUEST_TIMEOUT, read_request(&mut stream)).await {
+        Ok(Some(request)) => respond(&request, &manager).await,
+        _ => "59 Bad request\r\n".to_string(),
+    };
+    let _ = stream.write_all(response.as_bytes()).await;
+    let _ = stream.shutdown().await;
+}
+
+/// Reads the request line, without its CRLF
+async fn read_request<S: AsyncReadExt + Unpin>(stream: &mut S) -> Option<String> {
+    let mut request = Vec::new();
+    let mut buffer = [0; 1024];
+    while !request.ends_with(b"\r\n") {
+        match stream.read(&mut buffer).await {
+            Ok(0) | Err(_) => return None,
+            Ok(read) => request.extend_from_slice(&buffer[..read]),
+        }
+        if request.len() > MAX_REQUEST_LENGTH {
+            return None;
+        }
+    }
+    request.truncate(request.len() - 2);
+    String::from_utf8(request).ok()
+}
+
+/// Builds the whole response, header line included
+async fn respond(request: &str, manager: &Manager) -> String {
+    let url = match Url::parse(request) {
+        Ok(url) => url,
+        Err(_) => return "59 Bad request\r\n".to_string(),
+    };
+    if url.scheme() != "gemini" {
+        return "53 Only gemini:// URLs are served here\r\n".to_string();
+    }
+    let path = url.path().trim_matches('/');
+    match (path, url.query()) {
+        ("", _) => success(
+            "text/gemini",
+            "# pasties\n\n=> /new Create a paste\n".to_string(),
+        ),
+        // Gemini clients can't send API keys
+        ("new", _) if manager.config().read_only => {
+            "59 Pastes can't be created over Gemini on this instance\r\n".to_string()
+        }
+        ("new", None) => "10 Paste content\r\n".to_string(),
+        ("new", Some(query)) => {
+            let content = match percent_decode_str(query).decode_utf8() {
+                Ok(content) => content.into_owned(),
+                Err(_) => return "59 Pastes have to be valid UTF-8\r\n".to_string(),
+            };
+            let paste = NewPasteData {
+                url: String::new(),
+                content,
+                password: utility::random_string(),
+                format: String::new(),
+                draft: false,
+                max_views: None,
+                expires_in: None,
+                font: None,
+                width: None,
+                wrap: None,
+            };
+            match manager.create_paste(paste).await {
+                Ok(url) => format!("30 /{url}\r\n"),
+                Err(e) => failure(e),
+            }
+        }
+        (path, _) => {
+            let (url, raw) = match path.strip_suffix("/raw") {
+                Some(url) => (url, true),
+                None => (path, false),
+            };
+            match manager.retrieve_paste(url.to_string()).await {
+                Ok(paste) if raw => success("text/plain", paste.content),
+                Ok(paste) => success("text/gemini", gemtext(&paste)),
+                Err(e) => failure(e),
+            }
+        }
+    }
+}
+
+fn success(mime: &str, body: String) -> String {
+    format!("20 {mime}; charset=utf-8\r\n{body}")
+}
+
+fn failure(e: PasteError) -> String {
+    match e {
+        PasteError::NotFound => "51 Not found\r\n".to_string(),
+        PasteError::Database(_) => "40 Something went wrong\r\n".to_string(),
+        e => format!("59 {e}\r\n"),
+    }
+}
+
+/// Converts a paste into gemtext
+/// Markdown is close enough to gemtext to be passed through, with its links repeated as link lines. Other formats are shown preformatted
+fn gemtext(paste: &PasteReturn) -> String {
+    let mut gemtext = format!("# {}\n\n=> /{}/raw Raw\n\n", paste.url, paste.url);
+    if paste.format != "markdown" {
+        gemtext.push_str(&format!("```{}\n{}\n```\n", paste.format, paste.content));
+        return gemtext;
+    }
+    let mut preformatted = false;
+    for line in paste.content.lines() {
+        if line.starts_with("```") {
+            preformatted = !preformatted;
+        }
+        if preformatted || line.starts_with("```") {
+            gemtext.push_str(line);
+            gemtext.push('\n');
+            continue;
+        }
+        // Gemtext only has three heading levels and one kind of list item
+        let level = line.chars().take_while(|c| *c == '#').count();
+        let line = match line.strip_prefix("- ") {
+            Some(item) => format!("* {item}"),
+            None if level > 3 => format!("###{}", &line[level..]),
+            None => line.to_string(),
+        };
+        gemtext.push_str(&line);
+        gemtext.push('\n');
+        for (text, target) in markdown_links(&line) {
+            gemtext.push_str(&format!("=> {target} {text}\n"));
+        }
+    }
+    gemtext
+}
+
+/// Finds inline links, `[text](target)`, in a line of markdown
+fn markdown_links(line: &str) -> Vec<(&str, &str)> {
+    let mut links = Vec::new();
+    let mut rest = line;
+    while let Some(start) = rest.find('[') {
+        rest = &rest[start + 1..];
+        let Some((text, after)) = rest.split_once("](") else {
+            break;
+        };
+        let Some((target, after)) = after.split_once(')') else {
+            break;
+        };
+        links.push((text, target));
+        rest = after;
+    }
+    links
+}
@@ -0,0 +1,80 @@
+//! `hooks` lets compiled-in extensions react to what happens to pastes and adjust how they are rendered
+//! Extensions implement `Hook` and are registered with `Manager::with_hook`, so forks can add behaviour without patching `Manager`
+
+use std::sync::Arc;
+
+use crate::model::PasteReturn;
+
+/// Callbacks into an extension. Every method does nothing by default, so a hook only implements what it needs
+/// Hooks are called synchronously on the request's task, anything slow should be spawned
+pub trait Hook: Send + Sync {
+    /// Called after a paste was created, drafts included
+    fn on_paste_created(&self, _paste: &PasteReturn) {}
+
+    /// Called whenever a paste is read, through any route
+    fn on_paste_viewed(&self, _paste: &PasteReturn) {}
+
+    /// Called after a paste was changed, with its URL from before the change and its current one
+    fn on_paste_updated(&self, _previous_url: &str, _url: &str) {}
+
+    /// Called after a paste was deleted, by its owner or because its view limit was reached
+    fn on_paste_deleted(&self, _url: &str) {}
+
+    /// Called before a paste is rendered, and may change what is rendered, e.g. to filter its content
+    fn before_render(&self, _paste: &mut PasteReturn) {}
+
+    /// Called with a paste's rendered HTML, just before it is served
+    fn after_render(&self, _paste: &PasteReturn, _html: &mut String) {}
+}
+
+/// The registered hooks, called in the order they were registered
+#[derive(Clone, Default)]
+pub struct Hooks {
+    hooks: Vec<Arc<dyn Hook>>,
+}
+
+impl Hooks {
+    pub fn register(&mut self, hook: impl Hook + 'static) {
+        self.hooks.push(Arc::new(hook));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hooks.is_empty()
+    }
+
+    pub fn paste_created(&self, paste: &PasteReturn) {
+        for hook in &self.hooks {
+            hook.on_paste_created(paste);
+        }
+    }
+
+    pub fn paste_viewed(&self, paste: &PasteReturn) {
+        for hook in &self.hooks {
+            hook.on_paste_viewed(paste);
+        }
+    }
+
+    pub fn paste_updated(&self, previous_url: &str, url: &str) {
+        for hook in &self.hooks {
+            hook.on_paste_updated(previous_url, url);
+        }
+    }
+
+    pub fn paste_deleted(&self, url: &str) {
+        for hook in &self.hooks {
+            hook.on_paste_deleted(url);
+        }
+    }
+
+    pub fn before_render(&self, paste: &mut PasteReturn) {
+        for hook in &self.hooks {
+            hook.before_render(paste);
+        }
+    }
+
+    pub fn after_render(&self, paste: &PasteReturn, html: &mut String) {
+        for hook in &self.hooks {
+            hook.after_render(paste, html);
+        }
+    }
+}
@@ -0,0 +1,63 @@
+//! `ipfs` publishes pastes to IPFS through the HTTP API of a node configured in `[ipfs]`, for authors who want a copy that outlives the instance
+//! The raw content is added and pinned on the node, and the returned CID is stored with the paste. Later edits don't change what was published
+
+use std::time::Duration;
+
+use reqwest::multipart::{Form, Part};
+use serde::Deserialize;
+
+use crate::config::IpfsConfig;
+
+/// How long adding a paste to the node may take
+const ADD_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Clone)]
+pub struct Ipfs {
+    api:     String,
+    gateway: Option<String>,
+    client:  reqwest::Client,
+}
+
+/// The part of the node's response to `/api/v0/add` that is used
+#[derive(Deserialize)]
+struct AddResponse {
+    #[serde(rename = "Hash")]
+    hash: String,
+}
+
+impl Ipfs {
+    pub fn new(config: &IpfsConfig) -> Self {
+        Self {
+            api:     config.api.trim_end_matches('/').to_string(),
+            gateway: config
+                .gateway
+                .as_ref()
+                .map(|gateway| gateway.trim_end_matches('/').to_string()),
+            client:  reqwest::Client::new(),
+        }
+    }
+
+    /// Adds the content to the node and pins it, returning its CID
+    pub async fn add(&self, content: String) -> Result<String, reqwest::Error> {
+        let form = Form::new().part("file", Part::text(content).file_name("paste"));
+        let response: AddResponse = self
+            .client
+            .post(format!("{}/api/v0/add", self.api))
+            .query(&[("pin", "true"), ("cid-version", "1")])
+            .multipart(form)
+            .timeout(ADD_TIMEOUT)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(response.hash)
+    }
+
+    /// Where the content with the CID can be viewed, if a gateway is configured
+    pub fn gateway_url(&self, cid: &str) -> Option<String> {
+        self.gateway
+            .as_ref()
+            .map(|gateway| format!("{gateway}/ipfs/{cid}"))
+    }
+}
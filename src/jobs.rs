@@ -0,0 +1,213 @@
+//! `jobs` runs deferred work from a queue in the database, so scheduled work survives restarts
+//! Jobs are claimed by a background worker once they are due, which runs up to `[jobs] concurrency` of them at once
+//! Failed jobs are retried with exponential backoff, and kept as failed for the admins to look at once they failed too often, unless they are retried until they succeed
+
+use std::{sync::Arc, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use tokio::sync::{Notify, Semaphore};
+
+use crate::{
+    database::{self, DatabaseError, JobEntry},
+    model::Manager,
+    utility,
+};
+
+/// How many times a job is run before it is marked as failed
+const MAX_ATTEMPTS: i64 = 5;
+
+/// How long to wait before the first retry, in seconds. The delay doubles after every failed run
+const RETRY_DELAY: i64 = 30;
+
+/// The longest delay between two runs, in seconds, which only jobs that are retried until they succeed get to
+const MAX_RETRY_DELAY: i64 = 60 * 60;
+
+/// The longest the worker waits before checking the queue again, when no jobs were queued or finished in the meantime
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Work that is done in the background, stored as JSON tagged with its `kind`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Job {
+    /// Deletes an expiring paste, by its internal ID so renaming it doesn't save it
+    DeletePaste { id: i64 },
+    /// Renders the pastes that weren't rendered by this build yet, so their views don't have to, see `Manager::prerender_pastes`
+    RenderPastes,
+    /// Pushes a paste's current state to the `[mirror]`, by its stored URL, see the `mirror` module
+    MirrorPaste { url: String },
+}
+
+impl Job {
+    async fn run(self, manager: &Manager) -> Result<(), String> {
+        match self {
+            Job::DeletePaste { id } => manager.expire_paste(id).await.map_err(|e| e.to_string()),
+            Job::RenderPastes => manager.prerender_pastes().await.map_err(|e| e.to_string()),
+            Job::MirrorPaste { url } => manager.push_to_mirror(&url).await,
+        }
+    }
+
+    /// Whether the job is retried until it succeeds instead of failing for good after `MAX_ATTEMPTS`
+    /// Giving up on a push would leave the mirror's copy behind until the paste changes again
+    fn retries_until_success(&self) -> bool {
+        matches!(self, Job::MirrorPaste { .. })
+    }
+}
+
+#[derive(Clone)]
+pub struct JobQueue {
+    pool: SqlitePool,
+    /// Wakes the worker when jobs were queued or finished
+    wake: Arc<Notify>,
+}
+
+impl JobQueue {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self {
+            pool,
+            wake: Arc::default(),
+        }
+    }
+
+    /// Queues a job to run at the given unix timestamp, or as soon as possible if it has passed
+    pub async fn enqueue(&self, job: &Job, run_at: i64) -> Result<(), DatabaseError> {
+        // Jobs are plain enums, serializing them can't fail
+        let payload = serde_json::to_string(job).unwrap();
+        database::insert_job(&self.pool, &payload, run_at).await?;
+        self.wake.notify_one();
+        Ok(())
+    }
+
+    /// When a queued job is due, if it hasn't started yet
+    pub async fn due_at(&self, job: &Job) -> Result<Option<i64>, DatabaseError> {
+        let payload = serde_json::to_string(job).unwrap();
+        database::retrieve_pending_job_run_at(&self.pool, &payload).await
+    }
+
+    /// Moves a queued job that hasn't started yet to another time, returning whether there was one
+    pub async fn reschedule(&self, job: &Job, run_at: i64) -> Result<bool, DatabaseError> {
+        let payload = serde_json::to_string(job).unwrap();
+        let rescheduled = database::reschedule_pending_job(&self.pool, &payload, run_at).await?;
+        self.wake.notify_one();
+        Ok(rescheduled)
+    }
+
+    /// Queues a job unless the same job is queued and hasn't started yet, which is moved to `run_at` instead
+    /// A job that already started is queued again, as it may have started before whatever it is queued for
+    pub async fn enqueue_once(&self, job: &Job, run_at: i64) -> Result<(), DatabaseError> {
+        match self.reschedule(job, run_at).await? {
+            true => Ok(()),
+            false => self.enqueue(job, run_at).await,
+        }
+    }
+
+    /// How many jobs of a kind are queued or running, by their serialized `kind`
+    pub async fn count_unfinished(&self, kind: &str) -> Result<i64, DatabaseError> {
+        database::count_unfinished_jobs(&self.pool, kind).await
+    }
+
+    /// Every job that is pending, running or has failed
+    pub async fn list(&self) -> Result<Vec<JobEntry>, DatabaseError> {
+        database::retrieve_jobs(&self.pool).await
+    }
+
+    /// Runs due jobs in the background until the process is stopped, at most `concurrency` at once
+    /// Jobs that were running when pasties last stopped are run again
+    pub fn start(&self, manager: Manager, concurrency: usize) {
+        let queue = self.clone();
+        tokio::spawn(async move {
+            if let Err(e) = database::requeue_running_jobs(&queue.pool).await {
+                tracing::error!("Failed to requeue interrupted jobs: {e:?}");
+            }
+            let slots = Arc::new(Semaphore::new(concurrency.max(1)));
+            loop {
+                let free = slots.available_permits() as i64;
+                let claimed = match free {
+                    0 => Vec::new(),
+                    _ => {
+                        let now = utility::unix_timestamp();
+                        match database::claim_due_jobs(&queue.pool, now, free).await {
+                            Ok(claimed) => claimed,
+                            Err(e) => {
+                                tracing::error!("Failed to read the job queue: {e:?}");
+                                Vec::new()
+                            }
+                        }
+                    }
+                };
+                if claimed.is_empty() {
+                    let _ =
+                        tokio::time::timeout(queue.idle_time(free).await, queue.wake.notified())
+                            .await;
+                    continue;
+                }
+                for entry in claimed {
+                    // There were as many free slots as jobs were claimed
+                    let Ok(slot) = slots.clone().acquire_owned().await else {
+                        return;
+                    };
+                    let queue = queue.clone();
+                    let manager = manager.clone();
+                    tokio::spawn(async move {
+                        queue.execute(entry, &manager).await;
+                        drop(slot);
+                        queue.wake.notify_one();
+                    });
+                }
+            }
+        });
+    }
+
+    /// How long the worker can wait before the next pending job is due
+    async fn idle_time(&self, free: i64) -> Duration {
+        if free == 0 {
+            return POLL_INTERVAL;
+        }
+        match database::retrieve_next_job_due(&self.pool).await {
+            Ok(Some(run_at)) => {
+                let seconds =
+                    (run_at - utility::unix_timestamp()).clamp(1, POLL_INTERVAL.as_secs() as i64);
+                Duration::from_secs(seconds as u64)
+            }
+            _ => POLL_INTERVAL,
+        }
+    }
+
+    /// Runs a claimed job, removing it from the queue once it succeeded
+    async fn execute(&self, entry: JobEntry, manager: &Manager) {
+        let (result, retries_until_success) = match serde_json::from_str::<Job>(&entry.payload) {
+            Ok(job) => {
+                let retries_until_success = job.retries_until_success();
+                (job.run(manager).await, retries_until_success)
+            }
+            Err(e) => (Err(format!("Unknown job: {e}")), false),
+        };
+        let attempts = entry.attempts + 1;
+        let outcome = match result {
+            Ok(()) => database::delete_job(&self.pool, entry.id).await,
+            Err(e) if attempts >= MAX_ATTEMPTS && !retries_until_success => {
+                tracing::warn!(
+                    "Job {} failed for good after {attempts} runs: {e}",
+                    entry.id
+                );
+                database::record_job_failure(&self.pool, entry.id, attempts, &e, None).await
+            }
+            Err(e) => {
+                let delay = (RETRY_DELAY << (attempts - 1).min(16)).min(MAX_RETRY_DELAY);
+                match attempts >= MAX_ATTEMPTS {
+                    true => tracing::warn!(
+                        "Job {} failed {attempts} times, retrying in {delay}s: {e}",
+                        entry.id
+                    ),
+                    false => tracing::debug!("Job {} failed, retrying in {delay}s: {e}", entry.id),
+                }
+                let retry_at = utility::unix_timestamp() + delay;
+                database::record_job_failure(&self.pool, entry.id, attempts, &e, Some(retry_at))
+                    .await
+            }
+        };
+        if let Err(e) = outcome {
+            tracing::error!("Failed to update the job queue: {e:?}");
+        }
+    }
+}
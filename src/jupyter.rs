@@ -0,0 +1,140 @@
+//! `jupyter` renders Jupyter notebooks (`.ipynb` JSON) into static HTML, showing each cell with its text outputs
+//! Rich outputs like images and widgets are left out, as they would need a notebook frontend to be useful
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::{markdown, render::Renderer, utility};
+
+pub struct JupyterRenderer;
+
+#[derive(Deserialize)]
+struct Notebook {
+    #[serde(default)]
+    cells:    Vec<Cell>,
+    #[serde(default)]
+    metadata: Value,
+}
+
+#[derive(Deserialize)]
+struct Cell {
+    cell_type:       String,
+    #[serde(default)]
+    source:          Value,
+    #[serde(default)]
+    outputs:         Vec<Output>,
+    #[serde(default)]
+    execution_count: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct Output {
+    output_type: String,
+    #[serde(default)]
+    text:        Value,
+    #[serde(default)]
+    data:        Value,
+    #[serde(default)]
+    ename:       String,
+    #[serde(default)]
+    evalue:      String,
+    #[serde(default)]
+    traceback:   Vec<String>,
+}
+
+impl Renderer for JupyterRenderer {
+    fn render(&self, content: &str) -> String {
+        let notebook: Notebook = match serde_json::from_str(content) {
+            Ok(notebook) => notebook,
+            // Content that isn't a notebook is still worth showing as-is
+            Err(_) => return format!("<pre>{}</pre>", utility::escape_html(content)),
+        };
+        let language = notebook.metadata["language_info"]["name"]
+            .as_str()
+            .or(notebook.metadata["kernelspec"]["language"].as_str())
+            .unwrap_or_default();
+
+        let mut html = String::from("<div class=\"notebook\">");
+        for cell in &notebook.cells {
+            let source = multiline_text(&cell.source);
+            match cell.cell_type.as_str() {
+                "markdown" => {
+                    html.push_str("<div class=\"cell markdown-cell\">");
+                    html.push_str(&markdown::render_markdown(&source));
+                    html.push_str("</div>");
+                }
+                "code" => {
+                    let prompt = match cell.execution_count {
+                        Some(count) => format!("In [{count}]:"),
+                        None => "In [ ]:".to_string(),
+                    };
+                    html.push_str(&format!(
+                        "<div class=\"cell code-cell\"><div class=\"prompt\">{prompt}</div><pre><code \
+                         class=\"language-{}\">{}</code></pre>",
+                        utility::escape_html(language),
+                        utility::escape_html(&source)
+                    ));
+                    for output in &cell.outputs {
+                        html.push_str(&render_output(output));
+                    }
+                    html.push_str("</div>");
+                }
+                _ => html.push_str(&format!(
+                    "<div class=\"cell raw-cell\"><pre>{}</pre></div>",
+                    utility::escape_html(&source)
+                )),
+            }
+        }
+        html.push_str("</div>");
+        html
+    }
+}
+
+/// Renders the plain text representation of an output, if it has one
+fn render_output(output: &Output) -> String {
+    let text = match output.output_type.as_str() {
+        "stream" => multiline_text(&output.text),
+        "execute_result" | "display_data" => multiline_text(&output.data["text/plain"]),
+        "error" => match output.traceback.is_empty() {
+            true => format!("{}: {}", output.ename, output.evalue),
+            false => strip_ansi(&output.traceback.join("\n")),
+        },
+        _ => String::new(),
+    };
+    match text.is_empty() {
+        true => String::new(),
+        false => format!(
+            "<pre class=\"output output-{}\">{}</pre>",
+            utility::escape_html(&output.output_type),
+            utility::escape_html(&text)
+        ),
+    }
+}
+
+/// Notebooks store text either as a single string or as a list of lines
+fn multiline_text(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        Value::Array(lines) => lines.iter().filter_map(Value::as_str).collect(),
+        _ => String::new(),
+    }
+}
+
+/// Removes the terminal color codes tracebacks are stored with
+fn strip_ansi(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\u{1b}' {
+            // Escape sequences end with a letter, e.g. `\x1b[0;31m`
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            output.push(c);
+        }
+    }
+    output
+}
@@ -0,0 +1,254 @@
+//! `links` checks the links in rendered pastes, so links to known-malicious sites can be flagged or removed before a page is served
+//! Links are checked against the `[link_safety]` blocklist and, if a key is configured, the Google Safe Browsing lookup API
+//! External links are also marked as `nofollow`, and can be routed through the `/out` interstitial
+
+use std::{collections::HashSet, time::Duration};
+
+use lol_html::{element, html_content::Element, rewrite_str, RewriteStrSettings};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use url::Url;
+
+use crate::config::LinkSafetyConfig;
+
+/// `rel` values every external link carries, so links in pastes don't pass on ranking or a handle to the page
+const EXTERNAL_REL: [&str; 2] = ["nofollow", "noopener"];
+
+const SAFE_BROWSING_ENDPOINT: &str = "https://safebrowsing.googleapis.com/v4/threatMatches:find";
+
+/// The most URLs Safe Browsing accepts in a single lookup
+const SAFE_BROWSING_BATCH_SIZE: usize = 500;
+
+/// How long a Safe Browsing lookup may take before the links are served unchecked
+const SAFE_BROWSING_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// What happens to links found to be unsafe
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkAction {
+    /// The link is kept, but marked with a warning
+    #[default]
+    Flag,
+    /// The link's target is removed, only its text is kept
+    Remove,
+}
+
+#[derive(Clone)]
+pub struct LinkChecker {
+    /// Hosts whose links are unsafe, including their subdomains
+    blocklist:         Vec<String>,
+    safe_browsing_key: Option<String>,
+    action:            LinkAction,
+    client:            reqwest::Client,
+}
+
+#[derive(Deserialize, Default)]
+struct SafeBrowsingResponse {
+    #[serde(default)]
+    matches: Vec<SafeBrowsingMatch>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct SafeBrowsingMatch {
+    threat: SafeBrowsingEntry,
+}
+
+#[derive(Deserialize, Serialize)]
+struct SafeBrowsingEntry {
+    url: String,
+}
+
+/// The targets of every `http` and `https` link in a piece of HTML, without duplicates
+pub fn link_targets(html: &str) -> Vec<String> {
+    let mut targets = Vec::new();
+    let _ = rewrite_str(
+        html,
+        RewriteStrSettings::new().append_element_content_handler(element!(
+            "a[href]",
+            |el: &mut Element| {
+                if let Some(href) = href(el) {
+                    if is_web_url(&href) && !targets.contains(&href) {
+                        targets.push(href);
+                    }
+                }
+                Ok(())
+            }
+        )),
+    );
+    targets
+}
+
+/// Marks external links with `rel="nofollow noopener"`, keeping any other `rel` values
+/// With `interstitial`, their targets are replaced with `/out?url=<target>`, so readers see where a link goes before following it
+pub fn rewrite_external(html: String, interstitial: bool) -> String {
+    let rewritten = rewrite_str(
+        &html,
+        RewriteStrSettings::new().append_element_content_handler(element!(
+            "a[href]",
+            |el: &mut Element| {
+                let Some(href) = href(el).filter(|href| is_web_url(href)) else {
+                    return Ok(());
+                };
+                let mut rel: Vec<String> = el
+                    .get_attribute("rel")
+                    .unwrap_or_default()
+                    .split_whitespace()
+                    .map(str::to_string)
+                    .collect();
+                for value in EXTERNAL_REL {
+                    if !rel
+                        .iter()
+                        .any(|existing| existing.eq_ignore_ascii_case(value))
+                    {
+                        rel.push(value.to_string());
+                    }
+                }
+                el.set_attribute("rel", &rel.join(" "))?;
+                if interstitial {
+                    let target = utf8_percent_encode(&href, NON_ALPHANUMERIC);
+                    el.set_attribute("href", &format!("/out?url={target}"))?;
+                }
+                Ok(())
+            }
+        )),
+    );
+    // The rewrite only fails on invalid selectors or memory limits, neither of which depends on the paste
+    rewritten.unwrap_or(html)
+}
+
+/// A link's target, with character references decoded
+fn href(el: &Element) -> Option<String> {
+    decoded_attribute(el, "href")
+}
+
+/// An attribute's value, with the character references renderers escape in attributes decoded
+/// `lol_html` hands attributes over exactly as written, so `?a=1&amp;b=2` would otherwise be checked and forwarded as is
+pub fn decoded_attribute(el: &Element, name: &str) -> Option<String> {
+    el.get_attribute(name).map(|value| {
+        value
+            .replace("&quot;", "\"")
+            .replace("&#39;", "'")
+            .replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&amp;", "&")
+    })
+}
+
+/// Whether a link target can be followed through the `/out` interstitial
+pub fn is_web_url(href: &str) -> bool {
+    Url::parse(href).is_ok_and(|url| matches!(url.scheme(), "http" | "https"))
+}
+
+impl LinkChecker {
+    pub fn new(config: &LinkSafetyConfig) -> Self {
+        Self {
+            blocklist:         config
+                .blocklist
+                .iter()
+                .map(|host| host.trim_start_matches("*.").to_ascii_lowercase())
+                .collect(),
+            safe_browsing_key: config.safe_browsing_key.clone(),
+            action:            config.action,
+            client:            reqwest::Client::new(),
+        }
+    }
+
+    fn is_blocklisted(&self, target: &str) -> bool {
+        let Some(host) = Url::parse(target)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_ascii_lowercase))
+        else {
+            return false;
+        };
+        self.blocklist.iter().any(|blocked| {
+            host == *blocked
+                || host
+                    .strip_suffix(blocked.as_str())
+                    .is_some_and(|subdomain| subdomain.ends_with('.'))
+        })
+    }
+
+    /// Returns the targets that are blocklisted or known to Safe Browsing
+    /// If Safe Browsing can't be reached, only the blocklist is applied, as pastes shouldn't become unreadable when it is down
+    pub async fn unsafe_links(&self, targets: &[String]) -> HashSet<String> {
+        let mut found: HashSet<String> = targets
+            .iter()
+            .filter(|target| self.is_blocklisted(target))
+            .cloned()
+            .collect();
+        if let Some(key) = &self.safe_browsing_key {
+            for batch in targets.chunks(SAFE_BROWSING_BATCH_SIZE) {
+                match self.look_up(key, batch).await {
+                    Ok(matches) => found.extend(matches),
+                    Err(e) => tracing::warn!("Failed to look up links with Safe Browsing: {e}"),
+                }
+            }
+        }
+        found
+    }
+
+    async fn look_up(&self, key: &str, targets: &[String]) -> Result<Vec<String>, reqwest::Error> {
+        let entries: Vec<SafeBrowsingEntry> = targets
+            .iter()
+            .map(|url| SafeBrowsingEntry { url: url.clone() })
+            .collect();
+        let body = json!({
+            "client": { "clientId": "pasties", "clientVersion": env!("CARGO_PKG_VERSION") },
+            "threatInfo": {
+                "threatTypes": ["MALWARE", "SOCIAL_ENGINEERING", "UNWANTED_SOFTWARE", "POTENTIALLY_HARMFUL_APPLICATION"],
+                "platformTypes": ["ANY_PLATFORM"],
+                "threatEntryTypes": ["URL"],
+                "threatEntries": entries,
+            },
+        });
+        let response: SafeBrowsingResponse = self
+            .client
+            .post(SAFE_BROWSING_ENDPOINT)
+            .query(&[("key", key)])
+            .timeout(SAFE_BROWSING_TIMEOUT)
+            .json(&body)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(response
+            .matches
+            .into_iter()
+            .map(|found| found.threat.url)
+            .collect())
+    }
+
+    /// Flags or removes the links to unsafe targets, depending on the configured action
+    pub fn neutralize(&self, html: String, unsafe_links: &HashSet<String>) -> String {
+        if unsafe_links.is_empty() {
+            return html;
+        }
+        let action = self.action;
+        let rewritten = rewrite_str(
+            &html,
+            RewriteStrSettings::new().append_element_content_handler(element!(
+                "a[href]",
+                |el: &mut Element| {
+                    let is_unsafe = href(el).is_some_and(|href| unsafe_links.contains(&href));
+                    if !is_unsafe {
+                        return Ok(());
+                    }
+                    if action == LinkAction::Remove {
+                        el.remove_attribute("href");
+                    }
+                    el.set_attribute("class", "unsafe-link")?;
+                    el.set_attribute("rel", "nofollow noopener noreferrer")?;
+                    el.set_attribute("title", "This link is known to be unsafe")?;
+                    Ok(())
+                }
+            )),
+        );
+        match rewritten {
+            Ok(rewritten) => rewritten,
+            // Serving the links unchanged is not an option, so they are all dropped
+            Err(_) => "<p>This paste could not be displayed safely.</p>".to_string(),
+        }
+    }
+}
@@ -0,0 +1,133 @@
+//! `logging` sets up the application logs, which are written to stdout, syslog or journald depending on the configuration
+
+use std::{
+    io::{self, Write},
+    os::unix::net::UnixDatagram,
+    sync::Arc,
+};
+
+use serde::Deserialize;
+use tracing::{Level, Metadata};
+use tracing_subscriber::{
+    filter::LevelFilter, fmt::MakeWriter, layer::SubscriberExt, util::SubscriberInitExt, Layer,
+};
+
+use crate::config::Config;
+
+/// The socket local syslog daemons listen on
+const SYSLOG_SOCKET: &str = "/dev/log";
+/// The syslog facility pasties logs as, `daemon`
+const SYSLOG_FACILITY: u8 = 3;
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogOutput {
+    #[default]
+    Stdout,
+    Syslog,
+    Journald,
+}
+
+/// Installs the global `tracing` subscriber for the configured output
+/// Falls back to stdout if the syslog socket or journald cannot be reached, so that log lines are never silently dropped
+pub fn init(config: &Config) {
+    let stdout = || tracing_subscriber::fmt::layer().boxed();
+    let (layer, fallback_error) = match config.logging.output {
+        LogOutput::Stdout => (stdout(), None),
+        LogOutput::Syslog => match SyslogWriter::connect() {
+            Ok(writer) => (
+                tracing_subscriber::fmt::layer()
+                    .with_writer(writer)
+                    .with_ansi(false)
+                    .without_time()
+                    .with_level(false)
+                    .boxed(),
+                None,
+            ),
+            Err(e) => (stdout(), Some(format!("syslog at {SYSLOG_SOCKET}: {e}"))),
+        },
+        LogOutput::Journald => match tracing_journald::layer() {
+            Ok(layer) => (layer.boxed(), None),
+            Err(e) => (stdout(), Some(format!("journald: {e}"))),
+        },
+    };
+    tracing_subscriber::registry()
+        .with(layer.with_filter(LevelFilter::INFO))
+        .init();
+    if let Some(e) = fallback_error {
+        tracing::warn!("Logging to stdout, as connecting to {e}");
+    }
+}
+
+/// Sends every log line as its own datagram to the local syslog daemon, with the priority mapped from the line's level
+#[derive(Clone)]
+struct SyslogWriter {
+    socket: Arc<UnixDatagram>,
+}
+
+impl SyslogWriter {
+    fn connect() -> io::Result<Self> {
+        let socket = UnixDatagram::unbound()?;
+        socket.connect(SYSLOG_SOCKET)?;
+        Ok(Self {
+            socket: Arc::new(socket),
+        })
+    }
+
+    fn line(&self, severity: u8) -> SyslogLine {
+        SyslogLine {
+            socket: self.socket.clone(),
+            header: format!(
+                "<{}>pasties[{}]: ",
+                SYSLOG_FACILITY * 8 + severity,
+                std::process::id()
+            ),
+            buffer: Vec::new(),
+        }
+    }
+}
+
+impl<'a> MakeWriter<'a> for SyslogWriter {
+    type Writer = SyslogLine;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.line(6)
+    }
+
+    fn make_writer_for(&'a self, meta: &Metadata<'_>) -> Self::Writer {
+        let severity = match *meta.level() {
+            Level::ERROR => 3,
+            Level::WARN => 4,
+            Level::INFO => 6,
+            Level::DEBUG | Level::TRACE => 7,
+        };
+        self.line(severity)
+    }
+}
+
+/// Buffers a single formatted log line, which is sent once it is dropped
+struct SyslogLine {
+    socket: Arc<UnixDatagram>,
+    header: String,
+    buffer: Vec<u8>,
+}
+
+impl Write for SyslogLine {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for SyslogLine {
+    fn drop(&mut self) {
+        let message = String::from_utf8_lossy(&self.buffer);
+        let message = format!("{}{}", self.header, message.trim_end());
+        // There is nowhere left to report a failure to log to
+        let _ = self.socket.send(message.as_bytes());
+    }
+}
@@ -1,32 +1,228 @@
-use axum::Router;
+use std::process;
+
+use axum::{
+    http::{header, HeaderValue},
+    middleware,
+    response::Response,
+    Router,
+};
 
 use crate::{
+    access_log::AccessLog,
+    cli::{Args, Command},
+    config::Config,
     model::Manager,
-    routing::{api, pages},
+    rate_limit::RateLimiter,
+    reporting::Reporter,
+    routing::{
+        api, dav, hastebin,
+        pages::{self, Assets},
+        pastebin, sprunge,
+    },
+    systemd::InheritedSocket,
 };
 
+pub mod access_log;
+pub mod acme;
+#[cfg(feature = "asciidoc")]
+pub mod asciidoc;
+pub mod banner;
+pub mod cli;
+pub mod config;
+pub mod csp;
 pub mod database;
+pub mod detect;
+pub mod doctor;
+pub mod events;
+pub mod excerpt;
+pub mod filters;
+pub mod gemini;
+pub mod hooks;
+pub mod ipfs;
+pub mod jobs;
+pub mod jupyter;
+pub mod links;
+pub mod logging;
 pub mod markdown;
+pub mod migrate;
+pub mod minify;
+pub mod mirror;
 pub mod model;
+pub mod negotiate;
+pub mod org;
+pub mod private;
+pub mod proxy;
+pub mod rate_limit;
+pub mod read_only;
+pub mod referrers;
+pub mod render;
+pub mod reporting;
+pub mod request_id;
 pub mod routing;
+pub mod secrets;
+pub mod server;
+pub mod slow_queries;
+pub mod sso;
+pub mod structured;
+pub mod style;
+pub mod systemd;
+pub mod table;
+pub mod termbin;
 pub mod utility;
+pub mod validation;
+pub mod webhooks;
+
+fn main() {
+    // Before the runtime starts its threads, as the environment can't be changed safely while other threads may read it
+    let inherited = systemd::listen_fds();
+    run(inherited);
+}
 
 #[tokio::main]
-async fn main() {
-    const PORT: u16 = 7878;
-
-    let manager = Manager::init().await;
-
-    let app = Router::new()
-        .merge(pages::routes(manager.clone()))
-        .nest("/api", api::routes(manager.clone()))
-        .nest("/meta", pages::reserved_routes())
-        .nest("/assets", pages::asset_routes())
-        .fallback(pages::not_found_handler);
-    let listener = tokio::net::TcpListener::bind(format!("127.0.0.1:{PORT}"))
-        .await
-        .unwrap();
-
-    println!("Starting server at http://localhost:{PORT}!");
-    axum::serve(listener, app).await.unwrap();
+async fn run(inherited: Vec<InheritedSocket>) {
+    let args = Args::parse();
+    // The doctor reports configuration errors instead of panicking on them
+    if let Some(Command::Doctor) = &args.command {
+        doctor::run().await;
+        return;
+    }
+    let config = Config::load();
+    logging::init(&config);
+    slow_queries::init(config.logging.slow_query_ms);
+    if let Some(Command::Backup(path)) = &args.command {
+        let pool = database::init_database().await;
+        match database::backup(&pool, path).await {
+            Ok(()) => println!("Backed up the database to {path}"),
+            Err(e) => panic!("Failed to back up the database:\n    {e:?}"),
+        }
+        return;
+    }
+    if let Some(Command::Migrate { from, path }) = &args.command {
+        migrate::run(&config, *from, path).await;
+        return;
+    }
+    let report = doctor::check(&config).await;
+    for warning in &report.warnings {
+        tracing::warn!("{warning}");
+    }
+    if !report.errors.is_empty() {
+        for error in &report.errors {
+            tracing::error!("{error}");
+        }
+        tracing::error!("Not starting because of the errors above, `pasties doctor` lists them without starting the server");
+        process::exit(1);
+    }
+
+    let reporter = Reporter::new(&config);
+    let manager = Manager::init(&config).await;
+    manager
+        .jobs()
+        .start(manager.clone(), config.jobs.concurrency);
+    manager.schedule_prerendering().await;
+    let assets = Assets::new(args.dev);
+
+    // Namespaces share the limits of the rest of the instance
+    let limiter = RateLimiter::from_config(&config);
+    let rate_limited = |routes: Router| match &limiter {
+        Some(limiter) => routes.layer(middleware::from_fn_with_state(
+            limiter.clone(),
+            rate_limit::limit_requests,
+        )),
+        None => routes,
+    };
+    let dav_routes = |manager: Manager| match config.serves_webdav() {
+        true => dav::routes(manager),
+        false => Router::new(),
+    };
+    let api_routes = rate_limited(
+        Router::new()
+            .merge(hastebin::routes(manager.clone()))
+            .merge(pastebin::routes(manager.clone(), config.clone()))
+            .merge(sprunge::routes(manager.clone(), config.clone()))
+            .merge(dav_routes(manager.clone()))
+            .nest("/api", api::routes(manager.clone())),
+    );
+
+    let mut app = Router::new()
+        .merge(pages::routes(manager.clone(), assets.clone()))
+        .merge(api_routes)
+        .merge(mirror::routes(manager.clone()))
+        .nest("/meta", pages::meta_routes(manager.clone()))
+        .nest("/assets", pages::asset_routes(assets.clone()))
+        .nest("/auth", sso::routes(manager.clone()));
+    for namespace in &config.namespaces {
+        let manager = manager.in_namespace(namespace);
+        let routes = pages::routes(manager.clone(), assets.clone()).merge(rate_limited(
+            Router::new()
+                .nest("/api", api::routes(manager.clone()))
+                .merge(dav_routes(manager)),
+        ));
+        app = app.nest(&format!("/{}", namespace.name), routes);
+    }
+    let mut app = app
+        .fallback(pages::not_found_handler)
+        .layer(middleware::from_fn_with_state(
+            reporter,
+            reporting::report_errors,
+        ))
+        .layer(middleware::from_fn_with_state(
+            manager.clone(),
+            banner::provide_banner,
+        ))
+        .layer(middleware::from_fn(csp::apply_policy));
+    if args.dev {
+        tracing::info!(
+            "Running in development mode, assets are reloaded on every request. Templates are compiled in, so changes to them need a rebuild"
+        );
+        app = app.layer(middleware::map_response(|mut response: Response| async {
+            response
+                .headers_mut()
+                .insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+            response
+        }));
+    }
+    if config.sso.is_some() {
+        app = app
+            .layer(middleware::from_fn_with_state(
+                manager.clone(),
+                sso::provide_user,
+            ))
+            .layer(middleware::from_fn_with_state(
+                manager.clone(),
+                sso::require_session,
+            ));
+    }
+    if config.read_only {
+        app = app.layer(middleware::from_fn_with_state(
+            manager.clone(),
+            read_only::require_write_access,
+        ));
+    }
+    if config.minify_html {
+        app = app.layer(middleware::map_response(minify::minify_responses));
+    }
+    // Inside the access log, so requests the gate turns away are logged too
+    if config.private.is_some() {
+        app = app.layer(middleware::from_fn_with_state(
+            manager.clone(),
+            private::require_access,
+        ));
+    }
+    if let Some(access_log) = AccessLog::from_config(&config) {
+        app = app.layer(middleware::from_fn_with_state(
+            access_log,
+            access_log::log_access,
+        ));
+    }
+    let app = app.layer(middleware::from_fn(request_id::propagate_request_id));
+    if let Some(gemini) = &config.gemini {
+        let (listener, acceptor) = gemini::bind(gemini).await;
+        tokio::spawn(gemini::serve(listener, acceptor, manager.clone()));
+    }
+    if let Some(termbin) = &config.termbin {
+        let listener = termbin::bind(termbin).await;
+        tokio::spawn(termbin::serve(listener, manager, config.base_url(None)));
+    }
+    let listeners = server::bind(&config, inherited).await;
+    server::serve(listeners, app, &config.server).await;
 }
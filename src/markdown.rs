@@ -1,8 +1,93 @@
-use pulldown_cmark::{html, Parser};
+//! `markdown` renders markdown pastes, including `[[url]]` wiki links to other pastes on the same instance
+use pulldown_cmark::{html, CowStr, Event, Parser, TextMergeStream};
 
-pub fn render_markdown(markdown: String) -> String {
-    let parser = Parser::new(&markdown);
+use crate::{render::Renderer, utility};
+
+/// A piece of text, either plain or a wiki link's target
+enum Piece<'a> {
+    Text(&'a str),
+    WikiLink(&'a str),
+}
+
+/// Splits text around `[[url]]` wiki links. Brackets around something that can't be a paste URL are left as text
+fn split_wiki_links(text: &str) -> Vec<Piece<'_>> {
+    let mut pieces = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("[[") {
+        let Some(length) = rest[start + 2..].find("]]") else {
+            break;
+        };
+        let target = &rest[start + 2..start + 2 + length];
+        if target.is_empty() || !utility::is_url_safe(target) {
+            pieces.push(Piece::Text(&rest[..start + 2]));
+            rest = &rest[start + 2..];
+            continue;
+        }
+        pieces.push(Piece::Text(&rest[..start]));
+        pieces.push(Piece::WikiLink(target));
+        rest = &rest[start + 4 + length..];
+    }
+    pieces.push(Piece::Text(rest));
+    pieces
+}
+
+/// The URLs of every paste linked to with `[[url]]`, without duplicates. Links in code are not counted
+pub fn wiki_links(markdown: &str) -> Vec<String> {
+    let mut links: Vec<String> = Vec::new();
+    for event in TextMergeStream::new(Parser::new(markdown)) {
+        if let Event::Text(text) = event {
+            for piece in split_wiki_links(&text) {
+                if let Piece::WikiLink(url) = piece {
+                    if !links.iter().any(|link| link == url) {
+                        links.push(url.to_string());
+                    }
+                }
+            }
+        }
+    }
+    links
+}
+
+pub fn render_markdown(markdown: &str) -> String {
+    render_markdown_with_links(markdown, "", |_| true)
+}
+
+/// Renders markdown, turning `[[url]]` into links to other pastes under `base_path`. Links to pastes for which `exists` returns false are marked as missing
+pub fn render_markdown_with_links(
+    markdown: &str,
+    base_path: &str,
+    exists: impl Fn(&str) -> bool,
+) -> String {
+    let parser = TextMergeStream::new(Parser::new(markdown)).flat_map(|event| match event {
+        Event::Text(text) if text.contains("[[") => split_wiki_links(&text)
+            .into_iter()
+            .map(|piece| match piece {
+                Piece::Text(text) => Event::Text(CowStr::from(text.to_string())),
+                Piece::WikiLink(url) => {
+                    Event::InlineHtml(CowStr::from(wiki_link(base_path, url, exists(url))))
+                }
+            })
+            .collect(),
+        event => vec![event],
+    });
     let mut html_buf = String::new();
     html::push_html(&mut html_buf, parser);
     html_buf
 }
+
+fn wiki_link(base_path: &str, url: &str, exists: bool) -> String {
+    match exists {
+        true => format!("<a class=\"wiki-link\" href=\"{base_path}/{url}\">{url}</a>"),
+        false => format!(
+            "<a class=\"wiki-link missing\" href=\"{base_path}/{url}\" title=\"This paste does not exist\">{url}</a>"
+        ),
+    }
+}
+
+pub struct MarkdownRenderer;
+
+impl Renderer for MarkdownRenderer {
+    fn render(&self, content: &str) -> String {
+        render_markdown(content)
+    }
+}
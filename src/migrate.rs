@@ -0,0 +1,422 @@
+//! `migrate` imports the pastes of other self-hosted pastebins into pasties, for `pasties migrate --from <tool> --path <data>`
+//! Pastes keep their URLs and timestamps where pasties can represent them. Every imported paste gets a new password, printed along with its URL
+
+use std::{fmt, fs, path::Path, process, str::FromStr};
+
+use serde::{de::IgnoredAny, Deserialize};
+use sqlx::{
+    sqlite::{SqliteConnectOptions, SqliteRow},
+    Connection, Row, SqliteConnection,
+};
+use uuid::Uuid;
+
+use crate::{
+    config::Config,
+    detect,
+    model::{Manager, NewPasteData},
+    utility,
+};
+
+/// The pastebins pastes can be imported from
+#[derive(Debug, Clone, Copy)]
+pub enum Source {
+    Microbin,
+    Opengist,
+    Privatebin,
+}
+
+impl FromStr for Source {
+    type Err = String;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        match source.to_ascii_lowercase().as_str() {
+            "microbin" => Ok(Self::Microbin),
+            "opengist" => Ok(Self::Opengist),
+            "privatebin" => Ok(Self::Privatebin),
+            _ => Err(format!(
+                "Unknown pastebin: {source}, pastes can be migrated from microbin, opengist and privatebin"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Microbin => write!(f, "MicroBin"),
+            Self::Opengist => write!(f, "Opengist"),
+            Self::Privatebin => write!(f, "PrivateBin"),
+        }
+    }
+}
+
+/// A paste read from another pastebin, ready to be imported
+struct Import {
+    /// Where the paste was found, for reporting what was skipped
+    origin:         String,
+    paste:          NewPasteData,
+    uuid:           Option<Uuid>,
+    date_published: i64,
+    date_edited:    i64,
+}
+
+/// What was read from another pastebin: the pastes to import, and the ones that can't be, with the reason
+type Pastes = (Vec<Import>, Vec<(String, String)>);
+
+/// Runs `pasties migrate`, printing the URL and new password of every imported paste, separated by a tab
+/// Pastes that can't be imported are listed on stderr, and the process exits with status 1 if the data can't be read at all
+pub async fn run(config: &Config, source: Source, path: &str) {
+    let path = Path::new(path);
+    let read = match source {
+        Source::Microbin => read_microbin(path).await,
+        Source::Opengist => read_opengist(path).await,
+        Source::Privatebin => Err(
+            "PrivateBin encrypts pastes in the browser, and the key is only part of each paste's link, so its data can't be imported. Copy the pastes that are still needed over by hand".to_string(),
+        ),
+    };
+    let (pastes, mut skipped) = match read {
+        Ok(pastes) => pastes,
+        Err(e) => {
+            eprintln!("error: {e}");
+            process::exit(1)
+        }
+    };
+    let manager = Manager::init(config).await;
+    let formats = manager.formats();
+    let mut imported = 0;
+    for mut import in pastes {
+        if !formats.contains(&import.paste.format.as_str()) {
+            import.paste.format = "plain".to_string();
+        }
+        match manager
+            .import_paste(
+                import.paste,
+                import.uuid,
+                import.date_published,
+                import.date_edited,
+            )
+            .await
+        {
+            Ok(created) => {
+                println!("{}\t{}", created.url, created.password);
+                imported += 1;
+            }
+            Err(e) => skipped.push((import.origin, e.public_message())),
+        }
+    }
+    for (origin, reason) in &skipped {
+        eprintln!("skipped {origin}: {reason}");
+    }
+    eprintln!(
+        "Imported {imported} paste(s) from {source}, skipped {}",
+        skipped.len()
+    );
+}
+
+fn new_paste(url: String, content: String, format: &str) -> NewPasteData {
+    NewPasteData {
+        url,
+        content,
+        password: String::new(),
+        format: format.to_string(),
+        draft: false,
+        max_views: None,
+        expires_in: None,
+        font: None,
+        width: None,
+        wrap: None,
+    }
+}
+
+/// The format a file's name stands for, plain text if pasties doesn't know it
+fn format_of(file_name: &str) -> &'static str {
+    detect::format_of_file_name(file_name).unwrap_or("plain")
+}
+
+/// Opens another pastebin's SQLite database without changing it
+async fn open_database(path: &Path) -> Result<SqliteConnection, String> {
+    let options = SqliteConnectOptions::new().filename(path).read_only(true);
+    match SqliteConnection::connect_with(&options).await {
+        Ok(connection) => Ok(connection),
+        Err(e) => Err(format!("Failed to open {}:\n    {e}", path.display())),
+    }
+}
+
+/// A paste as MicroBin stores it, in `database.json` or the `pasta` table of `database.sqlite`
+#[derive(Deserialize)]
+struct Pasta {
+    id:               u64,
+    content:          String,
+    /// Only whether there is an attached file matters
+    #[serde(default)]
+    file:             Option<IgnoredAny>,
+    #[serde(default)]
+    extension:        String,
+    #[serde(default)]
+    encrypt_server:   bool,
+    #[serde(default)]
+    encrypt_client:   bool,
+    created:          i64,
+    /// The unix timestamp the paste expires at, 0 if it doesn't
+    #[serde(default)]
+    expiration:       i64,
+    #[serde(default)]
+    read_count:       u64,
+    /// The paste is deleted after being read this many times, 0 if it isn't
+    #[serde(default)]
+    burn_after_reads: u64,
+    /// `text`, or `url` for shortened links
+    #[serde(default)]
+    pasta_type:       String,
+}
+
+impl Pasta {
+    fn from_row(row: &SqliteRow) -> Result<Self, sqlx::Error> {
+        let file_name: Option<String> = row.try_get("file_name").unwrap_or_default();
+        Ok(Self {
+            id:               row.try_get::<i64, _>("id")? as u64,
+            content:          row.try_get("content")?,
+            file:             file_name.map(|_| IgnoredAny),
+            extension:        row.try_get("extension").unwrap_or_default(),
+            encrypt_server:   row.try_get("encrypt_server").unwrap_or_default(),
+            encrypt_client:   row.try_get("encrypt_client").unwrap_or_default(),
+            created:          row.try_get("created")?,
+            expiration:       row.try_get("expiration").unwrap_or_default(),
+            read_count:       row.try_get::<i64, _>("read_count").unwrap_or_default() as u64,
+            burn_after_reads: row
+                .try_get::<i64, _>("burn_after_reads")
+                .unwrap_or_default() as u64,
+            pasta_type:       row.try_get("pasta_type").unwrap_or_default(),
+        })
+    }
+
+    /// Turns the paste into an import, or returns why it can't be imported
+    fn into_import(self, now: i64) -> Result<Import, String> {
+        if self.pasta_type == "url" {
+            return Err("it is a shortened link, which pasties has no equivalent for".to_string());
+        }
+        if self.encrypt_server || self.encrypt_client {
+            return Err("it is encrypted".to_string());
+        }
+        if self.content.is_empty() && self.file.is_some() {
+            return Err("it only holds an uploaded file".to_string());
+        }
+        if self.expiration != 0 && self.expiration <= now {
+            return Err("it has expired".to_string());
+        }
+        let format = match self.extension.as_str() {
+            "" => "plain",
+            extension => format_of(&format!("pasta.{extension}")),
+        };
+        let mut paste = new_paste(microbin_url(self.id), self.content, format);
+        if self.expiration != 0 {
+            paste.expires_in = Some(self.expiration - now);
+        }
+        if self.burn_after_reads > 0 {
+            match self.burn_after_reads.checked_sub(self.read_count) {
+                Some(remaining) if remaining > 0 => paste.max_views = Some(remaining as i64),
+                _ => return Err("it was read as often as it was allowed to be".to_string()),
+            }
+        }
+        Ok(Import {
+            origin: format!("/upload/{}", paste.url),
+            paste,
+            uuid: None,
+            date_published: self.created,
+            date_edited: self.created,
+        })
+    }
+}
+
+/// The names MicroBin spells the IDs of pastes in, as digits in base 64
+const ANIMAL_NAMES: [&str; 64] = [
+    "ant", "eel", "mole", "sloth", "ape", "emu", "monkey", "snail", "bat", "falcon", "mouse",
+    "snake", "bear", "fish", "otter", "spider", "bee", "fly", "parrot", "squid", "bird", "fox",
+    "panda", "swan", "bison", "frog", "pig", "tiger", "camel", "gecko", "pigeon", "toad", "cat",
+    "goat", "pony", "turkey", "cobra", "goose", "pug", "turtle", "crow", "hawk", "rabbit", "viper",
+    "deer", "horse", "rat", "wasp", "dog", "jaguar", "raven", "whale", "dove", "koala", "seal",
+    "wolf", "duck", "lion", "shark", "worm", "eagle", "lizard", "sheep", "zebra",
+];
+
+/// The URL of a MicroBin paste, like `pig-sloth-bat`, as MicroBin builds it with its default animal names
+fn microbin_url(mut id: u64) -> String {
+    let mut names = Vec::new();
+    loop {
+        names.push(ANIMAL_NAMES[(id % 64) as usize]);
+        id /= 64;
+        if id == 0 {
+            break;
+        }
+    }
+    names.reverse();
+    names.join("-")
+}
+
+/// Reads MicroBin's data directory, or its `database.sqlite` or `database.json` directly
+async fn read_microbin(path: &Path) -> Result<Pastes, String> {
+    let database = match path.is_dir() {
+        true => match path.join("database.sqlite") {
+            sqlite if sqlite.exists() => sqlite,
+            _ => path.join("database.json"),
+        },
+        false => path.to_path_buf(),
+    };
+    let pastas = match database
+        .extension()
+        .is_some_and(|extension| extension == "json")
+    {
+        true => {
+            let json = match fs::read_to_string(&database) {
+                Ok(json) => json,
+                Err(e) => return Err(format!("Failed to read {}:\n    {e}", database.display())),
+            };
+            match serde_json::from_str::<Vec<Pasta>>(&json) {
+                Ok(pastas) => pastas,
+                Err(e) => return Err(format!("Failed to parse {}:\n    {e}", database.display())),
+            }
+        }
+        false => {
+            let mut connection = open_database(&database).await?;
+            let rows = sqlx::query("select * from pasta")
+                .fetch_all(&mut connection)
+                .await;
+            let _ = connection.close().await;
+            let rows = match rows {
+                Ok(rows) => rows,
+                Err(e) => return Err(format!("Failed to read the pastes:\n    {e}")),
+            };
+            let pastas: Result<Vec<Pasta>, sqlx::Error> =
+                rows.iter().map(Pasta::from_row).collect();
+            match pastas {
+                Ok(pastas) => pastas,
+                Err(e) => return Err(format!("Failed to read the pastes:\n    {e}")),
+            }
+        }
+    };
+    let now = utility::unix_timestamp();
+    let mut imports = Vec::new();
+    let mut skipped = Vec::new();
+    for pasta in pastas {
+        let origin = format!("/upload/{}", microbin_url(pasta.id));
+        match pasta.into_import(now) {
+            Ok(import) => imports.push(import),
+            Err(reason) => skipped.push((origin, reason)),
+        }
+    }
+    Ok((imports, skipped))
+}
+
+/// Reads Opengist's data directory, which holds `opengist.db` and the gists' git repositories under `repos/`
+/// Files are read with `git`, which has to be installed. Gists with several files become one markdown paste, with a section for each
+async fn read_opengist(path: &Path) -> Result<Pastes, String> {
+    let (database, repositories) = match path.is_dir() {
+        true => (path.join("opengist.db"), path.join("repos")),
+        false => (
+            path.to_path_buf(),
+            path.parent().unwrap_or(Path::new(".")).join("repos"),
+        ),
+    };
+    let mut connection = open_database(&database).await?;
+    let query = "select gists.uuid, gists.title, gists.description, gists.private,
+        gists.created_at, gists.updated_at, users.username
+        from gists join users on users.id = gists.user_id";
+    let rows = sqlx::query(query).fetch_all(&mut connection).await;
+    let _ = connection.close().await;
+    let rows = match rows {
+        Ok(rows) => rows,
+        Err(e) => return Err(format!("Failed to read the gists:\n    {e}")),
+    };
+    let mut imports = Vec::new();
+    let mut skipped = Vec::new();
+    for row in rows {
+        let username: String = row.get("username");
+        let id: String = row.get("uuid");
+        let origin = format!("/{username}/{id}");
+        let repository = repositories.join(username.to_lowercase()).join(&id);
+        let files = match gist_files(&repository) {
+            Ok(files) if files.is_empty() => {
+                skipped.push((origin, "it has no files".to_string()));
+                continue;
+            }
+            Ok(files) => files,
+            Err(reason) => {
+                skipped.push((origin, reason));
+                continue;
+            }
+        };
+        let title: String = row.try_get("title").unwrap_or_default();
+        let description: String = row.try_get("description").unwrap_or_default();
+        let mut paste = match files.as_slice() {
+            [(name, content)] => new_paste(String::new(), content.clone(), format_of(name)),
+            _ => new_paste(
+                String::new(),
+                gist_document(&title, &description, &files),
+                "markdown",
+            ),
+        };
+        // Visibility 2 is private, only its owner can see it, like a draft
+        paste.draft = row.try_get::<i64, _>("private").unwrap_or_default() == 2;
+        let date_published: i64 = row.try_get("created_at").unwrap_or_default();
+        imports.push(Import {
+            origin,
+            paste,
+            uuid: Uuid::parse_str(&id).ok(),
+            date_published,
+            date_edited: row.try_get("updated_at").unwrap_or(date_published),
+        });
+    }
+    Ok((imports, skipped))
+}
+
+/// The names and contents of the files in a gist's latest revision
+fn gist_files(repository: &Path) -> Result<Vec<(String, String)>, String> {
+    let names = git(repository, &["ls-tree", "-z", "--name-only", "HEAD"])?;
+    let mut files = Vec::new();
+    for name in names.split('\0').filter(|name| !name.is_empty()) {
+        let content = git(repository, &["show", &format!("HEAD:{name}")])?;
+        files.push((name.to_string(), content));
+    }
+    Ok(files)
+}
+
+fn git(repository: &Path, args: &[&str]) -> Result<String, String> {
+    let output = process::Command::new("git")
+        .arg("--git-dir")
+        .arg(repository)
+        .args(args)
+        .output();
+    match output {
+        Ok(output) if output.status.success() => match String::from_utf8(output.stdout) {
+            Ok(stdout) => Ok(stdout),
+            Err(_) => Err("it holds a file that isn't valid UTF-8".to_string()),
+        },
+        Ok(output) => Err(format!(
+            "git failed to read {}: {}",
+            repository.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )),
+        Err(e) => Err(format!("git couldn't be run: {e}")),
+    }
+}
+
+/// Puts a gist's files into one markdown document, each as a code block under its name
+fn gist_document(title: &str, description: &str, files: &[(String, String)]) -> String {
+    let mut document = String::new();
+    if !title.is_empty() {
+        document.push_str(&format!("# {title}\n\n"));
+    }
+    if !description.is_empty() {
+        document.push_str(&format!("{description}\n\n"));
+    }
+    for (name, content) in files {
+        // The fence has to be longer than any run of backticks in the file
+        let longest_run = content.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+        let fence = "`".repeat(longest_run.max(2) + 1);
+        let language = name.rsplit_once('.').map_or("", |(_, extension)| extension);
+        document.push_str(&format!(
+            "## {name}\n\n{fence}{language}\n{}\n{fence}\n\n",
+            content.trim_end_matches('\n')
+        ));
+    }
+    document
+}
@@ -0,0 +1,179 @@
+//! `minify` shrinks rendered HTML before it is sent, which mostly pays off for large rendered pastes
+//! The minification is conservative: comments are removed and whitespace is collapsed, but nothing that could change how a page renders
+
+use axum::{
+    body::{self, Body},
+    http::header,
+    response::{IntoResponse, Response},
+};
+
+/// Elements whose contents are whitespace-sensitive, and are therefore copied verbatim
+const PRESERVED_ELEMENTS: [&str; 5] = ["pre", "textarea", "script", "style", "code"];
+
+/// Response-mapping middleware that minifies every `text/html` response
+pub async fn minify_responses(response: Response) -> Response {
+    let is_html = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/html"));
+    if !is_html {
+        return response;
+    }
+    let (mut parts, body) = response.into_parts();
+    let html = match body::to_bytes(body, usize::MAX).await {
+        Ok(bytes) => bytes,
+        Err(_) => return parts.status.into_response(),
+    };
+    let minified = match std::str::from_utf8(&html) {
+        Ok(html) => minify_html(html),
+        Err(_) => return Response::from_parts(parts, Body::from(html)),
+    };
+    parts.headers.remove(header::CONTENT_LENGTH);
+    Response::from_parts(parts, Body::from(minified))
+}
+
+/// Removes comments and collapses runs of whitespace into a single space, outside of whitespace-sensitive elements and quoted attribute values
+pub fn minify_html(html: &str) -> String {
+    let mut output = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(c) = rest.chars().next() {
+        if rest.starts_with("<!--") {
+            rest = match rest.find("-->") {
+                Some(end) => &rest[end + 3..],
+                None => "",
+            };
+        } else if let Some(element) = preserved_element(rest) {
+            let end = closing_tag(rest, element).unwrap_or(rest.len());
+            output.push_str(&rest[..end]);
+            rest = &rest[end..];
+        } else if is_tag(rest) {
+            rest = minify_tag(rest, &mut output);
+        } else if c.is_whitespace() {
+            // Whitespace on both sides of a removed comment would leave two spaces otherwise
+            if !output.ends_with(' ') {
+                output.push(' ');
+            }
+            rest = rest.trim_start();
+        } else {
+            output.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    output
+}
+
+/// Returns the name of the whitespace-sensitive element opened at the start of `html`, if there is one
+fn preserved_element(html: &str) -> Option<&'static str> {
+    let tag = html.strip_prefix('<')?;
+    PRESERVED_ELEMENTS.into_iter().find(|element| {
+        tag.get(..element.len())
+            .is_some_and(|name| name.eq_ignore_ascii_case(element))
+            && tag[element.len()..].starts_with(|c: char| c == '>' || c == '/' || c.is_whitespace())
+    })
+}
+
+/// Finds where `element` is closed in `html`, matching its name in any case
+fn closing_tag(html: &str, element: &str) -> Option<usize> {
+    html.match_indices("</")
+        .map(|(start, _)| start)
+        .find(|start| {
+            html.get(start + 2..start + 2 + element.len())
+                .is_some_and(|name| name.eq_ignore_ascii_case(element))
+        })
+}
+
+/// Whether `html` starts with an opening or closing tag
+fn is_tag(html: &str) -> bool {
+    let Some(tag) = html.strip_prefix('<') else {
+        return false;
+    };
+    let name = tag.strip_prefix('/').unwrap_or(tag);
+    name.starts_with(|c: char| c.is_ascii_alphabetic())
+}
+
+/// Copies the tag at the start of `html` to `output`, collapsing the whitespace between its attributes but none within their quoted values
+/// Returns what follows the tag
+fn minify_tag<'a>(html: &'a str, output: &mut String) -> &'a str {
+    let mut quote = None;
+    let mut rest = html;
+    while let Some(c) = rest.chars().next() {
+        match quote {
+            Some(open) if c == open => quote = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c.is_whitespace() => {
+                output.push(' ');
+                rest = rest.trim_start();
+                continue;
+            }
+            None if c == '>' => {
+                output.push(c);
+                return &rest[1..];
+            }
+            None => {}
+        }
+        output.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    rest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::minify_html;
+
+    #[test]
+    fn collapses_whitespace_and_removes_comments() {
+        assert_eq!(
+            minify_html("<p>\n    some   text <!-- a comment -->\n</p>"),
+            "<p> some text </p>"
+        );
+    }
+
+    #[test]
+    fn preserves_whitespace_sensitive_elements() {
+        let html = "<pre>a\n    b</pre>  <textarea>  x  </textarea><code class=\"x\">1  2</code>";
+        assert_eq!(
+            minify_html(html),
+            "<pre>a\n    b</pre> <textarea>  x  </textarea><code class=\"x\">1  2</code>"
+        );
+    }
+
+    #[test]
+    fn finds_closing_tags_in_any_case() {
+        assert_eq!(
+            minify_html("<PRE>a  b</Pre>  <p>c   d</p>"),
+            "<PRE>a  b</Pre> <p>c d</p>"
+        );
+        assert_eq!(
+            minify_html("<script>let a  = 1;</SCRIPT>  x"),
+            "<script>let a  = 1;</SCRIPT> x"
+        );
+    }
+
+    #[test]
+    fn only_preserves_whole_element_names() {
+        assert_eq!(minify_html("<codex>a   b</codex>"), "<codex>a b</codex>");
+    }
+
+    #[test]
+    fn keeps_quoted_attribute_values() {
+        assert_eq!(
+            minify_html("<a   title=\"a  b\"\n   data-x='c   d'>e   f</a>"),
+            "<a title=\"a  b\" data-x='c   d'>e f</a>"
+        );
+        assert_eq!(
+            minify_html("<img alt=\"1 > 2  \" src=x>"),
+            "<img alt=\"1 > 2  \" src=x>"
+        );
+    }
+
+    #[test]
+    fn keeps_multibyte_text() {
+        assert_eq!(
+            minify_html("<p>é   ü</p>  <pre>ß  </PRE>"),
+            "<p>é ü</p> <pre>ß  </PRE>"
+        );
+    }
+}
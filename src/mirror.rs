@@ -0,0 +1,365 @@
+//! `mirror` keeps a downstream pasties instance in sync, as a warm standby or as a public copy of an internal instance
+//! Every change queues a `Job::MirrorPaste`, which pushes the paste's current state to the downstream instance's `/api/mirror`, signed with a shared secret
+//! Pushing states rather than the changes themselves means failed and reordered pushes can't leave the copy behind, the next push of a paste catches it up
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use axum::{
+    body::Bytes,
+    extract::State,
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::post,
+    Router,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use sqlx::SqlitePool;
+use tokio::sync::broadcast::{error::RecvError, Receiver};
+use url::Url;
+
+use crate::{
+    config::{Config, MirrorConfig},
+    database::{self, DatabaseError},
+    events::PasteEvent,
+    jobs::{Job, JobQueue},
+    model::{ContentStats, DatabasePaste, Manager},
+    request_id,
+    routing::pages,
+    style::PasteStyle,
+    utility,
+};
+
+/// Where the downstream instance accepts pushed changes. The access checks of private, read-only and SSO instances let it through, as pushes are authenticated by their signature
+pub const PATH: &str = "/api/mirror";
+
+const TIMESTAMP_HEADER: &str = "X-Pasties-Timestamp";
+const SIGNATURE_HEADER: &str = "X-Pasties-Signature";
+
+/// How far a push's timestamp may be from the downstream instance's clock, in seconds, so captured pushes can't be replayed later. Pushes received within it are remembered instead
+const MAX_CLOCK_SKEW: i64 = 5 * 60;
+
+/// How long a single push may take
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Checks that the mirroring settings can be used
+pub fn check_config(config: &Config) -> Result<(), String> {
+    if let Some(mirror) = &config.mirror {
+        if !Url::parse(&mirror.url).is_ok_and(|url| matches!(url.scheme(), "http" | "https")) {
+            return Err(format!(
+                "The [mirror] url has to be the http(s) address of another instance, not {}",
+                mirror.url
+            ));
+        }
+        if mirror.secret.is_empty() {
+            return Err("The [mirror] secret can't be empty".to_string());
+        }
+    }
+    match config.mirror_secret.as_deref() {
+        Some("") => Err("The mirror_secret can't be empty".to_string()),
+        _ => Ok(()),
+    }
+}
+
+/// A paste as it is pushed to the downstream instance
+/// It leaves out what only matters to the instance itself: view limits, owners, IPFS copies and pins
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MirroredPaste {
+    pub id:             i64,
+    pub url:            String,
+    pub uuid:           String,
+    pub password_hash:  String,
+    pub content:        String,
+    pub format:         String,
+    pub date_published: i64,
+    pub date_edited:    i64,
+    pub draft:          bool,
+    pub style:          PasteStyle,
+    pub quarantined:    bool,
+    pub date_locked:    Option<i64>,
+}
+
+impl From<DatabasePaste> for MirroredPaste {
+    fn from(paste: DatabasePaste) -> Self {
+        Self {
+            id:             paste.id,
+            url:            paste.url,
+            uuid:           paste.uuid,
+            password_hash:  paste.password_hash,
+            content:        paste.content,
+            format:         paste.format,
+            date_published: paste.date_published,
+            date_edited:    paste.date_edited,
+            draft:          paste.draft,
+            style:          paste.style,
+            quarantined:    paste.quarantined,
+            date_locked:    paste.date_locked,
+        }
+    }
+}
+
+impl From<MirroredPaste> for DatabasePaste {
+    fn from(paste: MirroredPaste) -> Self {
+        Self {
+            id:              paste.id,
+            stats:           ContentStats::of(&paste.content),
+            url:             paste.url,
+            uuid:            paste.uuid,
+            password_hash:   paste.password_hash,
+            content:         paste.content,
+            format:          paste.format,
+            date_published:  paste.date_published,
+            date_edited:     paste.date_edited,
+            draft:           paste.draft,
+            views_remaining: None,
+            style:           paste.style,
+            quarantined:     paste.quarantined,
+            ipfs_cid:        None,
+            date_locked:     paste.date_locked,
+            owner:           None,
+            rendered_html:   None,
+        }
+    }
+}
+
+/// The state of a paste on the upstream instance, `None` if it doesn't exist there (anymore)
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MirroredChange {
+    pub url:   String,
+    pub paste: Option<MirroredPaste>,
+}
+
+/// The MAC of a push, over its timestamp and body
+fn mac(secret: &str, timestamp: i64, body: &[u8]) -> Hmac<Sha256> {
+    // HMAC accepts keys of any length
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(format!("{timestamp}.").as_bytes());
+    mac.update(body);
+    mac
+}
+
+/// Checks that a push was signed with the secret, recently enough, returning its signature
+fn verify(secret: &str, headers: &HeaderMap, body: &[u8]) -> Option<(i64, Vec<u8>)> {
+    let timestamp = headers
+        .get(TIMESTAMP_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<i64>().ok())?;
+    if (utility::unix_timestamp() - timestamp).abs() > MAX_CLOCK_SKEW {
+        return None;
+    }
+    let signature = headers
+        .get(SIGNATURE_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| URL_SAFE_NO_PAD.decode(value).ok())?;
+    match mac(secret, timestamp, body).verify_slice(&signature) {
+        Ok(()) => Some((timestamp, signature)),
+        Err(_) => None,
+    }
+}
+
+/// The signatures of the pushes received while their timestamps are still accepted, so a captured push can't be replayed in the meantime
+#[derive(Clone, Default)]
+struct SeenPushes(Arc<Mutex<HashMap<Vec<u8>, i64>>>);
+
+impl SeenPushes {
+    /// Remembers a push, returns false if it was received before
+    fn insert(&self, timestamp: i64, signature: Vec<u8>) -> bool {
+        let now = utility::unix_timestamp();
+        let mut seen = self.0.lock().unwrap();
+        seen.retain(|_, timestamp| now - *timestamp <= MAX_CLOCK_SKEW);
+        seen.insert(signature, timestamp).is_none()
+    }
+}
+
+/// The route pushed changes are received at, which only exists with a `mirror_secret`
+pub fn routes(manager: Manager) -> Router {
+    Router::new()
+        .route(PATH, post(receive))
+        .with_state((manager, SeenPushes::default()))
+}
+
+/// Applies a change pushed by the instance this one mirrors
+async fn receive(
+    State((manager, seen)): State<(Manager, SeenPushes)>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let Some(secret) = &manager.config().mirror_secret else {
+        return pages::not_found_handler(headers).await;
+    };
+    let Some((timestamp, signature)) = verify(secret, &headers, &body) else {
+        return (
+            StatusCode::UNAUTHORIZED,
+            request_id::annotate(
+                "The change isn't signed with the mirror secret, or its timestamp is too far off"
+                    .to_string(),
+            ),
+        )
+            .into_response();
+    };
+    if !seen.insert(timestamp, signature) {
+        return (
+            StatusCode::CONFLICT,
+            request_id::annotate("The change was already received".to_string()),
+        )
+            .into_response();
+    }
+    let change = match serde_json::from_slice::<MirroredChange>(&body) {
+        Ok(change) => change,
+        Err(e) => return (StatusCode::UNPROCESSABLE_ENTITY, e.to_string()).into_response(),
+    };
+    match manager.apply_mirrored(change).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+/// Queues a push of the pastes that events from `events` are about in the background, until the event bus is gone
+/// A paste that is already queued is pushed right away instead, and a renamed paste is deleted under its old URL
+pub fn queue_pushes(jobs: JobQueue, mut events: Receiver<PasteEvent>) {
+    tokio::spawn(async move {
+        loop {
+            let urls = match events.recv().await {
+                Ok(
+                    PasteEvent::Created { url, .. }
+                    | PasteEvent::Published { url }
+                    | PasteEvent::Deleted { url }
+                    | PasteEvent::Changed { url },
+                ) => vec![url],
+                Ok(PasteEvent::Updated { previous_url, url }) if previous_url != url => {
+                    vec![previous_url, url]
+                }
+                Ok(PasteEvent::Updated { url, .. }) => vec![url],
+                Ok(PasteEvent::Viewed { .. }) => continue,
+                Err(RecvError::Lagged(skipped)) => {
+                    tracing::warn!(
+                        "The mirror fell behind, {skipped} changes were not pushed until the pastes change again"
+                    );
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            };
+            let now = utility::unix_timestamp();
+            for url in urls {
+                if let Err(e) = jobs
+                    .enqueue_once(&Job::MirrorPaste { url: url.clone() }, now)
+                    .await
+                {
+                    tracing::error!("Failed to queue {url} for the mirror: {e:?}");
+                }
+            }
+        }
+    });
+}
+
+#[derive(Clone)]
+pub struct Mirror {
+    config:  MirrorConfig,
+    client:  reqwest::Client,
+    pool:    SqlitePool,
+    /// Pushes are sent one at a time, so two pushes of the same paste can't overtake each other
+    sending: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl Mirror {
+    pub fn new(config: &MirrorConfig, pool: SqlitePool) -> Self {
+        Self {
+            config: config.clone(),
+            client: reqwest::Client::new(),
+            pool,
+            sending: Arc::default(),
+        }
+    }
+
+    /// Pushes a paste's current state, by its stored URL, see `Job::MirrorPaste`
+    /// Pastes with a view limit are deleted downstream instead, as a copy could be read as often as anyone likes
+    pub async fn push(&self, url: &str) -> Result<(), String> {
+        let _sending = self.sending.lock().await;
+        let paste = match database::retrieve_paste(&self.pool, &url.to_string()).await {
+            Ok(paste) if paste.views_remaining.is_none() => Some(MirroredPaste::from(paste)),
+            Ok(_) | Err(DatabaseError::Retrieval(sqlx::Error::RowNotFound)) => None,
+            Err(e) => return Err(format!("Failed to read {url} for the mirror: {e:?}")),
+        };
+        let change = MirroredChange {
+            url: url.to_string(),
+            paste,
+        };
+        // A plain struct of strings and numbers, serializing it can't fail
+        let body = serde_json::to_vec(&change).unwrap();
+        let timestamp = utility::unix_timestamp();
+        let signature = URL_SAFE_NO_PAD.encode(
+            mac(&self.config.secret, timestamp, &body)
+                .finalize()
+                .into_bytes(),
+        );
+        let result = self
+            .client
+            .post(format!("{}{PATH}", self.config.url.trim_end_matches('/')))
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(TIMESTAMP_HEADER, timestamp)
+            .header(SIGNATURE_HEADER, signature)
+            .body(body)
+            .timeout(DELIVERY_TIMEOUT)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        match result {
+            Ok(_) => Ok(()),
+            Err(e) => Err(format!("Failed to push {url} to the mirror: {e}")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderMap;
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+    use hmac::Mac;
+
+    use super::{mac, verify, MAX_CLOCK_SKEW, SIGNATURE_HEADER, TIMESTAMP_HEADER};
+    use crate::utility;
+
+    /// The headers of a push signed like `Mirror::push` signs them
+    fn signed_headers(secret: &str, timestamp: i64, body: &[u8]) -> HeaderMap {
+        let signature =
+            URL_SAFE_NO_PAD.encode(mac(secret, timestamp, body).finalize().into_bytes());
+        let mut headers = HeaderMap::new();
+        headers.insert(TIMESTAMP_HEADER, timestamp.into());
+        headers.insert(SIGNATURE_HEADER, signature.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn accepts_signed_pushes() {
+        let now = utility::unix_timestamp();
+        let headers = signed_headers("secret", now, b"body");
+        assert!(verify("secret", &headers, b"body").is_some_and(|(timestamp, _)| timestamp == now));
+    }
+
+    #[test]
+    fn rejects_pushes_with_another_secret_or_body() {
+        let headers = signed_headers("secret", utility::unix_timestamp(), b"body");
+        assert!(verify("other", &headers, b"body").is_none());
+        assert!(verify("secret", &headers, b"other").is_none());
+        assert!(verify("secret", &HeaderMap::new(), b"body").is_none());
+    }
+
+    #[test]
+    fn rejects_pushes_outside_the_clock_skew() {
+        let old = utility::unix_timestamp() - MAX_CLOCK_SKEW - 1;
+        assert!(verify("secret", &signed_headers("secret", old, b"body"), b"body").is_none());
+        let future = utility::unix_timestamp() + MAX_CLOCK_SKEW + 1;
+        assert!(verify(
+            "secret",
+            &signed_headers("secret", future, b"body"),
+            b"body"
+        )
+        .is_none());
+    }
+}
@@ -2,28 +2,114 @@
 //! It also handles the logic before database operations
 
 use core::fmt;
-use std::fmt::Display;
+use std::{env, fmt::Display, sync::Arc};
 
 use askama_axum::{IntoResponse, Response};
 use axum::http::StatusCode;
 use serde::{Deserialize, Serialize};
 use sqlx::SqlitePool;
+use tokio::{
+    fs::{self, File},
+    sync::broadcast::Receiver,
+};
+use uuid::Uuid;
 
 use crate::{
-    database::{self, DatabaseError},
+    banner::{Banner, CurrentBanner, Severity},
+    config::{Config, NamespaceConfig},
+    database::{self, DatabaseError, IdempotencyKey},
+    events::{EventBus, PasteEvent},
+    excerpt,
+    filters::ContentFilters,
+    hooks::{Hook, Hooks},
+    ipfs::Ipfs,
+    jobs::{Job, JobQueue},
+    links::{self, LinkChecker},
+    markdown,
+    mirror::{self, Mirror, MirroredChange},
+    proxy::ImageProxy,
+    referrers,
+    render::Renderers,
+    request_id,
+    routing::{self, api},
+    secrets,
+    sso::{self, Sso},
+    style::{Font, PasteStyle, Width},
     utility::{self, hash_string, is_url_safe},
+    validation::{PasteContent, PastePassword, PasteUrl},
+    webhooks::Notifier,
 };
 
+/// How long idempotency keys are remembered for, in seconds
+const IDEMPOTENCY_KEY_LIFETIME: i64 = 24 * 60 * 60;
+
+/// The longest a paste's content can be, in bytes
+pub const MAX_CONTENT_LENGTH: usize = 200_000;
+
+/// How long editor contents are kept after they were last autosaved, in seconds
+const AUTOSAVE_LIFETIME: i64 = 6 * 60 * 60;
+
+/// How long a URL stays reserved if no lifetime is given, and the longest it can be reserved for, in seconds
+const RESERVATION_LIFETIME: i64 = 24 * 60 * 60;
+const MAX_RESERVATION_LIFETIME: i64 = 7 * 24 * 60 * 60;
+/// How many referring domains are counted per paste, so spoofed `Referer` headers can't grow the table without bound
+const MAX_REFERRERS: i64 = 100;
+/// How many referring domains are shown to a paste's owner
+const TOP_REFERRERS: i64 = 10;
+
+/// How many pastes one `Job::RenderPastes` renders
+const PRERENDER_BATCH_SIZE: i64 = 100;
+
 pub enum PasteError {
     // Errors that may occur when creating a paste
     InvalidUrl,
     InvalidPassword,
     InvalidContent,
+    InvalidFormat,
     AlreadyExists,
     Database(DatabaseError),
     // todo!()
     NotFound,
     IncorrectPassword,
+    /// The idempotency key is malformed, or was used for a different request
+    InvalidIdempotencyKey,
+    /// Another request with the same idempotency key is still being handled
+    IdempotencyKeyInUse,
+    CollectionNotFound,
+    CollectionAlreadyExists,
+    /// Autosave tokens have to be URL-safe and 16 to 128 characters long
+    InvalidAutosaveToken,
+    AutosaveNotFound,
+    /// The view limit has to be at least one
+    InvalidMaxViews,
+    /// The content looks like it contains credentials, and `block_secrets` is set
+    ContainsSecret(String),
+    /// Pastes have to expire at least a second after they were created
+    InvalidExpiry,
+    /// The namespace would hold more pastes or bytes than it is allowed to
+    QuotaExceeded,
+    /// Banners need a message, and have to expire at least a second after they were set
+    InvalidBanner,
+    /// The content violates the instance's content policy with this name, see the `filters` module
+    Filtered(String),
+    /// Publishing to IPFS isn't configured on the instance
+    IpfsUnavailable,
+    /// The IPFS node couldn't be reached, or failed to add the paste
+    IpfsFailed,
+    /// The snapshot of the database couldn't be read back
+    BackupFailed,
+    /// The URL is reserved, and the paste wasn't given the reservation's password
+    UrlReserved,
+    /// The paste isn't set to expire, so its expiry can't be extended
+    NotExpiring,
+    /// Claiming pastes needs an `[sso]` session
+    SignInRequired,
+    /// The paste was locked, so it can't be changed
+    Locked,
+    /// The paste is quarantined, and is only shown on its page, behind a warning
+    Quarantined,
+    /// The paste's content doesn't have the requested hash, it was edited since or never had it
+    RevisionNotFound,
 }
 
 impl Display for PasteError {
@@ -37,9 +123,52 @@ impl Display for PasteError {
                 f,
                 "The specified content is invalid, or is the wrong length"
             ),
+            Self::InvalidFormat => write!(f, "The specified format is not supported"),
             Self::InvalidUrl => write!(f, "The specified URL is invalid, or is the wrong length"),
             Self::InvalidPassword => write!(f, "The specified password is invalid, or is the wrong length"),
             Self::IncorrectPassword => write!(f, "The specified password is incorrect"),
+            Self::CollectionNotFound => write!(f, "No collection with this name has been found"),
+            Self::InvalidAutosaveToken => write!(f, "The autosave token is invalid"),
+            Self::ContainsSecret(warning) => write!(
+                f,
+                "{warning}, remove it or replace it with a placeholder before publishing"
+            ),
+            Self::InvalidMaxViews => write!(f, "The maximum number of views has to be at least 1"),
+            Self::InvalidExpiry => write!(f, "Pastes have to expire at least 1 second after they are created"),
+            Self::QuotaExceeded => write!(f, "The namespace's quota doesn't leave room for this"),
+            Self::Filtered(policy) => write!(
+                f,
+                "The content violates this instance's \"{policy}\" policy, so it can't be published here"
+            ),
+            Self::IpfsUnavailable => write!(f, "Publishing to IPFS isn't enabled on this instance"),
+            Self::IpfsFailed => write!(f, "The paste couldn't be published to IPFS, try again later"),
+            Self::BackupFailed => write!(f, "The backup couldn't be read"),
+            Self::NotExpiring => write!(f, "This paste isn't set to expire"),
+            Self::SignInRequired => write!(f, "Sign in to claim pastes"),
+            Self::Locked => write!(f, "This paste is locked, so it can't be changed anymore"),
+            Self::Quarantined => write!(
+                f,
+                "This paste is quarantined until an admin reviews it, it can only be viewed on its page"
+            ),
+            Self::UrlReserved => write!(
+                f,
+                "This URL is reserved, only a paste with the reservation's password can take it"
+            ),
+            Self::InvalidBanner => write!(f, "Banners need a message, and have to expire at least 1 second after they are set"),
+            Self::AutosaveNotFound => write!(f, "Nothing has been autosaved under this token"),
+            Self::RevisionNotFound => write!(
+                f,
+                "This revision of the paste isn't available, its content has changed since"
+            ),
+            Self::CollectionAlreadyExists => write!(f, "A collection with this name already exists"),
+            Self::InvalidIdempotencyKey => write!(
+                f,
+                "The idempotency key is invalid, or was already used for a different paste"
+            ),
+            Self::IdempotencyKeyInUse => write!(
+                f,
+                "Another request with this idempotency key is still being handled, try again shortly"
+            ),
             Self::Database(e) => write!(f, "An unspecified error occured with the database.\nThe following error was passed: {:?}", e),
         }
     }
@@ -48,70 +177,206 @@ impl Display for PasteError {
 impl IntoResponse for PasteError {
     fn into_response(self) -> Response {
         use crate::model::PasteError::*;
-        match self {
-            NotFound => (StatusCode::NOT_FOUND, format!("{}", NotFound)).into_response(),
-            AlreadyExists => (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                format!("{}", AlreadyExists),
-            )
-                .into_response(),
-            InvalidContent => {
-                (StatusCode::BAD_REQUEST, format!("{}", InvalidContent)).into_response()
+        let status = match self {
+            NotFound | CollectionNotFound | AutosaveNotFound | RevisionNotFound => {
+                StatusCode::NOT_FOUND
             }
-            InvalidUrl => (StatusCode::BAD_REQUEST, format!("{}", InvalidUrl)).into_response(),
-            InvalidPassword => {
-                (StatusCode::BAD_REQUEST, format!("{}", InvalidPassword)).into_response()
+            InvalidContent | InvalidFormat | InvalidUrl | InvalidPassword
+            | InvalidAutosaveToken | InvalidMaxViews | InvalidExpiry | InvalidBanner => {
+                StatusCode::BAD_REQUEST
             }
-            IncorrectPassword => {
-                (StatusCode::UNAUTHORIZED, format!("{}", IncorrectPassword)).into_response()
+            IncorrectPassword | SignInRequired => StatusCode::UNAUTHORIZED,
+            InvalidIdempotencyKey | ContainsSecret(_) | Filtered(_) => {
+                StatusCode::UNPROCESSABLE_ENTITY
             }
-            _ => (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "An unspecified error occured with the paste manager",
-            )
-                .into_response(),
+            QuotaExceeded => StatusCode::INSUFFICIENT_STORAGE,
+            IpfsUnavailable => StatusCode::NOT_IMPLEMENTED,
+            IpfsFailed => StatusCode::BAD_GATEWAY,
+            AlreadyExists
+            | CollectionAlreadyExists
+            | UrlReserved
+            | NotExpiring
+            | IdempotencyKeyInUse => StatusCode::CONFLICT,
+            Locked => StatusCode::LOCKED,
+            Quarantined => StatusCode::FORBIDDEN,
+            Database(_) | BackupFailed => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        (status, request_id::annotate(self.public_message())).into_response()
+    }
+}
+
+impl PasteError {
+    /// The error's message, without the details of database errors, which clients have no use for
+    pub fn public_message(&self) -> String {
+        match self {
+            Self::Database(_) => "An unspecified error occured with the paste manager".to_string(),
+            _ => self.to_string(),
+        }
+    }
+}
+
+/// Words read per minute, used to estimate a paste's reading time
+const WORDS_PER_MINUTE: i64 = 200;
+
+/// Counts derived from a paste's content, computed and stored whenever the content changes
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+pub struct ContentStats {
+    pub word_count:   i64,
+    pub line_count:   i64,
+    /// Estimated minutes to read the content, rounded up
+    pub reading_time: i64,
+}
+
+impl ContentStats {
+    pub fn of(content: &str) -> Self {
+        let word_count = content.split_whitespace().count() as i64;
+        Self {
+            word_count,
+            line_count: content.lines().count() as i64,
+            reading_time: (word_count + WORDS_PER_MINUTE - 1) / WORDS_PER_MINUTE,
         }
     }
 }
 
 /// Represents the database's paste schema as a struct, excluding the primary key, as a randomly generated i64 ID uniquely identifies any paste
+#[derive(Clone)]
 pub struct DatabasePaste {
-    pub id:             i64,
-    pub url:            String,
-    pub content:        String,
-    pub password_hash:  String,
-    pub date_published: i64,
-    pub date_edited:    i64,
+    pub id:              i64,
+    pub url:             String,
+    pub content:         String,
+    pub format:          String,
+    pub password_hash:   String,
+    pub date_published:  i64,
+    pub date_edited:     i64,
+    pub stats:           ContentStats,
+    pub draft:           bool,
+    /// How many more times the paste can be viewed before it is deleted, if it is limited
+    pub views_remaining: Option<i64>,
+    pub uuid:            String,
+    pub style:           PasteStyle,
+    /// Hidden from everyone without the password until an admin releases it, see the `filters` module
+    pub quarantined:     bool,
+    /// The CID the paste was last published to IPFS under, see the `ipfs` module
+    pub ipfs_cid:        Option<String>,
+    /// When the paste was locked, after which it can't be changed anymore
+    pub date_locked:     Option<i64>,
+    /// The subject of the signed-in user who claimed the paste, who doesn't need its password
+    pub owner:           Option<String>,
+    /// The content as rendered when it was written, if it doesn't have to be rendered on every view, see `Manager::prerender`
+    pub rendered_html:   Option<String>,
 }
 
 impl From<NewPasteData> for DatabasePaste {
     fn from(paste: NewPasteData) -> Self {
         DatabasePaste {
-            id:             utility::pseudoid(),
-            url:            paste.url,
-            content:        paste.content,
-            password_hash:  utility::hash_string(paste.password),
-            date_published: utility::unix_timestamp(),
-            date_edited:    utility::unix_timestamp(),
+            id:              utility::pseudoid(),
+            stats:           ContentStats::of(&paste.content),
+            style:           paste.style(PasteStyle::default()),
+            url:             paste.url,
+            content:         paste.content,
+            format:          paste.format,
+            password_hash:   utility::hash_string(paste.password),
+            date_published:  utility::unix_timestamp(),
+            date_edited:     utility::unix_timestamp(),
+            draft:           paste.draft,
+            views_remaining: paste.max_views,
+            uuid:            Uuid::new_v4().to_string(),
+            quarantined:     false,
+            ipfs_cid:        None,
+            date_locked:     None,
+            owner:           None,
+            rendered_html:   None,
         }
     }
 }
 
 /// Represents the "mutable" fields on a paste within the database. Used for interacting with (and editing) existing paste records.
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Debug, Clone)]
 pub struct PartialDatabasePaste {
     pub url:           String,
-    pub content:       String,
+    pub content:       PasteContent,
+    pub format:        String,
     pub password_hash: String,
     pub date_edited:   i64,
+    pub stats:         ContentStats,
+    pub style:         PasteStyle,
+    pub quarantined:   bool,
+    pub rendered_html: Option<String>,
+}
+
+/// The URL and password of a newly created paste, which are random if none were given
+#[derive(Serialize, Debug, Clone)]
+pub struct CreatedPaste {
+    pub url:      String,
+    pub id:       String,
+    pub uuid:     String,
+    pub password: String,
+}
+
+/// How much of its quota a namespace uses, limits that are `None` aren't enforced
+#[derive(Serialize, Debug, Clone)]
+pub struct Quota {
+    pub pastes:           i64,
+    /// The bytes of content of all pastes together
+    pub bytes:            i64,
+    pub max_pastes:       Option<i64>,
+    pub max_bytes:        Option<i64>,
+    pub remaining_pastes: Option<i64>,
+    pub remaining_bytes:  Option<i64>,
+}
+
+/// Changes to apply to an existing paste, fields that are `None` are left as they are
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct PasteChanges {
+    pub url:      Option<String>,
+    pub content:  Option<String>,
+    pub password: Option<String>,
+    pub format:   Option<String>,
+    pub font:     Option<Font>,
+    pub width:    Option<Width>,
+    pub wrap:     Option<bool>,
 }
 
 /// Data provided by the user to create a new paste from, or update an existing paste with
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct NewPasteData {
-    pub url:      String,
-    pub content:  String,
-    pub password: String,
+    /// A random URL is used if empty
+    #[serde(default)]
+    pub url:        String,
+    pub content:    String,
+    /// A random password is used if empty
+    #[serde(default)]
+    pub password:   String,
+    /// The name of the renderer used to display the paste, it is detected from the content if empty
+    #[serde(default)]
+    pub format:     String,
+    /// Drafts are hidden from everyone without the password until they are published. Ignored when updating a paste
+    #[serde(default)]
+    pub draft:      bool,
+    /// The paste is deleted after being viewed this many times, views by its owner don't count
+    #[serde(default, deserialize_with = "utility::empty_as_none")]
+    pub max_views:  Option<i64>,
+    /// The paste is deleted this many seconds after it was created. Ignored when updating a paste
+    #[serde(default, deserialize_with = "utility::empty_as_none")]
+    pub expires_in: Option<i64>,
+    /// How the paste is presented, see the `style` module. Options that are `None` are left as they are when updating a paste
+    #[serde(default, deserialize_with = "utility::empty_as_none")]
+    pub font:       Option<Font>,
+    #[serde(default, deserialize_with = "utility::empty_as_none")]
+    pub width:      Option<Width>,
+    #[serde(default, deserialize_with = "utility::empty_as_none")]
+    pub wrap:       Option<bool>,
+}
+
+impl NewPasteData {
+    /// The paste's style, with the options that weren't given taken from `current`
+    pub fn style(&self, current: PasteStyle) -> PasteStyle {
+        PasteStyle {
+            font:  self.font.unwrap_or(current.font),
+            width: self.width.unwrap_or(current.width),
+            wrap:  self.wrap.unwrap_or(current.wrap),
+        }
+    }
 }
 
 /// Struct to identify and authorize access to pastes
@@ -121,140 +386,1754 @@ pub struct PasteCredentials {
     pub password: String,
 }
 
+/// Identifies and authorizes changes to a collection of pastes. Also returned when a collection is created, as both fields may be random
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CollectionCredentials {
+    /// A random name is used if empty
+    #[serde(default)]
+    pub name:     String,
+    /// A random password is used if empty
+    #[serde(default)]
+    pub password: String,
+}
+
+/// A URL to hold for a paste whose content isn't ready yet
+#[derive(Deserialize, Debug, Clone)]
+pub struct ReservationRequest {
+    pub url:        String,
+    /// A random password is used if empty
+    #[serde(default)]
+    pub password:   String,
+    /// Seconds until the reservation expires, a day if not given
+    #[serde(default, deserialize_with = "utility::empty_as_none")]
+    pub expires_in: Option<i64>,
+}
+
+/// A reserved URL, which only a paste created with the password can take until the reservation expires
+#[derive(Serialize, Debug, Clone)]
+pub struct Reservation {
+    pub url:      String,
+    pub password: String,
+    /// The unix timestamp the reservation expires at
+    pub expires:  i64,
+}
+
+/// A site a paste was opened from, and how often
+#[derive(Serialize, Debug, Clone)]
+pub struct Referrer {
+    pub domain: String,
+    pub views:  i64,
+}
+
+/// Editor contents saved while a paste is being written, so they survive a browser crash
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Autosave {
+    pub content: String,
+    #[serde(default)]
+    pub format:  String,
+    /// When the contents were saved, set by the server
+    #[serde(default)]
+    pub updated: i64,
+}
+
+/// A named group of pastes, in the order they were added
+#[derive(Serialize, Debug, Clone)]
+pub struct Collection {
+    pub name:   String,
+    pub pastes: Vec<PasteReturn>,
+}
+
 /// Struct to be served to the end user, only contains data that is displayed on the front-end
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct PasteReturn {
-    pub url:            String,
-    pub content:        String,
-    pub date_published: i64,
-    pub date_edited:    i64,
+    pub url:             String,
+    /// The paste's ID in base62, which `/p/<id>` redirects to the paste under, even after it is renamed
+    pub id:              String,
+    /// The paste's public UUID, which is accepted in place of its URL and never changes
+    pub uuid:            String,
+    pub content:         String,
+    pub format:          String,
+    pub date_published:  i64,
+    pub date_edited:     i64,
+    /// Hex-encoded SHA256 hash of the stored content, for clients to verify they received it intact
+    pub content_sha256:  String,
+    #[serde(flatten)]
+    pub stats:           ContentStats,
+    /// The start of the content as plain text, for listings and link previews
+    pub excerpt:         String,
+    pub draft:           bool,
+    /// How many more times the paste can be viewed before it is deleted, if it is limited
+    pub views_remaining: Option<i64>,
+    pub style:           PasteStyle,
+    /// Only whoever has the password can see the paste until an admin releases it
+    pub quarantined:     bool,
+    /// The CID of the content last published to IPFS, which may be older than the current content
+    pub ipfs_cid:        Option<String>,
+    /// When the paste was locked, if it was. Locked pastes can only be deleted, their content never changes
+    pub date_locked:     Option<i64>,
+    /// The content as rendered when it was written, which `Manager::render` starts from instead of rendering it again
+    #[serde(skip)]
+    pub rendered_html:   Option<String>,
 }
 
 impl From<DatabasePaste> for PasteReturn {
     fn from(paste: DatabasePaste) -> Self {
         Self {
-            url:            paste.url,
-            content:        paste.content,
-            date_published: paste.date_published,
-            date_edited:    paste.date_edited,
+            url:             paste.url,
+            id:              utility::to_base62(paste.id),
+            uuid:            paste.uuid,
+            content_sha256:  hash_string(paste.content.clone()),
+            excerpt:         excerpt::excerpt(&paste.format, &paste.content),
+            content:         paste.content,
+            format:          paste.format,
+            date_published:  paste.date_published,
+            date_edited:     paste.date_edited,
+            stats:           paste.stats,
+            draft:           paste.draft,
+            views_remaining: paste.views_remaining,
+            style:           paste.style,
+            quarantined:     paste.quarantined,
+            ipfs_cid:        paste.ipfs_cid,
+            date_locked:     paste.date_locked,
+            rendered_html:   paste.rendered_html,
         }
     }
 }
 
 #[derive(Clone)]
 pub struct Manager {
-    pool: SqlitePool,
+    pool:         SqlitePool,
+    /// Where paste views and listings are read from, the same as `pool` unless `[database] read_connections` is set
+    read_pool:    SqlitePool,
+    renderers:    Renderers,
+    config:       Arc<Config>,
+    events:       EventBus,
+    link_checker: Option<LinkChecker>,
+    image_proxy:  Option<ImageProxy>,
+    ipfs:         Option<Ipfs>,
+    mirror:       Option<Mirror>,
+    sso:          Option<Sso>,
+    hooks:        Hooks,
+    jobs:         JobQueue,
+    banner:       CurrentBanner,
+    filters:      ContentFilters,
+    /// The namespace pastes are created and looked up in, see `Manager::in_namespace`
+    namespace:    Option<Arc<NamespaceConfig>>,
+    /// When the instance was started, as a Unix timestamp
+    started:      i64,
 }
 
 impl Manager {
-    pub async fn init() -> Self {
+    pub async fn init(config: &Config) -> Self {
+        let pool = database::init_database().await;
+        let read_pool = match config.database.read_connections {
+            0 => pool.clone(),
+            connections => database::init_read_pool(&pool, connections).await,
+        };
+        let events = EventBus::default();
+        Notifier::new(config, pool.clone()).listen(events.subscribe());
+        let jobs = JobQueue::new(pool.clone());
+        if config.mirror.is_some() {
+            mirror::queue_pushes(jobs.clone(), events.subscribe());
+        }
+        let banner = match database::retrieve_banner(&pool).await {
+            Ok(banner) => banner.map(Banner::from),
+            Err(e) => panic!("Failed to load the banner:\n    {e:?}"),
+        };
         Self {
-            pool: database::init_database().await,
+            jobs,
+            mirror: config
+                .mirror
+                .as_ref()
+                .map(|mirror| Mirror::new(mirror, pool.clone())),
+            pool,
+            read_pool,
+            renderers: Renderers::new(config.default_format),
+            config: Arc::new(config.clone()),
+            events,
+            link_checker: config.link_safety.as_ref().map(LinkChecker::new),
+            image_proxy: config.image_proxy.as_ref().map(ImageProxy::new),
+            ipfs: config.ipfs.as_ref().map(Ipfs::new),
+            sso: config.sso.as_ref().map(|sso| Sso::new(config, sso)),
+            hooks: Hooks::default(),
+            banner: CurrentBanner::new(banner),
+            filters: ContentFilters::new(&config.filters),
+            namespace: None,
+            started: utility::unix_timestamp(),
         }
     }
-    pub async fn create_paste(&self, mut paste: NewPasteData) -> Result<(), PasteError> {
-        // Check if the provided URL contains only accepted ASCII, and if it is short enough
-        if !is_url_safe(&paste.url) || paste.url.len() > 250 {
-            return Err(PasteError::InvalidUrl);
+
+    /// Registers an extension's hook, see the `hooks` module
+    pub fn with_hook(mut self, hook: impl Hook + 'static) -> Self {
+        self.hooks.register(hook);
+        self
+    }
+
+    /// A manager for one of the configured namespaces, which share the database but each have a URL space of their own
+    /// Pastes in a namespace are stored under `<namespace>/<url>`, which is also the path they are served at
+    pub fn in_namespace(&self, namespace: &NamespaceConfig) -> Self {
+        Self {
+            namespace: Some(Arc::new(namespace.clone())),
+            ..self.clone()
         }
+    }
 
-        // Provide a default URL if it is empty, or throw an error if an already registered URL is given as input
-        if paste.url.is_empty() {
-            // Even though random collisions are unlikely, it is ensured here that random URLs will be unique
-            let mut random_url = utility::random_string();
-            while database::retrieve_paste(&self.pool, &random_url)
+    pub fn namespace(&self) -> Option<&NamespaceConfig> {
+        self.namespace.as_deref()
+    }
+
+    /// The path the manager's pages and API are served under, empty outside of namespaces
+    pub fn base_path(&self) -> String {
+        match &self.namespace {
+            Some(namespace) => format!("/{}", namespace.name),
+            None => String::new(),
+        }
+    }
+
+    /// The token for the admin endpoints, the namespace's own one within namespaces
+    pub fn admin_token(&self) -> Option<&String> {
+        match &self.namespace {
+            Some(namespace) => namespace.admin_token.as_ref(),
+            None => self.config.admin_token.as_ref(),
+        }
+    }
+
+    /// The longest a paste's content can be, in bytes, which namespaces may lower
+    fn max_content_length(&self) -> usize {
+        match &self.namespace {
+            Some(namespace) => namespace
+                .max_content_length
+                .map_or(MAX_CONTENT_LENGTH, |max| max.min(MAX_CONTENT_LENGTH)),
+            None => MAX_CONTENT_LENGTH,
+        }
+    }
+
+    /// How much of the namespace's quota is used. Outside of namespaces, nothing is limited
+    pub async fn quota(&self) -> Result<Quota, PasteError> {
+        let name = self
+            .namespace
+            .as_ref()
+            .map(|namespace| namespace.name.as_str());
+        let (pastes, bytes) = match database::retrieve_usage(&self.pool, name).await {
+            Ok(usage) => usage,
+            Err(e) => return Err(PasteError::Database(e)),
+        };
+        let max_pastes = self
+            .namespace
+            .as_ref()
+            .and_then(|namespace| namespace.max_pastes);
+        let max_bytes = self
+            .namespace
+            .as_ref()
+            .and_then(|namespace| namespace.max_bytes);
+        Ok(Quota {
+            pastes,
+            bytes,
+            max_pastes,
+            max_bytes,
+            remaining_pastes: max_pastes.map(|max| (max - pastes).max(0)),
+            remaining_bytes: max_bytes.map(|max| (max - bytes).max(0)),
+        })
+    }
+
+    /// Throws an error if adding `pastes` pastes and `bytes` bytes of content would exceed the namespace's quota
+    async fn check_quota(&self, pastes: i64, bytes: i64) -> Result<(), PasteError> {
+        let limited = self.namespace.as_ref().is_some_and(|namespace| {
+            namespace.max_pastes.is_some() || namespace.max_bytes.is_some()
+        });
+        if !limited {
+            return Ok(());
+        }
+        let quota = self.quota().await?;
+        let fits = |remaining: Option<i64>, added: i64| {
+            remaining.is_none_or(|remaining| added <= remaining)
+        };
+        match fits(quota.remaining_pastes, pastes) && fits(quota.remaining_bytes, bytes) {
+            true => Ok(()),
+            false => Err(PasteError::QuotaExceeded),
+        }
+    }
+
+    /// Whether a stored URL belongs to the manager's namespace. Outside of namespaces, every paste does
+    fn owns(&self, url: &str) -> bool {
+        match &self.namespace {
+            Some(namespace) => url
+                .strip_prefix(namespace.name.as_str())
+                .is_some_and(|rest| rest.starts_with('/')),
+            None => true,
+        }
+    }
+
+    /// A URL as it is stored, with the namespace prepended unless it already is
+    fn scoped_url(&self, url: &str) -> String {
+        match &self.namespace {
+            Some(namespace) if !self.owns(url) => format!("{}/{url}", namespace.name),
+            _ => url.to_string(),
+        }
+    }
+
+    /// The part of a URL after its namespace
+    fn local_url<'a>(&self, url: &'a str) -> &'a str {
+        match &self.namespace {
+            Some(namespace) if self.owns(url) => &url[namespace.name.len() + 1..],
+            _ => url,
+        }
+    }
+
+    /// Checks a URL given for a paste, which also can't be the name of a namespace or of one of pasties' own routes, as the namespace's pages or the route would hide it
+    fn new_url(&self, url: String) -> Result<PasteUrl, PasteError> {
+        let url = PasteUrl::new(url)?;
+        let is_reserved = routing::RESERVED_PATHS.contains(&url.as_str())
+            || api::RESERVED_URLS.contains(&url.as_str());
+        match self.is_namespace_name(url.as_str()) || is_reserved {
+            true => Err(PasteError::InvalidUrl),
+            false => Ok(url),
+        }
+    }
+
+    /// Whether a URL is taken by a namespace, whose routes would shadow a paste with that URL
+    fn is_namespace_name(&self, url: &str) -> bool {
+        self.namespace.is_none()
+            && self
+                .config
+                .namespaces
+                .iter()
+                .any(|namespace| namespace.name == url)
+    }
+
+    /// Receives an event for everything that happens to pastes from now on
+    pub fn subscribe(&self) -> Receiver<PasteEvent> {
+        self.events.subscribe()
+    }
+
+    /// How long the instance has been running, in seconds
+    pub fn uptime(&self) -> i64 {
+        utility::unix_timestamp() - self.started
+    }
+
+    /// The format pastes fall back to if they don't specify one and it can't be detected
+    pub fn default_format(&self) -> &'static str {
+        self.renderers.default_format()
+    }
+
+    /// The queue of deferred work, see the `jobs` module
+    pub fn jobs(&self) -> &JobQueue {
+        &self.jobs
+    }
+
+    /// The IPFS node pastes are published to, if `[ipfs]` is configured
+    pub fn ipfs(&self) -> Option<&Ipfs> {
+        self.ipfs.as_ref()
+    }
+
+    /// The image proxy, if `[image_proxy]` is configured
+    pub fn image_proxy(&self) -> Option<&ImageProxy> {
+        self.image_proxy.as_ref()
+    }
+
+    /// Signing in, if `[sso]` is configured
+    pub fn sso(&self) -> Option<&Sso> {
+        self.sso.as_ref()
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn formats(&self) -> Vec<&'static str> {
+        self.renderers.formats()
+    }
+
+    /// The format content is detected as, the default format if it can't be
+    pub fn detect_format(&self, content: &str) -> &'static str {
+        self.renderers.detect(content)
+    }
+
+    /// Renders a paste's content, with its links checked if `[link_safety]` is configured and external links marked as `nofollow`
+    pub async fn render(&self, paste: &PasteReturn) -> String {
+        let mut html = match (&paste.rendered_html, self.hooks.is_empty()) {
+            (Some(html), true) => html.clone(),
+            (None, true) => self.render_html(paste).await,
+            (_, false) => {
+                let mut paste = paste.clone();
+                self.hooks.before_render(&mut paste);
+                self.render_html(&paste).await
+            }
+        };
+        if let Some(link_checker) = &self.link_checker {
+            let targets = links::link_targets(&html);
+            let unsafe_links = link_checker.unsafe_links(&targets).await;
+            html = link_checker.neutralize(html, &unsafe_links);
+        }
+        if let Some(image_proxy) = &self.image_proxy {
+            html = image_proxy.rewrite_images(html);
+        }
+        let mut html = links::rewrite_external(html, self.config.link_interstitial);
+        self.hooks.after_render(paste, &mut html);
+        html
+    }
+
+    /// Turns a paste's UUID into its current URL, and qualifies URLs with the manager's namespace
+    /// Unknown UUIDs, and those of pastes in other namespaces, are returned as is, so looking them up fails like for any missing paste
+    pub async fn resolve_url(&self, url: String) -> String {
+        if let Ok(uuid) = Uuid::parse_str(&url) {
+            let uuid = uuid.hyphenated().to_string();
+            if let Ok(resolved) = database::retrieve_url_by_uuid(&self.read_pool, &uuid).await {
+                if self.owns(&resolved) {
+                    return resolved;
+                }
+            }
+        }
+        self.scoped_url(&url)
+    }
+
+    /// Looks up the current URL of a paste by its base62 ID
+    pub async fn url_of_id(&self, id: &str) -> Result<String, PasteError> {
+        let Some(id) = utility::from_base62(id) else {
+            return Err(PasteError::NotFound);
+        };
+        match database::retrieve_url_by_id(&self.read_pool, id).await {
+            Ok(url) => Ok(url),
+            Err(_) => Err(PasteError::NotFound),
+        }
+    }
+
+    /// Renders content ahead of views, as far as the HTML only depends on the content
+    /// Markdown with wiki links is rendered on every view, as the links are styled by whether their pastes exist, and so is content in formats without a renderer
+    fn prerender(&self, format: &str, content: &str) -> Option<String> {
+        let has_wiki_links = format == "markdown" && !markdown::wiki_links(content).is_empty();
+        match self.renderers.supports(format) && !has_wiki_links {
+            true => Some(self.renderers.render(format, content)),
+            false => None,
+        }
+    }
+
+    /// Renders and stores the content of pastes that weren't rendered by this build yet, a batch at a time
+    /// Another `Job::RenderPastes` is queued as long as there may be more
+    pub async fn prerender_pastes(&self) -> Result<(), PasteError> {
+        let pastes =
+            match database::retrieve_unrendered_pastes(&self.pool, PRERENDER_BATCH_SIZE).await {
+                Ok(pastes) => pastes,
+                Err(e) => return Err(PasteError::Database(e)),
+            };
+        for paste in &pastes {
+            let rendered_html = self.prerender(&paste.format, &paste.content);
+            if let Err(e) =
+                database::set_rendered_html(&self.pool, &paste.url, &paste.content, rendered_html)
+                    .await
+            {
+                return Err(PasteError::Database(e));
+            }
+        }
+        if pastes.len() as i64 == PRERENDER_BATCH_SIZE {
+            if let Err(e) = self
+                .jobs
+                .enqueue(&Job::RenderPastes, utility::unix_timestamp())
                 .await
-                .is_ok()
             {
-                random_url = utility::random_string()
+                return Err(PasteError::Database(e));
             }
-            paste.url = random_url
-        } else if database::retrieve_paste(&self.pool, &paste.url)
-            .await
-            .is_ok()
-        {
-            return Err(PasteError::AlreadyExists);
         }
+        Ok(())
+    }
+
+    /// Queues rendering the pastes this build hasn't rendered yet, unless it is queued already
+    pub async fn schedule_prerendering(&self) {
+        let queued = self.jobs.due_at(&Job::RenderPastes).await;
+        if let Ok(None) = queued {
+            if let Err(e) = self
+                .jobs
+                .enqueue(&Job::RenderPastes, utility::unix_timestamp())
+                .await
+            {
+                tracing::warn!("Failed to queue rendering the pastes: {e:?}");
+            }
+        }
+    }
+
+    /// Wiki links in markdown pastes are checked against the database, so links to missing pastes can be marked
+    async fn render_html(&self, paste: &PasteReturn) -> String {
+        if paste.format != "markdown" {
+            return self.renderers.render(&paste.format, &paste.content);
+        }
+        let links = markdown::wiki_links(&paste.content);
+        if links.is_empty() {
+            return self.renderers.render(&paste.format, &paste.content);
+        }
+        // Links point to pastes in the same namespace, so they are looked up by their stored URLs
+        let links: Vec<String> = links.iter().map(|url| self.scoped_url(url)).collect();
+        // Links are rendered as existing if the lookup fails, it only decides how they are styled
+        let existing = match database::retrieve_existing_urls(&self.read_pool, &links).await {
+            Ok(existing) => existing,
+            Err(_) => links,
+        };
+        markdown::render_markdown_with_links(&paste.content, &self.base_path(), |url| {
+            existing.contains(&self.scoped_url(url))
+        })
+    }
+
+    /// Rejects content that looks like it contains credentials, if the instance is configured to block them
+    fn check_secrets(&self, content: &str) -> Result<(), PasteError> {
+        if !self.config.block_secrets {
+            return Ok(());
+        }
+        match secrets::warning(content) {
+            Some(warning) => Err(PasteError::ContainsSecret(warning)),
+            None => Ok(()),
+        }
+    }
 
-        // Provide a default password, or throw an error if the one given as input is too long
-        if paste.password.is_empty() {
-            paste.password = utility::random_string();
-        } else if paste.password.len() > 250 {
-            return Err(PasteError::InvalidPassword);
+    /// Refuses content that violates a rejecting `[[filters]]` policy
+    fn check_filters(&self, content: &str) -> Result<(), PasteError> {
+        match self.filters.rejects(content) {
+            Some(policy) => Err(PasteError::Filtered(policy.to_string())),
+            None => Ok(()),
         }
+    }
 
-        // Check the content's length
-        if paste.content.is_empty() || paste.content.len() > 200_000 {
-            return Err(PasteError::InvalidContent);
+    /// Whether the password is the paste's, or the paste was claimed by the user signed in for the current request
+    fn is_authorized(paste: &DatabasePaste, password: &str) -> bool {
+        paste.password_hash == hash_string(password.to_owned())
+            || paste
+                .owner
+                .as_ref()
+                .is_some_and(|owner| sso::current_user().as_ref() == Some(owner))
+    }
+
+    /// Whether content violates a quarantining `[[filters]]` policy, which is logged for the admins
+    fn is_quarantined(&self, url: &str, content: &str) -> bool {
+        match self.filters.quarantines(content) {
+            Some(policy) => {
+                tracing::info!("Quarantined {url}, as it violates the \"{policy}\" policy");
+                true
+            }
+            None => false,
         }
+    }
 
-        let new_paste: DatabasePaste = paste.into();
+    /// Turns prepared data into a paste record, quarantined if a policy says so
+    fn new_record(&self, paste: NewPasteData) -> DatabasePaste {
+        let mut record = DatabasePaste::from(paste);
+        record.quarantined = self.is_quarantined(&record.url, &record.content);
+        record.rendered_html = self.prerender(&record.format, &record.content);
+        record
+    }
+
+    /// Fills in the format detected from the content if none is given, or throws an error if the given one has no renderer
+    fn validate_format(&self, format: &mut String, content: &str) -> Result<(), PasteError> {
+        if format.is_empty() {
+            *format = self.detect_format(content).to_string();
+        }
+        match self.renderers.supports(format) {
+            true => Ok(()),
+            false => Err(PasteError::InvalidFormat),
+        }
+    }
+    /// Creates a paste, returning its URL, which is random if none was given
+    pub async fn create_paste(&self, paste: NewPasteData) -> Result<String, PasteError> {
+        let paste = self.prepare_paste(paste).await?;
+        self.check_quota(1, paste.content.len() as i64).await?;
+        let url = paste.url.clone();
+        let expires_in = paste.expires_in;
+        let new_paste = self.new_record(paste);
+        let hidden = new_paste.draft || new_paste.quarantined;
+        let id = new_paste.id;
+        let created = match self.hooks.is_empty() {
+            true => None,
+            false => Some(PasteReturn::from(new_paste.clone())),
+        };
 
         match database::insert_paste(&self.pool, new_paste).await {
-            Ok(_) => Ok(()),
+            Ok(_) => {
+                self.schedule_expiry(id, expires_in).await;
+                self.consume_reservation(&url).await;
+                if let Some(created) = &created {
+                    self.hooks.paste_created(created);
+                }
+                self.events.publish(PasteEvent::Created {
+                    url: url.clone(),
+                    hidden,
+                });
+                Ok(url)
+            }
             Err(e) => Err(PasteError::Database(e)),
         }
     }
-    pub async fn update_paste(
+
+    /// Creates a paste imported from another pastebin, published and last edited at the given unix timestamps
+    /// A UUID from the other pastebin is kept, so its links keep working through `resolve_url`
+    /// Imports aren't announced to hooks, webhooks or the event stream, as the pastes aren't new, and don't count against quotas
+    pub async fn import_paste(
         &self,
-        paste_credentials: PasteCredentials,
-        mut paste: NewPasteData,
-    ) -> Result<(), PasteError> {
-        let existing_paste =
-            match database::retrieve_paste(&self.pool, &paste_credentials.url).await {
-                Ok(paste) => paste,
-                Err(_) => return Err(PasteError::NotFound),
-            };
-        if existing_paste.password_hash
-            != utility::hash_string(paste_credentials.password.to_owned())
-        {
-            return Err(PasteError::IncorrectPassword);
+        paste: NewPasteData,
+        uuid: Option<Uuid>,
+        date_published: i64,
+        date_edited: i64,
+    ) -> Result<CreatedPaste, PasteError> {
+        let paste = self.prepare_paste(paste).await?;
+        let password = paste.password.clone();
+        let expires_in = paste.expires_in;
+        let mut record = self.new_record(paste);
+        if let Some(uuid) = uuid {
+            record.uuid = uuid.hyphenated().to_string();
+            if database::retrieve_url_by_uuid(&self.pool, &record.uuid)
+                .await
+                .is_ok()
+            {
+                return Err(PasteError::AlreadyExists);
+            }
         }
-        if paste.url.is_empty() {
-            paste_credentials.url.clone_into(&mut paste.url)
+        record.date_published = date_published;
+        record.date_edited = date_edited;
+        let created = CreatedPaste {
+            url: record.url.clone(),
+            id: utility::to_base62(record.id),
+            uuid: record.uuid.clone(),
+            password,
+        };
+        let id = record.id;
+        match database::insert_paste(&self.pool, record).await {
+            Ok(_) => {
+                self.schedule_expiry(id, expires_in).await;
+                Ok(created)
+            }
+            Err(e) => Err(PasteError::Database(e)),
         }
-        if !paste.password.is_empty() && paste.password.len() > 250 {
-            return Err(PasteError::InvalidPassword);
+    }
+
+    /// Creates a paste unless a request with the same idempotency key already did, in which case that paste's URL is returned
+    /// Keys are remembered for a day per namespace, and reusing one for a request with different data is an error
+    /// The key is claimed before the paste is created, so concurrent retries can't both create it
+    pub async fn create_paste_idempotently(
+        &self,
+        key: &str,
+        paste: NewPasteData,
+    ) -> Result<String, PasteError> {
+        if key.is_empty() || key.len() > 255 {
+            return Err(PasteError::InvalidIdempotencyKey);
         }
-        let password_hash = match paste.password.is_empty() {
-            true => hash_string(paste_credentials.password),
-            false => hash_string(paste.password),
+        // Header values can't contain NUL, so keys of different namespaces can't collide
+        let key = match &self.namespace {
+            Some(namespace) => format!("{}\0{key}", namespace.name),
+            None => key.to_string(),
         };
-        // Check the content's length
-        if paste.content.is_empty() || paste.content.len() > 200_000 {
-            return Err(PasteError::InvalidContent);
+        let fingerprint = hash_string(format!(
+            "{}\0{}\0{}\0{}",
+            paste.url, paste.password, paste.format, paste.content
+        ));
+        let since = utility::unix_timestamp() - IDEMPOTENCY_KEY_LIFETIME;
+        match database::claim_idempotency_key(&self.pool, &key, &fingerprint, since).await {
+            Ok(true) => {}
+            Ok(false) => {
+                return match database::retrieve_idempotency_key(&self.pool, &key).await {
+                    Ok(Some(entry)) if entry.fingerprint != fingerprint => {
+                        Err(PasteError::InvalidIdempotencyKey)
+                    }
+                    Ok(Some(IdempotencyKey { url: Some(url), .. })) => Ok(url),
+                    // The request that claimed the key is still creating the paste, or just gave up
+                    Ok(_) => Err(PasteError::IdempotencyKeyInUse),
+                    Err(e) => Err(PasteError::Database(e)),
+                };
+            }
+            Err(e) => return Err(PasteError::Database(e)),
         }
-
-        let updated_paste = PartialDatabasePaste {
-            url: paste.url,
-            content: paste.content,
-            password_hash,
-            date_edited: utility::unix_timestamp(),
+        let url = match self.create_paste(paste).await {
+            Ok(url) => url,
+            Err(e) => {
+                if let Err(e) = database::release_idempotency_key(&self.pool, &key).await {
+                    tracing::warn!("Failed to release an idempotency key: {e:?}");
+                }
+                return Err(e);
+            }
         };
-        match database::update_paste(&self.pool, paste_credentials.url, updated_paste).await {
-            Ok(_) => Ok(()),
+        match database::complete_idempotency_key(&self.pool, &key, &url).await {
+            Ok(_) => Ok(url),
             Err(e) => Err(PasteError::Database(e)),
         }
     }
 
-    pub async fn delete_paste(&self, paste: PasteCredentials) -> Result<(), PasteError> {
-        let existing_paste = match database::retrieve_paste(&self.pool, &paste.url).await {
-            Ok(paste) => paste,
-            Err(_) => return Err(PasteError::NotFound),
+    /// Creates several pastes at once, with one result per paste in the same order
+    /// The valid pastes are inserted in a single transaction, so either all of them are created or none are
+    pub async fn create_pastes(
+        &self,
+        pastes: Vec<NewPasteData>,
+    ) -> Vec<Result<CreatedPaste, PasteError>> {
+        let mut results = Vec::with_capacity(pastes.len());
+        let mut prepared = Vec::new();
+        for paste in pastes {
+            let result = match self.prepare_paste(paste).await {
+                // URLs have to be unique within the batch as well
+                Ok(paste)
+                    if prepared
+                        .iter()
+                        .any(|other: &NewPasteData| other.url == paste.url) =>
+                {
+                    Err(PasteError::AlreadyExists)
+                }
+                Ok(paste) => {
+                    prepared.push(paste);
+                    Ok(())
+                }
+                Err(e) => Err(e),
+            };
+            results.push(result);
+        }
+        let bytes = prepared
+            .iter()
+            .map(|paste| paste.content.len() as i64)
+            .sum();
+        if let Err(e) = self.check_quota(prepared.len() as i64, bytes).await {
+            let quota_exceeded = matches!(e, PasteError::QuotaExceeded);
+            return results
+                .into_iter()
+                .map(|result| match result {
+                    Ok(()) if quota_exceeded => Err(PasteError::QuotaExceeded),
+                    Ok(()) => Err(PasteError::Database(DatabaseError::Transaction)),
+                    Err(e) => Err(e),
+                })
+                .collect();
+        }
+        let passwords: Vec<String> = prepared
+            .iter()
+            .map(|paste| paste.password.clone())
+            .collect();
+        let expiries: Vec<Option<i64>> = prepared.iter().map(|paste| paste.expires_in).collect();
+        let new_pastes: Vec<DatabasePaste> = prepared
+            .into_iter()
+            .map(|paste| self.new_record(paste))
+            .collect();
+        let events: Vec<PasteEvent> = new_pastes
+            .iter()
+            .map(|paste| PasteEvent::Created {
+                url:    paste.url.clone(),
+                hidden: paste.draft || paste.quarantined,
+            })
+            .collect();
+        let created: Vec<CreatedPaste> = new_pastes
+            .iter()
+            .zip(passwords)
+            .map(|(paste, password)| CreatedPaste {
+                url: paste.url.clone(),
+                id: utility::to_base62(paste.id),
+                uuid: paste.uuid.clone(),
+                password,
+            })
+            .collect();
+        let returned: Vec<PasteReturn> = match self.hooks.is_empty() {
+            true => Vec::new(),
+            false => new_pastes.iter().cloned().map(PasteReturn::from).collect(),
         };
-        if existing_paste.password_hash != hash_string(paste.password) {
-            return Err(PasteError::IncorrectPassword);
+        let ids: Vec<i64> = new_pastes.iter().map(|paste| paste.id).collect();
+        let inserted = database::insert_pastes(&self.pool, new_pastes).await;
+        if inserted.is_ok() {
+            for (id, expires_in) in ids.into_iter().zip(expiries) {
+                self.schedule_expiry(id, expires_in).await;
+            }
+            for paste in &created {
+                self.consume_reservation(&paste.url).await;
+            }
+            for paste in &returned {
+                self.hooks.paste_created(paste);
+            }
+            for event in events {
+                self.events.publish(event);
+            }
         }
-        match database::delete_paste(&self.pool, &paste.url).await {
+        let mut created = created.into_iter();
+        results
+            .into_iter()
+            .map(|result| match (result, &inserted) {
+                (Ok(()), Ok(())) => Ok(created.next().unwrap()),
+                (Ok(()), Err(_)) => Err(PasteError::Database(DatabaseError::Transaction)),
+                (Err(e), _) => Err(e),
+            })
+            .collect()
+    }
+
+    /// Reserves a URL for a paste that will be created later, so that nobody else can take it in the meantime
+    /// The paste has to be created with the reservation's password, which is random unless chosen
+    pub async fn reserve_url(
+        &self,
+        reservation: ReservationRequest,
+    ) -> Result<Reservation, PasteError> {
+        let local_url =
+            utility::normalize_url(self.local_url(&reservation.url), self.config.lowercase_urls);
+        let url = self.scoped_url(self.new_url(local_url)?.as_str());
+        if database::retrieve_paste(&self.pool, &url).await.is_ok() {
+            return Err(PasteError::AlreadyExists);
+        }
+        let password = match reservation.password.is_empty() {
+            true => PastePassword::random(),
+            false => PastePassword::new(reservation.password)?,
+        };
+        let expires_in = match reservation.expires_in {
+            Some(expires_in) if expires_in < 1 => return Err(PasteError::InvalidExpiry),
+            Some(expires_in) => expires_in.min(MAX_RESERVATION_LIFETIME),
+            None => RESERVATION_LIFETIME,
+        };
+        let expires = utility::unix_timestamp() + expires_in;
+        match database::insert_reservation(&self.pool, &url, &password.hash(), expires).await {
+            Ok(true) => Ok(Reservation {
+                url,
+                password: password.into_string(),
+                expires,
+            }),
+            Ok(false) => Err(PasteError::UrlReserved),
+            Err(e) => Err(PasteError::Database(e)),
+        }
+    }
+
+    /// Throws an error if the URL is reserved with a different password than the one given
+    async fn check_reservation(&self, url: &str, password: &str) -> Result<(), PasteError> {
+        match database::retrieve_reservation(&self.pool, url).await {
+            Ok(Some(password_hash)) if password_hash != hash_string(password.to_owned()) => {
+                Err(PasteError::UrlReserved)
+            }
             Ok(_) => Ok(()),
             Err(e) => Err(PasteError::Database(e)),
         }
     }
 
-    pub async fn retrieve_paste(&self, url: String) -> Result<PasteReturn, PasteError> {
-        match database::retrieve_paste(&self.pool, &url).await {
-            Ok(database_paste) => Ok(PasteReturn::from(database_paste)),
-            Err(_) => Err(PasteError::NotFound),
+    /// Forgets the reservation of a URL a paste has been created with. The reservation would expire anyway, so failing to is only logged
+    async fn consume_reservation(&self, url: &str) {
+        if let Err(e) = database::delete_reservation(&self.pool, url).await {
+            tracing::warn!("Failed to remove the reservation of {url}: {e:?}");
+        }
+    }
+
+    /// Validates a new paste and fills in its defaults, without creating it
+    async fn prepare_paste(&self, mut paste: NewPasteData) -> Result<NewPasteData, PasteError> {
+        let local_url =
+            utility::normalize_url(self.local_url(&paste.url), self.config.lowercase_urls);
+        // Provide a default URL if it is empty, or throw an error if an already registered URL is given as input
+        if local_url.is_empty() {
+            // Even though random collisions are unlikely, it is ensured here that random URLs will be unique
+            let mut random_url = self.scoped_url(PasteUrl::random().as_str());
+            while database::retrieve_paste(&self.pool, &random_url)
+                .await
+                .is_ok()
+            {
+                random_url = self.scoped_url(PasteUrl::random().as_str())
+            }
+            paste.url = random_url
+        } else {
+            let url = self.new_url(local_url)?;
+            paste.url = self.scoped_url(url.as_str());
+            if database::retrieve_paste(&self.pool, &paste.url)
+                .await
+                .is_ok()
+            {
+                return Err(PasteError::AlreadyExists);
+            }
+            self.check_reservation(&paste.url, &paste.password).await?;
+        }
+
+        // Provide a default password if it is empty
+        let password = match paste.password.is_empty() {
+            true => PastePassword::random(),
+            false => PastePassword::new(paste.password)?,
+        };
+        paste.password = password.into_string();
+
+        let content = PasteContent::new(paste.content, self.max_content_length())?;
+        self.check_secrets(content.as_str())?;
+        self.check_filters(content.as_str())?;
+        paste.content = content.into_string();
+        if paste.max_views.is_some_and(|max_views| max_views < 1) {
+            return Err(PasteError::InvalidMaxViews);
+        }
+        if paste.expires_in.is_some_and(|expires_in| expires_in < 1) {
+            return Err(PasteError::InvalidExpiry);
+        }
+        if let Some(max_expiry) = self.config.max_expiry {
+            paste.expires_in = paste
+                .expires_in
+                .map(|expires_in| expires_in.min(max_expiry));
+        }
+        self.validate_format(&mut paste.format, &paste.content)?;
+        Ok(paste)
+    }
+
+    /// Replaces a paste's content and settings, returning the URL it is stored under afterwards, which changes if it is renamed
+    pub async fn update_paste(
+        &self,
+        paste_credentials: PasteCredentials,
+        mut paste: NewPasteData,
+    ) -> Result<String, PasteError> {
+        let existing_paste =
+            match database::retrieve_paste(&self.pool, &paste_credentials.url).await {
+                Ok(paste) => paste,
+                Err(_) => return Err(PasteError::NotFound),
+            };
+        if !Self::is_authorized(&existing_paste, &paste_credentials.password) {
+            return Err(PasteError::IncorrectPassword);
+        }
+        if existing_paste.date_locked.is_some() {
+            return Err(PasteError::Locked);
+        }
+        // New URLs are normalized like those of new pastes, so renaming can't get around `lowercase_urls`
+        let local_url =
+            utility::normalize_url(self.local_url(&paste.url), self.config.lowercase_urls);
+        if local_url.is_empty() {
+            paste_credentials.url.clone_into(&mut paste.url)
+        } else if self.scoped_url(&local_url) != paste_credentials.url {
+            let url = self.new_url(local_url)?;
+            paste.url = self.scoped_url(url.as_str());
+            if database::retrieve_paste(&self.pool, &paste.url)
+                .await
+                .is_ok()
+            {
+                return Err(PasteError::AlreadyExists);
+            }
+            let password = match paste.password.is_empty() {
+                true => &paste_credentials.password,
+                false => &paste.password,
+            };
+            self.check_reservation(&paste.url, password).await?;
+        } else {
+            paste_credentials.url.clone_into(&mut paste.url)
+        }
+        let style = paste.style(existing_paste.style);
+        // The password is kept unless a new one is given
+        let password_hash = match paste.password.is_empty() {
+            true => existing_paste.password_hash,
+            false => PastePassword::new(paste.password)?.hash(),
+        };
+        let content = PasteContent::new(paste.content, self.max_content_length())?;
+        self.check_secrets(content.as_str())?;
+        self.check_filters(content.as_str())?;
+        let added_bytes = content.as_str().len() as i64 - existing_paste.content.len() as i64;
+        self.check_quota(0, added_bytes).await?;
+        // Keep the existing format unless a new one is given
+        if paste.format.is_empty() {
+            paste.format = existing_paste.format;
+        }
+        self.validate_format(&mut paste.format, content.as_str())?;
+
+        // Cleaning up a quarantined paste doesn't release it, only an admin can
+        let quarantined =
+            existing_paste.quarantined || self.is_quarantined(&paste.url, content.as_str());
+        let updated_paste = PartialDatabasePaste {
+            stats: ContentStats::of(content.as_str()),
+            rendered_html: self.prerender(&paste.format, content.as_str()),
+            style,
+            quarantined,
+            url: paste.url,
+            content,
+            format: paste.format,
+            password_hash,
+            date_edited: utility::unix_timestamp(),
+        };
+        let url = updated_paste.url.clone();
+        match database::update_paste(&self.pool, paste_credentials.url.clone(), updated_paste).await
+        {
+            Ok(_) => {
+                if url != paste_credentials.url {
+                    self.consume_reservation(&url).await;
+                }
+                self.hooks.paste_updated(&paste_credentials.url, &url);
+                self.events.publish(PasteEvent::Updated {
+                    previous_url: paste_credentials.url,
+                    url:          url.clone(),
+                });
+                Ok(url)
+            }
+            Err(e) => Err(PasteError::Database(e)),
+        }
+    }
+
+    /// Applies only the given changes to a paste, e.g. to rename it without sending its content again
+    pub async fn patch_paste(
+        &self,
+        paste_credentials: PasteCredentials,
+        changes: PasteChanges,
+    ) -> Result<(), PasteError> {
+        let content = match changes.content {
+            Some(content) => content,
+            None => match database::retrieve_paste(&self.pool, &paste_credentials.url).await {
+                Ok(paste) => paste.content,
+                Err(_) => return Err(PasteError::NotFound),
+            },
+        };
+        let paste = NewPasteData {
+            url: changes.url.unwrap_or_default(),
+            content,
+            password: changes.password.unwrap_or_default(),
+            format: changes.format.unwrap_or_default(),
+            draft: false,
+            max_views: None,
+            expires_in: None,
+            font: changes.font,
+            width: changes.width,
+            wrap: changes.wrap,
+        };
+        self.update_paste(paste_credentials, paste)
+            .await
+            .map(|_| ())
+    }
+
+    pub async fn delete_paste(&self, paste: PasteCredentials) -> Result<(), PasteError> {
+        let existing_paste = match database::retrieve_paste(&self.pool, &paste.url).await {
+            Ok(paste) => paste,
+            Err(_) => return Err(PasteError::NotFound),
+        };
+        if !Self::is_authorized(&existing_paste, &paste.password) {
+            return Err(PasteError::IncorrectPassword);
+        }
+        match database::delete_paste(&self.pool, &paste.url).await {
+            Ok(_) => {
+                self.hooks.paste_deleted(&paste.url);
+                self.events.publish(PasteEvent::Deleted { url: paste.url });
+                Ok(())
+            }
+            Err(e) => Err(PasteError::Database(e)),
+        }
+    }
+
+    /// Queues the deletion of a new paste, if it expires
+    /// The paste has already been created, so failing to queue its deletion is only logged
+    async fn schedule_expiry(&self, id: i64, expires_in: Option<i64>) {
+        let Some(expires_in) = expires_in else {
+            return;
+        };
+        let run_at = utility::unix_timestamp().saturating_add(expires_in);
+        if let Err(e) = self.jobs.enqueue(&Job::DeletePaste { id }, run_at).await {
+            tracing::error!("Failed to schedule the deletion of a paste: {e:?}");
+        }
+    }
+
+    /// Pushes back when an expiring paste is deleted, by `by` seconds but no further than `max_expiry` from now
+    /// Returns the unix timestamp the paste now expires at
+    pub async fn extend_expiry(&self, paste: PasteCredentials, by: i64) -> Result<i64, PasteError> {
+        if by < 1 {
+            return Err(PasteError::InvalidExpiry);
+        }
+        let existing_paste = match database::retrieve_paste(&self.pool, &paste.url).await {
+            Ok(paste) => paste,
+            Err(_) => return Err(PasteError::NotFound),
+        };
+        if !Self::is_authorized(&existing_paste, &paste.password) {
+            return Err(PasteError::IncorrectPassword);
+        }
+        let job = Job::DeletePaste {
+            id: existing_paste.id,
+        };
+        let expires = match self.jobs.due_at(&job).await {
+            Ok(Some(expires)) => expires,
+            Ok(None) => return Err(PasteError::NotExpiring),
+            Err(e) => return Err(PasteError::Database(e)),
+        };
+        let mut extended = expires.saturating_add(by);
+        if let Some(max_expiry) = self.config.max_expiry {
+            // An expiry beyond the limit, set before the limit was, isn't shortened
+            let limit = utility::unix_timestamp().saturating_add(max_expiry);
+            extended = extended.min(limit.max(expires));
+        }
+        match self.jobs.reschedule(&job, extended).await {
+            Ok(_) => Ok(extended),
+            Err(e) => Err(PasteError::Database(e)),
+        }
+    }
+
+    /// Deletes an expired paste by its internal ID. A paste that is already gone counts as deleted
+    pub async fn expire_paste(&self, id: i64) -> Result<(), PasteError> {
+        let url = match database::retrieve_url_by_id(&self.pool, id).await {
+            Ok(url) => url,
+            Err(DatabaseError::Retrieval(sqlx::Error::RowNotFound)) => return Ok(()),
+            Err(e) => return Err(PasteError::Database(e)),
+        };
+        match database::delete_paste(&self.pool, &url).await {
+            Ok(_) => {
+                self.hooks.paste_deleted(&url);
+                self.events.publish(PasteEvent::Deleted { url });
+                Ok(())
+            }
+            Err(e) => Err(PasteError::Database(e)),
+        }
+    }
+
+    /// The announcement banner shown on every page, unless none is set or it has expired
+    pub fn banner(&self) -> Option<Banner> {
+        self.banner.get()
+    }
+
+    /// Sets the announcement banner, hidden after `expires_in` seconds if given
+    pub async fn set_banner(
+        &self,
+        message: String,
+        severity: Severity,
+        expires_in: Option<i64>,
+    ) -> Result<Banner, PasteError> {
+        if message.trim().is_empty() || expires_in.is_some_and(|expires_in| expires_in < 1) {
+            return Err(PasteError::InvalidBanner);
+        }
+        let banner = Banner {
+            message,
+            severity,
+            expires: expires_in.map(|expires_in| utility::unix_timestamp() + expires_in),
+        };
+        match database::set_banner(&self.pool, &(&banner).into()).await {
+            Ok(()) => {
+                self.banner.set(Some(banner.clone()));
+                Ok(banner)
+            }
+            Err(e) => Err(PasteError::Database(e)),
+        }
+    }
+
+    /// Removes the announcement banner
+    pub async fn clear_banner(&self) -> Result<(), PasteError> {
+        match database::delete_banner(&self.pool).await {
+            Ok(()) => {
+                self.banner.set(None);
+                Ok(())
+            }
+            Err(e) => Err(PasteError::Database(e)),
+        }
+    }
+
+    /// Deletes several pastes, with one result per paste in the same order
+    /// Every paste is deleted on its own, so one with a wrong password doesn't keep the others from being deleted
+    pub async fn delete_pastes(
+        &self,
+        pastes: Vec<PasteCredentials>,
+    ) -> Vec<Result<(), PasteError>> {
+        let mut results = Vec::with_capacity(pastes.len());
+        for paste in pastes {
+            results.push(self.delete_paste(paste).await);
+        }
+        results
+    }
+
+    /// Creates a collection, within the manager's namespace like pastes are
+    pub async fn create_collection(
+        &self,
+        mut collection: CollectionCredentials,
+    ) -> Result<CollectionCredentials, PasteError> {
+        // Collection names follow the same rules as paste URLs
+        let name = match collection.name.is_empty() {
+            true => {
+                let mut random_name = PasteUrl::random();
+                while database::retrieve_collection_password(
+                    &self.pool,
+                    &self.scoped_url(random_name.as_str()),
+                )
+                .await
+                .is_ok()
+                {
+                    random_name = PasteUrl::random()
+                }
+                random_name
+            }
+            false => {
+                let name = PasteUrl::new(collection.name)?;
+                if database::retrieve_collection_password(
+                    &self.pool,
+                    &self.scoped_url(name.as_str()),
+                )
+                .await
+                .is_ok()
+                {
+                    return Err(PasteError::CollectionAlreadyExists);
+                }
+                name
+            }
+        };
+        collection.name = name.into_string();
+        let password = match collection.password.is_empty() {
+            true => PastePassword::random(),
+            false => PastePassword::new(collection.password)?,
+        };
+        let password_hash = password.hash();
+        collection.password = password.into_string();
+        let name = self.scoped_url(&collection.name);
+        match database::insert_collection(&self.pool, &name, &password_hash).await {
+            Ok(_) => Ok(collection),
+            Err(e) => Err(PasteError::Database(e)),
+        }
+    }
+
+    /// Checks a collection's password, returning the collection's name as it is stored
+    async fn authorize_collection(
+        &self,
+        collection: &CollectionCredentials,
+    ) -> Result<String, PasteError> {
+        let name = self.scoped_url(&collection.name);
+        match database::retrieve_collection_password(&self.pool, &name).await {
+            Ok(password_hash) if password_hash == hash_string(collection.password.clone()) => {
+                Ok(name)
+            }
+            Ok(_) => Err(PasteError::IncorrectPassword),
+            Err(_) => Err(PasteError::CollectionNotFound),
+        }
+    }
+
+    /// Adds an existing paste to the end of a collection
+    pub async fn add_to_collection(
+        &self,
+        collection: CollectionCredentials,
+        url: String,
+    ) -> Result<(), PasteError> {
+        let name = self.authorize_collection(&collection).await?;
+        if database::retrieve_paste(&self.pool, &url).await.is_err() {
+            return Err(PasteError::NotFound);
+        }
+        match database::insert_collection_paste(&self.pool, &name, &url).await {
+            Ok(_) => Ok(()),
+            Err(e) => Err(PasteError::Database(e)),
+        }
+    }
+
+    /// Removes a paste from a collection, the paste itself is kept
+    pub async fn remove_from_collection(
+        &self,
+        collection: CollectionCredentials,
+        url: String,
+    ) -> Result<(), PasteError> {
+        let name = self.authorize_collection(&collection).await?;
+        match database::delete_collection_paste(&self.pool, &name, &url).await {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(PasteError::NotFound),
+            Err(e) => Err(PasteError::Database(e)),
+        }
+    }
+
+    pub async fn retrieve_collection(&self, name: String) -> Result<Collection, PasteError> {
+        let scoped_name = self.scoped_url(&name);
+        if database::retrieve_collection_password(&self.read_pool, &scoped_name)
+            .await
+            .is_err()
+        {
+            return Err(PasteError::CollectionNotFound);
+        }
+        match database::retrieve_collection_pastes(&self.read_pool, &scoped_name).await {
+            Ok(pastes) => Ok(Collection {
+                name,
+                pastes: pastes.into_iter().map(PasteReturn::from).collect(),
+            }),
+            Err(e) => Err(PasteError::Database(e)),
+        }
+    }
+
+    /// Pins a paste to the homepage, or unpins it
+    pub async fn set_pinned(&self, url: &str, pinned: bool) -> Result<(), PasteError> {
+        match database::set_pinned(&self.pool, &self.scoped_url(url), pinned).await {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(PasteError::NotFound),
+            Err(e) => Err(PasteError::Database(e)),
+        }
+    }
+
+    /// Quarantines a paste until an admin reviews it, like a quarantining content filter would
+    pub async fn quarantine_paste(&self, url: &str) -> Result<(), PasteError> {
+        let url = self.scoped_url(url);
+        match database::set_quarantined(&self.pool, &url, true).await {
+            Ok(true) => {
+                tracing::info!("Quarantined {url}, as requested by an admin");
+                self.events.publish(PasteEvent::Changed { url });
+                Ok(())
+            }
+            Ok(false) => Err(PasteError::NotFound),
+            Err(e) => Err(PasteError::Database(e)),
+        }
+    }
+
+    /// Releases a paste from quarantine, so everyone can see it again
+    /// Pastes that aren't drafts are announced as published, as they were left out of the event stream while quarantined
+    pub async fn release_paste(&self, url: &str) -> Result<(), PasteError> {
+        let url = self.scoped_url(url);
+        let paste = match database::retrieve_paste(&self.pool, &url).await {
+            Ok(paste) => paste,
+            Err(_) => return Err(PasteError::NotFound),
+        };
+        if let Err(e) = database::set_quarantined(&self.pool, &url, false).await {
+            return Err(PasteError::Database(e));
+        }
+        match paste.quarantined && !paste.draft {
+            true => self.events.publish(PasteEvent::Published { url }),
+            false => self.events.publish(PasteEvent::Changed { url }),
+        }
+        Ok(())
+    }
+
+    /// The pastes pinned to the homepage, with their content rendered
+    pub async fn pinned_pastes(&self) -> Result<Vec<PasteReturn>, PasteError> {
+        let pastes = match database::retrieve_pinned_pastes(&self.read_pool).await {
+            Ok(pastes) => pastes,
+            Err(e) => return Err(PasteError::Database(e)),
+        };
+        let mut pinned = Vec::with_capacity(pastes.len());
+        // Only the pastes of the homepage's own namespace are shown on it
+        let pastes = pastes.into_iter().filter(|paste| match &self.namespace {
+            Some(_) => self.owns(&paste.url),
+            None => !paste.url.contains('/'),
+        });
+        for paste in pastes {
+            let mut paste = PasteReturn::from(paste);
+            paste.content = self.render(&paste).await;
+            pinned.push(paste);
+        }
+        Ok(pinned)
+    }
+
+    /// Appends a chunk to a paste's content. The content is extended in a single statement, so concurrent appends don't overwrite each other
+    pub async fn append_to_paste(
+        &self,
+        paste: PasteCredentials,
+        chunk: String,
+    ) -> Result<(), PasteError> {
+        let chunk = PasteContent::new(chunk, self.max_content_length())?;
+        self.check_secrets(chunk.as_str())?;
+        self.check_filters(chunk.as_str())?;
+        self.check_quota(0, chunk.as_str().len() as i64).await?;
+        // The append is authorized by the stored hash, which the owner doesn't have to know
+        let password_hash = match sso::current_user() {
+            Some(user) => match database::retrieve_paste(&self.pool, &paste.url).await {
+                Ok(existing) if existing.owner.as_ref() == Some(&user) => existing.password_hash,
+                _ => hash_string(paste.password),
+            },
+            None => hash_string(paste.password),
+        };
+        let date_edited = utility::unix_timestamp();
+        let appended = database::append_to_paste(
+            &self.pool,
+            &paste.url,
+            &password_hash,
+            &chunk,
+            self.max_content_length(),
+            date_edited,
+        )
+        .await;
+        match appended {
+            Ok(Some(appended)) => {
+                if self.is_quarantined(&paste.url, chunk.as_str()) {
+                    if let Err(e) = database::set_quarantined(&self.pool, &paste.url, true).await {
+                        return Err(PasteError::Database(e));
+                    }
+                }
+                // Left to `Job::RenderPastes` if it fails, as views render the content themselves until then
+                let rendered_html = self.prerender(&appended.format, &appended.content);
+                if let Err(e) = database::set_rendered_html(
+                    &self.pool,
+                    &paste.url,
+                    &appended.content,
+                    rendered_html,
+                )
+                .await
+                {
+                    tracing::warn!(
+                        "Failed to store the rendered content of {}: {e:?}",
+                        paste.url
+                    );
+                }
+                let content = appended.content;
+                let stats = ContentStats::of(&content);
+                match database::update_content_stats(&self.pool, &paste.url, content.len(), stats)
+                    .await
+                {
+                    Ok(_) => {
+                        self.hooks.paste_updated(&paste.url, &paste.url);
+                        self.events.publish(PasteEvent::Updated {
+                            previous_url: paste.url.clone(),
+                            url:          paste.url,
+                        });
+                        Ok(())
+                    }
+                    Err(e) => Err(PasteError::Database(e)),
+                }
+            }
+            // Nothing was appended, find out why
+            Ok(None) => match database::retrieve_paste(&self.pool, &paste.url).await {
+                Ok(existing) if existing.password_hash != password_hash => {
+                    Err(PasteError::IncorrectPassword)
+                }
+                Ok(existing) if existing.date_locked.is_some() => Err(PasteError::Locked),
+                Ok(_) => Err(PasteError::InvalidContent),
+                Err(_) => Err(PasteError::NotFound),
+            },
+            Err(e) => Err(PasteError::Database(e)),
+        }
+    }
+
+    fn validate_autosave_token(token: &str) -> Result<(), PasteError> {
+        match is_url_safe(token) && (16..=128).contains(&token.len()) {
+            true => Ok(()),
+            false => Err(PasteError::InvalidAutosaveToken),
+        }
+    }
+
+    /// Saves the editor contents under a client-chosen token. Autosaves expire a few hours after their last save
+    pub async fn save_autosave(
+        &self,
+        token: &str,
+        mut autosave: Autosave,
+    ) -> Result<(), PasteError> {
+        Self::validate_autosave_token(token)?;
+        if autosave.content.len() > self.max_content_length() {
+            return Err(PasteError::InvalidContent);
+        }
+        autosave.updated = utility::unix_timestamp();
+        let since = autosave.updated - AUTOSAVE_LIFETIME;
+        match database::save_autosave(&self.pool, token, &autosave, since).await {
+            Ok(_) => Ok(()),
+            Err(e) => Err(PasteError::Database(e)),
+        }
+    }
+
+    pub async fn retrieve_autosave(&self, token: &str) -> Result<Autosave, PasteError> {
+        Self::validate_autosave_token(token)?;
+        let since = utility::unix_timestamp() - AUTOSAVE_LIFETIME;
+        match database::retrieve_autosave(&self.pool, token, since).await {
+            Ok(Some(autosave)) => Ok(autosave),
+            Ok(None) => Err(PasteError::AutosaveNotFound),
+            Err(e) => Err(PasteError::Database(e)),
+        }
+    }
+
+    pub async fn delete_autosave(&self, token: &str) -> Result<(), PasteError> {
+        Self::validate_autosave_token(token)?;
+        match database::delete_autosave(&self.pool, token).await {
+            Ok(_) => Ok(()),
+            Err(e) => Err(PasteError::Database(e)),
+        }
+    }
+
+    /// Retrieves a published paste, drafts are treated as missing
+    pub async fn retrieve_paste(&self, url: String) -> Result<PasteReturn, PasteError> {
+        self.retrieve_paste_or_draft(url, None).await
+    }
+
+    /// Retrieves a paste, drafts are only returned along with their password. A wrong password is treated like a missing paste, so drafts can't be discovered
+    /// Quarantined pastes need the password as well, otherwise they are reported as quarantined, see `retrieve_quarantined_paste`
+    /// Retrieving a paste with a view limit counts as a view, unless the password is given
+    pub async fn retrieve_paste_or_draft(
+        &self,
+        url: String,
+        password: Option<String>,
+    ) -> Result<PasteReturn, PasteError> {
+        self.retrieve(url, password, false).await
+    }
+
+    /// Retrieves a paste if its content still has the SHA256 hash `revision`, which otherwise works like `retrieve_paste`
+    /// The hash is compared before the paste is retrieved, so outdated revisions don't count as views of pastes with a view limit
+    pub async fn retrieve_revision(
+        &self,
+        url: String,
+        revision: &str,
+    ) -> Result<PasteReturn, PasteError> {
+        match database::retrieve_paste(&self.read_pool, &url).await {
+            Ok(paste) if paste.draft => return Err(PasteError::NotFound),
+            Ok(paste) if hash_string(paste.content.clone()) != revision => {
+                return Err(PasteError::RevisionNotFound)
+            }
+            Ok(_) => {}
+            Err(_) => return Err(PasteError::NotFound),
+        }
+        let paste = self.retrieve_paste(url).await?;
+        // The paste may have been edited in the meantime
+        match paste.content_sha256 == revision {
+            true => Ok(paste),
+            false => Err(PasteError::RevisionNotFound),
+        }
+    }
+
+    /// Retrieves a quarantined paste for a reader who was warned about it, which otherwise works like `retrieve_paste`
+    pub async fn retrieve_quarantined_paste(&self, url: String) -> Result<PasteReturn, PasteError> {
+        self.retrieve(url, None, true).await
+    }
+
+    async fn retrieve(
+        &self,
+        url: String,
+        password: Option<String>,
+        warned: bool,
+    ) -> Result<PasteReturn, PasteError> {
+        let paste = self.view_paste(url, password, warned).await?;
+        self.hooks.paste_viewed(&paste);
+        self.events.publish(PasteEvent::Viewed {
+            url: paste.url.clone(),
+        });
+        Ok(paste)
+    }
+
+    async fn view_paste(
+        &self,
+        url: String,
+        password: Option<String>,
+        warned: bool,
+    ) -> Result<PasteReturn, PasteError> {
+        let database_paste = match database::retrieve_paste(&self.read_pool, &url).await {
+            Ok(paste) => paste,
+            Err(_) => return Err(PasteError::NotFound),
+        };
+        let authorized = Self::is_authorized(&database_paste, &password.unwrap_or_default());
+        if database_paste.draft && !authorized {
+            return Err(PasteError::NotFound);
+        }
+        if database_paste.quarantined && !authorized && !warned {
+            return Err(PasteError::Quarantined);
+        }
+        let mut paste = PasteReturn::from(database_paste);
+        if paste.views_remaining.is_none() || paste.draft || authorized {
+            return Ok(paste);
+        }
+        match database::count_view(&self.pool, &url).await {
+            // This was the last view, the paste is still served this once
+            Ok(Some(0)) => {
+                if let Err(e) = database::delete_paste(&self.pool, &url).await {
+                    return Err(PasteError::Database(e));
+                }
+                self.hooks.paste_deleted(&url);
+                self.events
+                    .publish(PasteEvent::Deleted { url: url.clone() });
+                paste.views_remaining = Some(0);
+                Ok(paste)
+            }
+            Ok(Some(views_remaining)) => {
+                paste.views_remaining = Some(views_remaining);
+                Ok(paste)
+            }
+            // Another request took the last view
+            Ok(None) => Err(PasteError::NotFound),
+            Err(e) => Err(PasteError::Database(e)),
+        }
+    }
+
+    /// Checks a paste's password, without it counting as a view
+    pub async fn check_password(&self, paste: &PasteCredentials) -> Result<(), PasteError> {
+        match database::retrieve_paste(&self.read_pool, &paste.url).await {
+            Ok(existing_paste) if Self::is_authorized(&existing_paste, &paste.password) => Ok(()),
+            Ok(_) => Err(PasteError::IncorrectPassword),
+            Err(_) => Err(PasteError::NotFound),
+        }
+    }
+
+    /// Publishes a draft, which counts as its publication date. Publishing a paste that is already public does nothing
+    pub async fn publish_paste(&self, paste: PasteCredentials) -> Result<(), PasteError> {
+        let existing_paste = match database::retrieve_paste(&self.pool, &paste.url).await {
+            Ok(paste) => paste,
+            Err(_) => return Err(PasteError::NotFound),
+        };
+        if !Self::is_authorized(&existing_paste, &paste.password) {
+            return Err(PasteError::IncorrectPassword);
+        }
+        if !existing_paste.draft {
+            return Ok(());
+        }
+        match database::publish_paste(&self.pool, &paste.url, utility::unix_timestamp()).await {
+            Ok(_) => {
+                self.hooks.paste_updated(&paste.url, &paste.url);
+                if !existing_paste.quarantined {
+                    self.events
+                        .publish(PasteEvent::Published { url: paste.url });
+                }
+                Ok(())
+            }
+            Err(e) => Err(PasteError::Database(e)),
+        }
+    }
+
+    /// Changes only a paste's password, which is random unless a new one is given, and returns it
+    /// Locked pastes can have their password changed too, as it isn't part of their content
+    pub async fn change_password(
+        &self,
+        paste: PasteCredentials,
+        new_password: String,
+    ) -> Result<PasteCredentials, PasteError> {
+        let existing_paste = match database::retrieve_paste(&self.pool, &paste.url).await {
+            Ok(paste) => paste,
+            Err(_) => return Err(PasteError::NotFound),
+        };
+        if !Self::is_authorized(&existing_paste, &paste.password) {
+            return Err(PasteError::IncorrectPassword);
+        }
+        let new_password = match new_password.is_empty() {
+            true => PastePassword::random(),
+            false => PastePassword::new(new_password)?,
+        };
+        match database::set_password(&self.pool, &paste.url, &new_password.hash()).await {
+            Ok(_) => {
+                self.events.publish(PasteEvent::Changed {
+                    url: paste.url.clone(),
+                });
+                Ok(PasteCredentials {
+                    url:      paste.url,
+                    password: new_password.into_string(),
+                })
+            }
+            Err(e) => Err(PasteError::Database(e)),
+        }
+    }
+
+    /// Transfers a paste to the signed-in user, who has to know its password, and can change it without the password from then on
+    pub async fn claim_paste(&self, paste: PasteCredentials) -> Result<(), PasteError> {
+        let Some(user) = sso::current_user() else {
+            return Err(PasteError::SignInRequired);
+        };
+        let existing_paste = match database::retrieve_paste(&self.pool, &paste.url).await {
+            Ok(paste) => paste,
+            Err(_) => return Err(PasteError::NotFound),
+        };
+        if existing_paste.password_hash != hash_string(paste.password) {
+            return Err(PasteError::IncorrectPassword);
+        }
+        match database::set_owner(&self.pool, &paste.url, &user).await {
+            Ok(_) => Ok(()),
+            Err(e) => Err(PasteError::Database(e)),
+        }
+    }
+
+    /// The pastes claimed by the signed-in user, from every namespace, the most recently published first
+    pub async fn claimed_pastes(&self) -> Result<Vec<PasteReturn>, PasteError> {
+        let Some(user) = sso::current_user() else {
+            return Err(PasteError::SignInRequired);
+        };
+        match database::retrieve_owned_pastes(&self.read_pool, &user).await {
+            Ok(pastes) => Ok(pastes.into_iter().map(PasteReturn::from).collect()),
+            Err(e) => Err(PasteError::Database(e)),
+        }
+    }
+
+    /// Counts a view of a paste's page referred from another site, if `record_referrers` is on. Failures are only logged, as the view itself succeeded
+    pub async fn record_referrer(&self, url: &str, referrer: &str, base_url: &str) {
+        if !self.config.record_referrers {
+            return;
+        }
+        let Some(domain) = referrers::domain(referrer, base_url) else {
+            return;
+        };
+        if let Err(e) = database::count_referrer(&self.pool, url, &domain, MAX_REFERRERS).await {
+            tracing::warn!("Failed to count a referrer of {url}: {e:?}");
+        }
+    }
+
+    /// The sites a paste was opened from the most, for its owner
+    pub async fn referrers(&self, paste: PasteCredentials) -> Result<Vec<Referrer>, PasteError> {
+        let existing_paste = match database::retrieve_paste(&self.pool, &paste.url).await {
+            Ok(paste) => paste,
+            Err(_) => return Err(PasteError::NotFound),
+        };
+        if !Self::is_authorized(&existing_paste, &paste.password) {
+            return Err(PasteError::IncorrectPassword);
+        }
+        match database::retrieve_referrers(&self.read_pool, &paste.url, TOP_REFERRERS).await {
+            Ok(referrers) => Ok(referrers),
+            Err(e) => Err(PasteError::Database(e)),
+        }
+    }
+
+    /// Locks a paste, after which it can be deleted but never changed. Locking a paste that is already locked does nothing
+    pub async fn lock_paste(&self, paste: PasteCredentials) -> Result<(), PasteError> {
+        let existing_paste = match database::retrieve_paste(&self.pool, &paste.url).await {
+            Ok(paste) => paste,
+            Err(_) => return Err(PasteError::NotFound),
+        };
+        if !Self::is_authorized(&existing_paste, &paste.password) {
+            return Err(PasteError::IncorrectPassword);
+        }
+        if existing_paste.date_locked.is_some() {
+            return Ok(());
+        }
+        match database::lock_paste(&self.pool, &paste.url, utility::unix_timestamp()).await {
+            Ok(_) => {
+                self.events.publish(PasteEvent::Changed { url: paste.url });
+                Ok(())
+            }
+            Err(e) => Err(PasteError::Database(e)),
+        }
+    }
+
+    /// Applies a change pushed by the instance this one mirrors, see the `mirror` module
+    /// The change is passed on as an event, so this instance's webhooks and mirror see it too
+    pub async fn apply_mirrored(&self, change: MirroredChange) -> Result<(), PasteError> {
+        match change.paste {
+            Some(paste) => {
+                let mut paste = DatabasePaste::from(paste);
+                paste.rendered_html = self.prerender(&paste.format, &paste.content);
+                match database::replace_paste(&self.pool, paste).await {
+                    Ok(true) => {
+                        self.events.publish(PasteEvent::Changed { url: change.url });
+                        Ok(())
+                    }
+                    // Pushes can arrive out of order, an outdated state is skipped
+                    Ok(false) => Ok(()),
+                    Err(e) => Err(PasteError::Database(e)),
+                }
+            }
+            None => match database::delete_paste(&self.pool, &change.url).await {
+                Ok(_) => {
+                    self.events.publish(PasteEvent::Deleted { url: change.url });
+                    Ok(())
+                }
+                Err(e) => Err(PasteError::Database(e)),
+            },
+        }
+    }
+
+    /// How many pastes are waiting to be pushed to the `[mirror]`
+    pub async fn mirror_backlog(&self) -> Result<i64, PasteError> {
+        match self.jobs.count_unfinished("mirror_paste").await {
+            Ok(count) => Ok(count),
+            Err(e) => Err(PasteError::Database(e)),
+        }
+    }
+
+    /// Pushes a paste's current state to the `[mirror]`, see `Job::MirrorPaste`
+    pub async fn push_to_mirror(&self, url: &str) -> Result<(), String> {
+        match &self.mirror {
+            Some(mirror) => mirror.push(url).await,
+            // The push was queued while a mirror was configured, and there is none to push to anymore
+            None => Ok(()),
+        }
+    }
+
+    /// Publishes a paste's current content to IPFS and stores the CID it was added under, which is returned
+    /// Publishing again adds the content as it is then, replacing the stored CID
+    pub async fn publish_to_ipfs(&self, paste: PasteCredentials) -> Result<String, PasteError> {
+        let Some(ipfs) = &self.ipfs else {
+            return Err(PasteError::IpfsUnavailable);
+        };
+        let existing_paste = match database::retrieve_paste(&self.pool, &paste.url).await {
+            Ok(paste) => paste,
+            Err(_) => return Err(PasteError::NotFound),
+        };
+        if !Self::is_authorized(&existing_paste, &paste.password) {
+            return Err(PasteError::IncorrectPassword);
+        }
+        // A copy on IPFS can't be taken down, so quarantined pastes aren't published until they are released
+        if existing_paste.quarantined {
+            return Err(PasteError::NotFound);
+        }
+        let cid = match ipfs.add(existing_paste.content).await {
+            Ok(cid) => cid,
+            Err(e) => {
+                tracing::warn!("Failed to publish {} to IPFS: {e}", paste.url);
+                return Err(PasteError::IpfsFailed);
+            }
+        };
+        match database::set_ipfs_cid(&self.pool, &paste.url, &cid).await {
+            Ok(_) => Ok(cid),
+            Err(e) => Err(PasteError::Database(e)),
+        }
+    }
+
+    /// Writes a consistent snapshot of the database to a temporary file and opens it
+    /// The file is removed right away, the returned handle keeps it readable until it is dropped
+    pub async fn backup(&self) -> Result<File, PasteError> {
+        let path = env::temp_dir().join(format!("pasties-backup-{}.db", utility::random_string()));
+        if let Err(e) = database::backup(&self.pool, &path.to_string_lossy()).await {
+            return Err(PasteError::Database(e));
+        }
+        let file = File::open(&path).await;
+        if let Err(e) = fs::remove_file(&path).await {
+            tracing::warn!("Failed to remove the backup at {}: {e}", path.display());
+        }
+        match file {
+            Ok(file) => Ok(file),
+            Err(e) => {
+                tracing::error!("Failed to open the backup at {}: {e}", path.display());
+                Err(PasteError::BackupFailed)
+            }
         }
     }
 }
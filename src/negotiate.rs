@@ -0,0 +1,82 @@
+//! `negotiate` picks how a response is represented, based on the request's `Accept` header and whether it comes from a command-line client
+
+use axum::http::{header, HeaderMap};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Representation {
+    /// The rendered page
+    Html,
+    /// The paste as `PasteReturn` JSON, like `/api/:url`
+    Json,
+    /// The raw content
+    PlainText,
+}
+
+impl Representation {
+    fn of_media_type(media_type: &str) -> Option<Self> {
+        match media_type {
+            "text/html" | "application/xhtml+xml" | "text/*" => Some(Self::Html),
+            "application/json" => Some(Self::Json),
+            "text/plain" => Some(Self::PlainText),
+            _ => None,
+        }
+    }
+}
+
+/// User agents of command-line clients, which are served plain text unless they ask for something specific
+const TERMINAL_CLIENTS: [&str; 4] = ["curl/", "Wget/", "HTTPie/", "xh/"];
+
+/// Whether the request comes from a command-line client like curl
+pub fn is_terminal_client(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::USER_AGENT)
+        .and_then(|user_agent| user_agent.to_str().ok())
+        .is_some_and(|user_agent| {
+            TERMINAL_CLIENTS
+                .iter()
+                .any(|client| user_agent.starts_with(client))
+        })
+}
+
+/// Returns the representation the client prefers, the one listed first wins among equally preferred ones
+/// Without an `Accept` header, or without anything supported in it, the rendered page is served, or plain text to command-line clients
+pub fn negotiate(headers: &HeaderMap) -> Representation {
+    let fallback = match is_terminal_client(headers) {
+        true => Representation::PlainText,
+        false => Representation::Html,
+    };
+    let accept = match headers
+        .get(header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+    {
+        Some(accept) => accept,
+        None => return fallback,
+    };
+    let mut best: Option<(Representation, f32)> = None;
+    for range in accept.split(',') {
+        let mut parameters = range.split(';');
+        let media_type = parameters
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase();
+        let quality = parameters
+            .filter_map(|parameter| parameter.trim().strip_prefix("q="))
+            .find_map(|quality| quality.parse::<f32>().ok())
+            .unwrap_or(1.0);
+        // Command-line clients send `*/*` by default, which says nothing about what their users want
+        let representation = match media_type.as_str() {
+            "*/*" => Some(fallback),
+            media_type => Representation::of_media_type(media_type),
+        };
+        if let Some(representation) = representation {
+            if quality > 0.0 && best.is_none_or(|(_, best)| quality > best) {
+                best = Some((representation, quality));
+            }
+        }
+    }
+    match best {
+        Some((representation, _)) => representation,
+        None => fallback,
+    }
+}
@@ -0,0 +1,18 @@
+//! `org` renders Org-mode pastes, so notes can be pasted straight out of Emacs
+
+use orgize::Org;
+
+use crate::{render::Renderer, utility};
+
+pub struct OrgRenderer;
+
+impl Renderer for OrgRenderer {
+    fn render(&self, content: &str) -> String {
+        let mut html = Vec::new();
+        match Org::parse(content).write_html(&mut html) {
+            Ok(()) => String::from_utf8_lossy(&html).into_owned(),
+            // Writing into memory doesn't fail in practice, but the content is still worth showing if it does
+            Err(_) => format!("<pre>{}</pre>", utility::escape_html(content)),
+        }
+    }
+}
@@ -0,0 +1,109 @@
+//! `private` turns pasties into an internal tool, where every request has to be authenticated and nothing is served anonymously
+//! Requests are let through with the shared token, HTTP basic authentication, the admin token, or a session from `[sso]`
+
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Redirect, Response},
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+use crate::{
+    config::{Config, PrivateConfig},
+    mirror,
+    model::Manager,
+    negotiate::{self, Representation},
+    request_id,
+    utility::hash_string,
+};
+
+/// Checks that no listener would serve pastes without authentication
+/// Fails if the termbin or Gemini listener is enabled, as neither protocol can carry credentials
+pub fn check_config(config: &Config) -> Result<(), String> {
+    match config.private.is_some() && (config.termbin.is_some() || config.gemini.is_some()) {
+        true => Err("The termbin and Gemini listeners can't authenticate clients, so they can't be enabled on a private instance".to_string()),
+        false => Ok(()),
+    }
+}
+
+/// Compares secrets by their hashes, so the comparison's timing doesn't leak them
+fn matches(given: &str, expected: &str) -> bool {
+    hash_string(given.to_string()) == hash_string(expected.to_string())
+}
+
+/// Whether the request carries any of the accepted credentials
+fn is_authenticated(private: &PrivateConfig, manager: &Manager, headers: &HeaderMap) -> bool {
+    if manager
+        .sso()
+        .is_some_and(|sso| sso.session(headers).is_some())
+    {
+        return true;
+    }
+    let Some(authorization) = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+    else {
+        return false;
+    };
+    if let Some(token) = authorization.strip_prefix("Bearer ") {
+        let tokens = [
+            private.token.as_ref(),
+            manager.config().admin_token.as_ref(),
+        ];
+        return tokens
+            .into_iter()
+            .flatten()
+            .any(|expected| matches(token, expected));
+    }
+    let credentials = authorization
+        .strip_prefix("Basic ")
+        .and_then(|credentials| STANDARD.decode(credentials).ok())
+        .and_then(|credentials| String::from_utf8(credentials).ok());
+    let Some((username, password)) = credentials
+        .as_deref()
+        .and_then(|credentials| credentials.split_once(':'))
+    else {
+        return false;
+    };
+    private
+        .users
+        .iter()
+        .any(|user| user.username == username && matches(password, &user.password))
+}
+
+/// Middleware that refuses every request without credentials
+/// Browsers are asked to sign in through `[sso]` if it is configured, or for a username and password otherwise
+pub async fn require_access(State(manager): State<Manager>, req: Request, next: Next) -> Response {
+    let Some(private) = &manager.config().private else {
+        return next.run(req).await;
+    };
+    // Signing in has to be reachable to get a session in the first place
+    let signing_in = manager.sso().is_some() && req.uri().path().starts_with("/auth/");
+    // Mirrored changes are authenticated by their signature instead
+    let mirrored = req.uri().path() == mirror::PATH;
+    if signing_in || mirrored || is_authenticated(private, &manager, req.headers()) {
+        return next.run(req).await;
+    }
+    let is_browser = negotiate::negotiate(req.headers()) == Representation::Html;
+    if manager.sso().is_some() && is_browser {
+        let return_to = req.uri().path_and_query().map_or("/", |path| path.as_str());
+        let return_to =
+            percent_encoding::utf8_percent_encode(return_to, percent_encoding::NON_ALPHANUMERIC);
+        return Redirect::to(&format!("/auth/login?return_to={return_to}")).into_response();
+    }
+    let message =
+        request_id::annotate("This instance is private, authentication is required".to_string());
+    match private.users.is_empty() {
+        true => (StatusCode::UNAUTHORIZED, message).into_response(),
+        false => (
+            StatusCode::UNAUTHORIZED,
+            [(
+                header::WWW_AUTHENTICATE,
+                "Basic realm=\"pasties\", charset=\"UTF-8\"",
+            )],
+            message,
+        )
+            .into_response(),
+    }
+}
@@ -0,0 +1,387 @@
+//! `proxy` serves the images in rendered pastes from pasties itself, like GitHub's camo
+//! Viewers' addresses aren't leaked to arbitrary image hosts, and `http` images don't cause mixed content warnings
+//! Only URLs signed by `ImageProxy::signed_path` are fetched, so the proxy can't be used to fetch anything else
+
+use std::{
+    fmt::Write,
+    net::{IpAddr, SocketAddr},
+    time::Duration,
+};
+
+use axum::{
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+};
+use hmac::{Hmac, Mac};
+use lol_html::{element, html_content::Element, rewrite_str, RewriteStrSettings};
+use rand::Rng;
+use reqwest::{
+    dns::{Addrs, Name, Resolve, Resolving},
+    redirect,
+};
+use sha2::Sha256;
+use url::{Host, Url};
+
+use crate::{config::ImageProxyConfig, links};
+
+/// How long fetching an image may take
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How many redirects are followed when fetching an image
+const MAX_REDIRECTS: usize = 3;
+
+/// Image types that can't carry scripts, SVGs are left out as they would run on pasties' origin
+const ALLOWED_TYPES: [&str; 6] = [
+    "image/png",
+    "image/jpeg",
+    "image/gif",
+    "image/webp",
+    "image/avif",
+    "image/bmp",
+];
+
+#[derive(Clone)]
+pub struct ImageProxy {
+    key:      Vec<u8>,
+    max_size: usize,
+    client:   reqwest::Client,
+}
+
+/// Resolves hostnames, leaving out addresses that aren't publicly routable
+/// Without it, a paste could point the proxy at services only reachable from the server
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let host = name.as_str().to_string();
+        Box::pin(async move {
+            let addresses: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), 0))
+                .await?
+                .filter(|address| is_public(address.ip()))
+                .collect();
+            if addresses.is_empty() {
+                return Err(format!("{host} has no public addresses").into());
+            }
+            let addresses: Addrs = Box::new(addresses.into_iter());
+            Ok(addresses)
+        })
+    }
+}
+
+/// Whether an address is publicly routable, a stable stand-in for `IpAddr::is_global`
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [first, second, third, _] = ip.octets();
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                // Shared address space used for carrier-grade NAT, 100.64.0.0/10
+                || (first == 100 && (second & 0b1100_0000) == 64)
+                // "This network", 0.0.0.0/8
+                || first == 0
+                // IETF protocol assignments, 192.0.0.0/24
+                || (first == 192 && second == 0 && third == 0)
+                // Benchmarking, 198.18.0.0/15
+                || (first == 198 && (second & 0b1111_1110) == 18)
+                // Reserved for future use, 240.0.0.0/4
+                || first >= 240)
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public(IpAddr::V4(ip)),
+            None => {
+                let segments = ip.segments();
+                let first = segments[0];
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_multicast()
+                    // Unique local addresses, fc00::/7
+                    || (first & 0xfe00) == 0xfc00
+                    // Link-local addresses, fe80::/10
+                    || (first & 0xffc0) == 0xfe80
+                    // NAT64, 64:ff9b::/96, which reaches internal IPv4 addresses on NAT64 networks
+                    || segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0]
+                    // IPv4-compatible addresses, ::a.b.c.d, which `to_ipv4_mapped` doesn't cover
+                    || segments[..6] == [0; 6]
+                    // 6to4, 2002::/16, which embeds an arbitrary IPv4 address
+                    || first == 0x2002
+                    // Teredo, 2001::/32, which embeds an arbitrary IPv4 address
+                    || (first == 0x2001 && segments[1] == 0)
+                    // Documentation, 2001:db8::/32
+                    || (first == 0x2001 && segments[1] == 0xdb8))
+            }
+        },
+    }
+}
+
+/// Whether a URL may be fetched: `http` or `https`, and not a literal non-public address, which the resolver never sees
+fn is_fetchable(url: &Url) -> bool {
+    let public_host = match url.host() {
+        Some(Host::Domain(_)) => true,
+        Some(Host::Ipv4(ip)) => is_public(IpAddr::V4(ip)),
+        Some(Host::Ipv6(ip)) => is_public(IpAddr::V6(ip)),
+        None => false,
+    };
+    matches!(url.scheme(), "http" | "https") && public_host
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut hex, byte| {
+        let _ = write!(hex, "{byte:02x}");
+        hex
+    })
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+impl ImageProxy {
+    pub fn new(config: &ImageProxyConfig) -> Self {
+        let key = match &config.key {
+            Some(key) => key.as_bytes().to_vec(),
+            None => rand::thread_rng().gen::<[u8; 32]>().to_vec(),
+        };
+        let redirects = redirect::Policy::custom(|attempt| {
+            if attempt.previous().len() >= MAX_REDIRECTS {
+                attempt.error("too many redirects")
+            } else if !is_fetchable(attempt.url()) {
+                attempt.error("redirected to a URL that can't be proxied")
+            } else {
+                attempt.follow()
+            }
+        });
+        let client = match reqwest::Client::builder()
+            .dns_resolver(std::sync::Arc::new(PublicResolver))
+            // A proxy would resolve hosts itself, bypassing `PublicResolver`
+            .no_proxy()
+            .redirect(redirects)
+            .timeout(FETCH_TIMEOUT)
+            .user_agent(concat!("pasties/", env!("CARGO_PKG_VERSION")))
+            .build()
+        {
+            Ok(client) => client,
+            Err(e) => panic!("Failed to create the image proxy's HTTP client:\n    {e}"),
+        };
+        Self {
+            key,
+            max_size: config.max_size,
+            client,
+        }
+    }
+
+    fn mac(&self) -> Hmac<Sha256> {
+        // HMAC accepts keys of any length
+        Hmac::<Sha256>::new_from_slice(&self.key).unwrap()
+    }
+
+    /// The path an image is proxied at, `/proxy/<signature>-<url>` with both parts hex-encoded
+    pub fn signed_path(&self, url: &str) -> String {
+        let mut mac = self.mac();
+        mac.update(url.as_bytes());
+        let signature = mac.finalize().into_bytes();
+        format!("/proxy/{}-{}", to_hex(&signature), to_hex(url.as_bytes()))
+    }
+
+    /// Returns the URL in a signed path segment, if its signature is valid
+    fn verify(&self, signed: &str) -> Option<String> {
+        let (signature, url) = signed.split_once('-')?;
+        let url = from_hex(url)?;
+        let mut mac = self.mac();
+        mac.update(&url);
+        mac.verify_slice(&from_hex(signature)?).ok()?;
+        String::from_utf8(url).ok()
+    }
+
+    /// Points the sources of external images at the proxy
+    /// `srcset` is dropped, as its candidates would otherwise still be loaded directly
+    pub fn rewrite_images(&self, html: String) -> String {
+        let rewritten = rewrite_str(
+            &html,
+            RewriteStrSettings::new().append_element_content_handler(element!(
+                "img[src]",
+                |el: &mut Element| {
+                    let Some(src) = links::decoded_attribute(el, "src") else {
+                        return Ok(());
+                    };
+                    if !Url::parse(&src).is_ok_and(|url| is_fetchable(&url)) {
+                        return Ok(());
+                    }
+                    el.set_attribute("src", &self.signed_path(&src))?;
+                    el.remove_attribute("srcset");
+                    Ok(())
+                }
+            )),
+        );
+        // The rewrite only fails on invalid selectors or memory limits, neither of which depends on the paste
+        rewritten.unwrap_or(html)
+    }
+
+    /// Fetches and serves the image behind a signed path segment
+    /// Anything that isn't an image of an allowed type within the size limit is refused, so the proxy can't serve arbitrary content
+    pub async fn serve(&self, signed: &str) -> Response {
+        let Some(url) = self.verify(signed).and_then(|url| Url::parse(&url).ok()) else {
+            return StatusCode::NOT_FOUND.into_response();
+        };
+        if !is_fetchable(&url) {
+            return StatusCode::NOT_FOUND.into_response();
+        }
+        match self.fetch(url).await {
+            Ok((content_type, body)) => (
+                StatusCode::OK,
+                [
+                    (header::CONTENT_TYPE, content_type),
+                    (header::CACHE_CONTROL, "public, max-age=86400".to_string()),
+                    (header::X_CONTENT_TYPE_OPTIONS, "nosniff".to_string()),
+                    (
+                        header::CONTENT_SECURITY_POLICY,
+                        "default-src 'none'; sandbox".to_string(),
+                    ),
+                ],
+                body,
+            )
+                .into_response(),
+            Err(e) => {
+                tracing::debug!("Failed to proxy an image: {e}");
+                StatusCode::BAD_GATEWAY.into_response()
+            }
+        }
+    }
+
+    async fn fetch(&self, url: Url) -> Result<(String, Vec<u8>), String> {
+        let mut response = self
+            .client
+            .get(url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| e.to_string())?;
+        let content_type = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|content_type| content_type.to_str().ok())
+            .and_then(|content_type| content_type.split(';').next())
+            .map(|content_type| content_type.trim().to_ascii_lowercase())
+            .unwrap_or_default();
+        if !ALLOWED_TYPES.contains(&content_type.as_str()) {
+            return Err(format!("{content_type} is not an allowed image type"));
+        }
+        if response
+            .content_length()
+            .is_some_and(|length| length > self.max_size as u64)
+        {
+            return Err("the image is too large".to_string());
+        }
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await.map_err(|e| e.to_string())? {
+            body.extend_from_slice(&chunk);
+            if body.len() > self.max_size {
+                return Err("the image is too large".to_string());
+            }
+        }
+        Ok((content_type, body))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::IpAddr;
+
+    use url::Url;
+
+    use super::{is_fetchable, is_public};
+
+    fn is_public_address(address: &str) -> bool {
+        is_public(address.parse::<IpAddr>().unwrap())
+    }
+
+    #[test]
+    fn accepts_public_addresses() {
+        for address in [
+            "93.184.216.34",
+            "1.1.1.1",
+            "2606:4700:4700::1111",
+            "::ffff:8.8.8.8",
+        ] {
+            assert!(is_public_address(address), "{address}");
+        }
+    }
+
+    #[test]
+    fn rejects_non_public_ipv4_addresses() {
+        for address in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.1.2.3",
+            "192.0.0.8",
+            "198.18.0.1",
+            "198.19.255.255",
+            "192.0.2.1",
+            "224.0.0.1",
+            "240.0.0.1",
+            "255.255.255.255",
+        ] {
+            assert!(!is_public_address(address), "{address}");
+        }
+    }
+
+    #[test]
+    fn rejects_non_public_ipv6_addresses() {
+        for address in [
+            "::1",
+            "::",
+            "fc00::1",
+            "fd12:3456::1",
+            "fe80::1",
+            "ff02::1",
+            "::ffff:127.0.0.1",
+            "::ffff:10.0.0.1",
+            "::127.0.0.1",
+            "::10.0.0.1",
+            "64:ff9b::a00:1",
+            "2002:7f00:1::",
+            "2001:0:4136:e378::1",
+            "2001:db8::1",
+        ] {
+            assert!(!is_public_address(address), "{address}");
+        }
+    }
+
+    #[test]
+    fn fetches_only_http_urls_without_non_public_literal_addresses() {
+        for url in [
+            "https://example.com/image.png",
+            "http://example.com/image.png",
+            "http://93.184.216.34/image.png",
+            "http://[2606:4700:4700::1111]/image.png",
+        ] {
+            assert!(is_fetchable(&Url::parse(url).unwrap()), "{url}");
+        }
+        for url in [
+            "ftp://example.com/image.png",
+            "file:///etc/passwd",
+            "http://127.0.0.1/image.png",
+            "http://2130706433/image.png",
+            "http://0x7f.1/image.png",
+            "http://[::1]/image.png",
+            "http://[::ffff:169.254.169.254]/image.png",
+            "http://[2002:a00:1::]/image.png",
+        ] {
+            assert!(!is_fetchable(&Url::parse(url).unwrap()), "{url}");
+        }
+    }
+}
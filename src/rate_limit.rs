@@ -0,0 +1,128 @@
+//! `rate_limit` limits how many API requests a client can make per time window, keyed by the client's IP address
+//! In privacy mode, clients are tracked by a salted hash of their address, and the salt is replaced daily
+//! Every limited response carries `RateLimit-Limit`, `RateLimit-Remaining` and `RateLimit-Reset` headers, and `Retry-After` once the limit is hit
+
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+};
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{header, HeaderName, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::{config::Config, request_id, utility};
+
+const LIMIT_HEADER: HeaderName = HeaderName::from_static("ratelimit-limit");
+const REMAINING_HEADER: HeaderName = HeaderName::from_static("ratelimit-remaining");
+const RESET_HEADER: HeaderName = HeaderName::from_static("ratelimit-reset");
+
+/// How many clients are tracked before windows that have ended are cleaned up
+const PRUNE_THRESHOLD: usize = 10_000;
+
+/// How long a salt for hashing addresses is used before it is replaced, in seconds
+const SALT_LIFETIME: i64 = 24 * 60 * 60;
+
+#[derive(Clone)]
+pub struct RateLimiter {
+    requests: u32,
+    window:   i64,
+    privacy:  bool,
+    clients:  Arc<Mutex<Clients>>,
+}
+
+#[derive(Default)]
+struct Clients {
+    /// The start of each client's current window, and how many requests it made in it, keyed by the client's address or its hash
+    windows:      HashMap<String, (i64, u32)>,
+    salt:         String,
+    salt_created: i64,
+}
+
+/// The state of a client's window after counting a request
+struct Usage {
+    allowed:   bool,
+    remaining: u32,
+    reset:     i64,
+}
+
+impl RateLimiter {
+    /// Creates a rate limiter from the `[rate_limit]` section, if it is present
+    pub fn from_config(config: &Config) -> Option<Self> {
+        config.rate_limit.as_ref().map(|rate_limit| Self {
+            requests: rate_limit.requests,
+            window:   rate_limit.window.max(1),
+            privacy:  config.privacy_mode,
+            clients:  Arc::default(),
+        })
+    }
+
+    fn count(&self, client: IpAddr) -> Usage {
+        let now = utility::unix_timestamp();
+        let mut clients = self.clients.lock().unwrap();
+        let key = match self.privacy {
+            true => {
+                // Hashes made with the old salt can't be matched anymore, so their windows are dropped with it
+                if now - clients.salt_created >= SALT_LIFETIME {
+                    clients.salt = utility::random_string();
+                    clients.salt_created = now;
+                    clients.windows.clear();
+                }
+                utility::hash_string(format!("{}{client}", clients.salt))
+            }
+            false => client.to_string(),
+        };
+        let windows = &mut clients.windows;
+        if windows.len() > PRUNE_THRESHOLD {
+            windows.retain(|_, (start, _)| now - *start < self.window);
+        }
+        let (start, count) = windows.entry(key).or_insert((now, 0));
+        if now - *start >= self.window {
+            *start = now;
+            *count = 0;
+        }
+        let allowed = *count < self.requests;
+        if allowed {
+            *count += 1;
+        }
+        Usage {
+            allowed,
+            remaining: self.requests - *count,
+            reset: *start + self.window - now,
+        }
+    }
+}
+
+/// Middleware that rejects requests over the limit with `429 Too Many Requests`
+/// Requests without a peer address, i.e. ones over unix sockets, are not limited, as they would all share one limit
+pub async fn limit_requests(
+    State(limiter): State<RateLimiter>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let Some(ConnectInfo(address)) = connect_info else {
+        return next.run(req).await;
+    };
+    let usage = limiter.count(address.ip());
+    let mut response = match usage.allowed {
+        true => next.run(req).await,
+        false => {
+            let message = request_id::annotate("Too many requests, try again later".to_string());
+            let mut response = (StatusCode::TOO_MANY_REQUESTS, message).into_response();
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(usage.reset));
+            response
+        }
+    };
+    let headers = response.headers_mut();
+    headers.insert(LIMIT_HEADER, HeaderValue::from(limiter.requests));
+    headers.insert(REMAINING_HEADER, HeaderValue::from(usage.remaining));
+    headers.insert(RESET_HEADER, HeaderValue::from(usage.reset));
+    response
+}
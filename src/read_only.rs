@@ -0,0 +1,68 @@
+//! `read_only` lets anonymous visitors read pastes, while creating and changing them requires credentials
+//! Every request that isn't a read is checked here, in one place, so no handler can forget to. API keys, the admin token and `[sso]` sessions are accepted
+
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::{config::Config, mirror, model::Manager, request_id, utility::hash_string};
+
+/// Checks that no listener would accept pastes without credentials
+/// Fails if the termbin listener is enabled, as it only creates pastes and can't carry credentials
+pub fn check_config(config: &Config) -> Result<(), String> {
+    match config.read_only && config.termbin.is_some() {
+        true => Err("The termbin listener can't authenticate clients, so it can't be enabled on a read-only instance".to_string()),
+        false => Ok(()),
+    }
+}
+
+/// Whether the request carries an API key, the admin token or a session
+pub fn may_write(manager: &Manager, headers: &HeaderMap) -> bool {
+    if manager
+        .sso()
+        .is_some_and(|sso| sso.session(headers).is_some())
+    {
+        return true;
+    }
+    let Some(key) = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+    else {
+        return false;
+    };
+    // Hashes are compared rather than the keys, so the comparison's timing doesn't leak them
+    let key = hash_string(key.to_string());
+    let config = manager.config();
+    config
+        .api_keys
+        .iter()
+        .chain(config.admin_token.as_ref())
+        .any(|expected| hash_string(expected.clone()) == key)
+}
+
+/// Middleware that refuses anything but reads from anonymous clients
+pub async fn require_write_access(
+    State(manager): State<Manager>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let is_read = matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS);
+    // Mirrored changes are authenticated by their signature instead
+    let mirrored = req.uri().path() == mirror::PATH;
+    if !manager.config().read_only || is_read || mirrored || may_write(&manager, req.headers()) {
+        return next.run(req).await;
+    }
+    let message = match manager.sso() {
+        Some(_) => "Creating and changing pastes requires signing in at /auth/login, or an API key",
+        None => "Creating and changing pastes requires an API key",
+    };
+    (
+        StatusCode::UNAUTHORIZED,
+        request_id::annotate(message.to_string()),
+    )
+        .into_response()
+}
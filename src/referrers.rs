@@ -0,0 +1,32 @@
+//! `referrers` reduces the `Referer` header of paste views to the domain of the linking site, so owners can see where their links are opened from
+//! Paths, queries, IP addresses and local host names are dropped, so a referrer never identifies more than a public website
+use url::{Host, Url};
+
+/// Suffixes of host names that only resolve inside a network, and would tell more about the viewer than about where the link was shared
+const LOCAL_SUFFIXES: [&str; 5] = [".local", ".localhost", ".internal", ".lan", ".home.arpa"];
+
+/// Returns the domain a view was referred from, without a leading `www.`
+/// Referrers that aren't public websites, and links between pages of the instance itself, at `base_url`, are ignored
+pub fn domain(referrer: &str, base_url: &str) -> Option<String> {
+    let referrer = Url::parse(referrer).ok()?;
+    if !matches!(referrer.scheme(), "http" | "https") {
+        return None;
+    }
+    let domain = match referrer.host() {
+        Some(Host::Domain(domain)) => domain.trim_end_matches('.').to_lowercase(),
+        _ => return None,
+    };
+    if !domain.contains('.') || LOCAL_SUFFIXES.iter().any(|suffix| domain.ends_with(suffix)) {
+        return None;
+    }
+    let own_domain = Url::parse(base_url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_lowercase));
+    if own_domain.as_deref() == Some(domain.as_str()) {
+        return None;
+    }
+    match domain.strip_prefix("www.") {
+        Some(domain) => Some(domain.to_string()),
+        None => Some(domain),
+    }
+}
@@ -0,0 +1,133 @@
+//! `render` turns paste content into HTML. Every paste format has its own `Renderer`, registered under the format's name
+
+use std::{collections::HashMap, sync::Arc};
+
+use serde::Deserialize;
+
+use crate::{
+    detect,
+    jupyter::JupyterRenderer,
+    markdown::MarkdownRenderer,
+    org::OrgRenderer,
+    structured::{StructuredRenderer, Syntax},
+    table::TableRenderer,
+    utility,
+};
+
+/// The build pastes' stored HTML was rendered by, see `Manager::prerender`
+/// HTML stored by any other build is rendered again, as its renderers may have worked differently
+pub const VERSION: &str = concat!(env!("CARGO_PKG_VERSION"), "+", env!("PASTIES_COMMIT"));
+
+/// The format pastes are rendered as if they don't specify one and it can't be detected
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DefaultFormat {
+    #[default]
+    Markdown,
+    /// Escaped and preformatted, so nothing in a paste is interpreted unless its author opts into a format
+    Plain,
+}
+
+impl DefaultFormat {
+    pub fn name(self) -> &'static str {
+        match self {
+            DefaultFormat::Markdown => "markdown",
+            DefaultFormat::Plain => "plain",
+        }
+    }
+}
+
+/// Strips everything that could run script from rendered HTML, for when it's shown outside of pasties' own pages and their Content-Security-Policy
+/// Classes are kept, as the styles of formats rely on them
+pub fn sanitize(html: &str) -> String {
+    ammonia::Builder::default()
+        .add_generic_attributes(["class"])
+        .clean(html)
+        .to_string()
+}
+
+pub trait Renderer: Send + Sync {
+    fn render(&self, content: &str) -> String;
+}
+
+/// Renders content as-is, escaped and preformatted
+pub struct PlainTextRenderer;
+
+impl Renderer for PlainTextRenderer {
+    fn render(&self, content: &str) -> String {
+        format!("<pre>{}</pre>", utility::escape_html(content))
+    }
+}
+
+/// The renderers available to pastes, keyed by format name
+#[derive(Clone)]
+pub struct Renderers {
+    renderers:      Arc<HashMap<&'static str, Box<dyn Renderer>>>,
+    default_format: &'static str,
+}
+
+impl Renderers {
+    pub fn new(default_format: DefaultFormat) -> Self {
+        let mut renderers: HashMap<&'static str, Box<dyn Renderer>> = HashMap::new();
+        renderers.insert("markdown", Box::new(MarkdownRenderer));
+        renderers.insert("plain", Box::new(PlainTextRenderer));
+        renderers.insert("org", Box::new(OrgRenderer));
+        renderers.insert("jupyter", Box::new(JupyterRenderer));
+        renderers.insert("csv", Box::new(TableRenderer { delimiter: b',' }));
+        renderers.insert("tsv", Box::new(TableRenderer { delimiter: b'\t' }));
+        renderers.insert(
+            "json",
+            Box::new(StructuredRenderer {
+                syntax: Syntax::Json,
+            }),
+        );
+        renderers.insert(
+            "yaml",
+            Box::new(StructuredRenderer {
+                syntax: Syntax::Yaml,
+            }),
+        );
+        #[cfg(feature = "asciidoc")]
+        renderers.insert("asciidoc", Box::new(crate::asciidoc::AsciiDocRenderer));
+        Self {
+            renderers:      Arc::new(renderers),
+            default_format: default_format.name(),
+        }
+    }
+
+    /// The format pastes without one fall back to, from the instance's `default_format`
+    pub fn default_format(&self) -> &'static str {
+        self.default_format
+    }
+
+    pub fn supports(&self, format: &str) -> bool {
+        self.renderers.contains_key(format)
+    }
+
+    /// The names of all registered formats, in alphabetical order
+    pub fn formats(&self) -> Vec<&'static str> {
+        let mut formats: Vec<&'static str> = self.renderers.keys().copied().collect();
+        formats.sort_unstable();
+        formats
+    }
+
+    /// Guesses the format of `content`, falling back to the default format if the guess isn't a registered one
+    pub fn detect(&self, content: &str) -> &'static str {
+        match detect::guess_format(content) {
+            Some(format) if self.supports(format) => format,
+            _ => self.default_format,
+        }
+    }
+
+    /// Renders content with the renderer for `format`, falling back to the default format for unknown ones
+    pub fn render(&self, format: &str, content: &str) -> String {
+        match self
+            .renderers
+            .get(format)
+            .or(self.renderers.get(self.default_format))
+        {
+            Some(renderer) => renderer.render(content),
+            None => PlainTextRenderer.render(content),
+        }
+    }
+}
@@ -0,0 +1,119 @@
+//! `reporting` forwards panics and server errors to an external error-reporting backend, so operators learn about failures that would otherwise only show up as a blank 500 page
+
+use std::{any::Any, panic::AssertUnwindSafe};
+
+use axum::{
+    extract::{Request, State},
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use futures_util::FutureExt;
+use serde::Serialize;
+
+use crate::{config::Config, request_id, utility};
+
+/// Sends error reports to the endpoint configured under `[error_reporting]`, or only logs them if there is none
+#[derive(Clone)]
+pub struct Reporter {
+    endpoint: Option<String>,
+    client:   reqwest::Client,
+}
+
+/// The JSON payload sent to the error-reporting backend
+#[derive(Serialize, Debug, Clone)]
+pub struct ErrorReport {
+    pub kind:       &'static str,
+    pub message:    String,
+    pub method:     String,
+    pub path:       String,
+    pub status:     u16,
+    pub user_agent: Option<String>,
+    pub request_id: Option<String>,
+    pub timestamp:  i64,
+}
+
+impl Reporter {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            endpoint: config.error_reporting.as_ref().map(|c| c.endpoint.clone()),
+            client:   reqwest::Client::new(),
+        }
+    }
+
+    /// Logs the report and delivers it in the background, so a slow backend never delays the response
+    pub fn report(&self, report: ErrorReport) {
+        tracing::error!(
+            "{} on {} {}: {}",
+            report.kind,
+            report.method,
+            report.path,
+            report.message
+        );
+        let Some(endpoint) = self.endpoint.clone() else {
+            return;
+        };
+        let client = self.client.clone();
+        tokio::spawn(async move {
+            if let Err(e) = client.post(&endpoint).json(&report).send().await {
+                tracing::warn!("Failed to deliver an error report to {endpoint}: {e}");
+            }
+        });
+    }
+}
+
+/// Middleware that turns panics into 500 responses and reports them, along with any other 5xx response, with the request's context
+pub async fn report_errors(
+    State(reporter): State<Reporter>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let method = request.method().to_string();
+    let path = request.uri().path().to_string();
+    let user_agent = request
+        .headers()
+        .get(header::USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+
+    let (kind, message, response) = match AssertUnwindSafe(next.run(request)).catch_unwind().await {
+        Ok(response) if response.status().is_server_error() => {
+            let message = format!("Responded with {}", response.status());
+            ("response", message, response)
+        }
+        Ok(response) => return response,
+        Err(panic) => (
+            "panic",
+            panic_message(panic),
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                request_id::annotate(
+                    "An unexpected error occured while handling this request".to_string(),
+                ),
+            )
+                .into_response(),
+        ),
+    };
+    reporter.report(ErrorReport {
+        kind,
+        message,
+        method,
+        path,
+        status: response.status().as_u16(),
+        user_agent,
+        request_id: request_id::current(),
+        timestamp: utility::unix_timestamp(),
+    });
+    response
+}
+
+/// Extracts the message a panic was raised with, if it was a string
+fn panic_message(panic: Box<dyn Any + Send>) -> String {
+    match panic.downcast::<String>() {
+        Ok(message) => *message,
+        Err(panic) => match panic.downcast::<&'static str>() {
+            Ok(message) => message.to_string(),
+            Err(_) => "Panicked with a non-string payload".to_string(),
+        },
+    }
+}
@@ -0,0 +1,58 @@
+//! `request_id` tags every request with an ID that shows up in logs, error pages and API error bodies, so users can report failures that operators can find again
+
+use axum::{
+    extract::Request,
+    http::{HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use rand::Rng;
+use tracing::Instrument;
+
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// Returns the ID of the request currently being handled, if called from within one
+pub fn current() -> Option<String> {
+    REQUEST_ID.try_with(|id| id.clone()).ok()
+}
+
+/// Appends the current request's ID to a message meant for the end user
+pub fn annotate(message: String) -> String {
+    match current() {
+        Some(id) => format!("{message} (error id: {id})"),
+        None => message,
+    }
+}
+
+/// Middleware that honors an incoming `X-Request-Id` header, or generates a new ID, and makes it available for the rest of the request
+pub async fn propagate_request_id(request: Request, next: Next) -> Response {
+    let id = match request
+        .headers()
+        .get(&REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+    {
+        Some(id) if is_valid_request_id(id) => id.to_string(),
+        _ => format!("{:016x}", rand::thread_rng().gen::<u64>()),
+    };
+    let span = tracing::info_span!("request", id = %id);
+    let mut response = REQUEST_ID
+        .scope(id.clone(), next.run(request).instrument(span))
+        .await;
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
+
+/// Client-provided IDs end up in logs and pages, so only short, plain ones are accepted
+fn is_valid_request_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= 64
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_' || b == b'.')
+}
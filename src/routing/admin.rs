@@ -0,0 +1,215 @@
+//! `routing::admin` responds to requests from the instance's administrators, authenticated with the `admin_token` setting
+
+use axum::{
+    body::Body,
+    extract::{Path, Request, State},
+    http::{header, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{get, post, put},
+    Json, Router,
+};
+use serde::Deserialize;
+use tokio_util::io::ReaderStream;
+
+use super::{pages, JsonOrForm};
+use crate::{
+    banner::{Banner, Severity},
+    database::JobEntry,
+    model::{Manager, PasteError},
+    request_id, slow_queries,
+    utility::{self, hash_string},
+};
+
+pub fn routes(manager: Manager) -> Router {
+    let mut router = Router::new()
+        .route("/pins/:url", post(pin_request).delete(unpin_request))
+        .route(
+            "/quarantine/:url",
+            put(quarantine_request).delete(release_request),
+        );
+    // The job queue, banner and database are shared by the whole instance, so only its own admins see them
+    if manager.namespace().is_none() {
+        router = router
+            .route("/jobs", get(jobs_request))
+            .route(
+                "/banner",
+                put(set_banner_request).delete(clear_banner_request),
+            )
+            .route("/backup", get(backup_request))
+            .route("/metrics", get(metrics_request));
+    }
+    router
+        .layer(middleware::from_fn_with_state(
+            manager.clone(),
+            require_admin,
+        ))
+        .with_state(manager)
+}
+
+/// Middleware that only lets requests with the admin token, or from users signed in as admins, through
+/// Without a configured token or SSO, the admin endpoints don't exist as far as clients can tell
+async fn require_admin(State(manager): State<Manager>, req: Request, next: Next) -> Response {
+    if let Some(sso) = manager.sso() {
+        if sso
+            .session(req.headers())
+            .is_some_and(|session| session.admin)
+        {
+            return next.run(req).await;
+        }
+    }
+    let Some(admin_token) = manager.admin_token() else {
+        if manager.sso().is_some() {
+            return unauthorized();
+        }
+        return pages::not_found_handler(req.headers().clone()).await;
+    };
+    let token = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    // Hashes are compared rather than the tokens, so the comparison's timing doesn't leak the token
+    match token {
+        Some(token) if hash_string(token.to_string()) == hash_string(admin_token.clone()) => {
+            next.run(req).await
+        }
+        _ => unauthorized(),
+    }
+}
+
+fn unauthorized() -> Response {
+    (
+        StatusCode::UNAUTHORIZED,
+        request_id::annotate("A valid admin token is required".to_string()),
+    )
+        .into_response()
+}
+
+/// Pins a paste to the homepage
+async fn pin_request(
+    State(manager): State<Manager>,
+    Path(url): Path<String>,
+) -> Result<StatusCode, PasteError> {
+    match manager.set_pinned(&url, true).await {
+        Ok(()) => Ok(StatusCode::NO_CONTENT),
+        Err(e) => Err(e),
+    }
+}
+
+async fn unpin_request(
+    State(manager): State<Manager>,
+    Path(url): Path<String>,
+) -> Result<StatusCode, PasteError> {
+    match manager.set_pinned(&url, false).await {
+        Ok(()) => Ok(StatusCode::NO_CONTENT),
+        Err(e) => Err(e),
+    }
+}
+
+/// Quarantines a paste, so it is only shown behind a warning until it is reviewed
+async fn quarantine_request(
+    State(manager): State<Manager>,
+    Path(url): Path<String>,
+) -> Result<StatusCode, PasteError> {
+    match manager.quarantine_paste(&url).await {
+        Ok(()) => Ok(StatusCode::NO_CONTENT),
+        Err(e) => Err(e),
+    }
+}
+
+/// Releases a paste quarantined by a content filter or an admin, so everyone can see it again
+async fn release_request(
+    State(manager): State<Manager>,
+    Path(url): Path<String>,
+) -> Result<StatusCode, PasteError> {
+    match manager.release_paste(&url).await {
+        Ok(()) => Ok(StatusCode::NO_CONTENT),
+        Err(e) => Err(e),
+    }
+}
+
+/// Lists the jobs that are queued, running or have failed for good, in the order they are due
+async fn jobs_request(State(manager): State<Manager>) -> Result<Json<Vec<JobEntry>>, PasteError> {
+    match manager.jobs().list().await {
+        Ok(jobs) => Ok(Json(jobs)),
+        Err(e) => Err(PasteError::Database(e)),
+    }
+}
+
+#[derive(Deserialize)]
+struct BannerForm {
+    message:    String,
+    #[serde(default = "default_severity")]
+    severity:   Severity,
+    /// Seconds until the banner is hidden, it stays until it is removed otherwise
+    expires_in: Option<i64>,
+}
+
+fn default_severity() -> Severity {
+    Severity::Info
+}
+
+/// Sets the banner shown at the top of every page, replacing the current one
+async fn set_banner_request(
+    State(manager): State<Manager>,
+    JsonOrForm(form): JsonOrForm<BannerForm>,
+) -> Result<Json<Banner>, PasteError> {
+    match manager
+        .set_banner(form.message, form.severity, form.expires_in)
+        .await
+    {
+        Ok(banner) => Ok(Json(banner)),
+        Err(e) => Err(e),
+    }
+}
+
+/// Removes the banner
+async fn clear_banner_request(State(manager): State<Manager>) -> Result<StatusCode, PasteError> {
+    match manager.clear_banner().await {
+        Ok(()) => Ok(StatusCode::NO_CONTENT),
+        Err(e) => Err(e),
+    }
+}
+
+/// Downloads a consistent snapshot of the database, taken while the instance keeps running
+async fn backup_request(State(manager): State<Manager>) -> Result<Response, PasteError> {
+    match manager.backup().await {
+        Ok(file) => Ok((
+            [
+                (header::CONTENT_TYPE, "application/vnd.sqlite3".to_string()),
+                (
+                    header::CONTENT_DISPOSITION,
+                    format!(
+                        "attachment; filename=\"pasties-{}.db\"",
+                        utility::unix_timestamp()
+                    ),
+                ),
+            ],
+            Body::from_stream(ReaderStream::new(file)),
+        )
+            .into_response()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Returns the instance's counters in the Prometheus text format
+async fn metrics_request(State(manager): State<Manager>) -> Response {
+    let mut body = format!(
+        "# HELP pasties_slow_queries_total Database calls that took at least the slow query threshold\n\
+        # TYPE pasties_slow_queries_total counter\n\
+        pasties_slow_queries_total {}\n",
+        slow_queries::count()
+    );
+    if manager.config().mirror.is_some() {
+        match manager.mirror_backlog().await {
+            Ok(backlog) => body.push_str(&format!(
+                "# HELP pasties_mirror_backlog Pastes waiting to be pushed to the mirror\n\
+                # TYPE pasties_mirror_backlog gauge\n\
+                pasties_mirror_backlog {backlog}\n"
+            )),
+            Err(e) => return e.into_response(),
+        }
+    }
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], body).into_response()
+}
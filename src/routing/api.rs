@@ -1,19 +1,36 @@
 //! `routing::api` responds to requests that should return serialized data to the client. It creates an interface for the `Manager` CRUD struct defined in `model`
 use askama_axum::{IntoResponse, Response};
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
+    async_trait,
+    extract::{FromRequest, Multipart, Path, Query, Request, State},
+    http::{header, HeaderMap, HeaderName, HeaderValue, StatusCode},
     response::Html,
     routing::{get, post},
-    Form, Json, Router,
+    Json, Router,
 };
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    markdown::render_markdown,
-    model::{Manager, NewPasteData, PasteCredentials, PasteError, PasteReturn},
+    detect,
+    model::{
+        Autosave, CollectionCredentials, ContentStats, CreatedPaste, Manager, NewPasteData,
+        PasteChanges, PasteCredentials, PasteError, PasteReturn, Quota, ReservationRequest,
+    },
+    secrets,
+    structured::{self, Syntax},
+    style::{Font, PasteStyle, Width},
+    utility, validation,
 };
-use super::pages;
+use super::{admin, pages, JsonOrForm, ResolvedUrl};
+
+const IDEMPOTENCY_KEY_HEADER: HeaderName = HeaderName::from_static("idempotency-key");
+
+/// Header carrying a warning that the created paste seems to contain credentials
+const SECRET_WARNING_HEADER: HeaderName = HeaderName::from_static("x-secret-warning");
+
+/// How far `/api/<url>/extend` pushes back a paste's expiry if no duration is given, in seconds
+const DEFAULT_EXTENSION: i64 = 24 * 60 * 60;
 
 pub struct ApiReturn {
     status:        StatusCode,
@@ -28,6 +45,14 @@ pub struct UpdateForm {
     pub content:      String,
     pub new_url:      String,
     pub new_password: String,
+    #[serde(default)]
+    pub format:       String,
+    #[serde(default, deserialize_with = "utility::empty_as_none")]
+    pub font:         Option<Font>,
+    #[serde(default, deserialize_with = "utility::empty_as_none")]
+    pub width:        Option<Width>,
+    #[serde(default, deserialize_with = "utility::empty_as_none")]
+    pub wrap:         Option<bool>,
 }
 
 impl IntoResponse for ApiReturn {
@@ -39,6 +64,18 @@ impl IntoResponse for ApiReturn {
     }
 }
 
+/// The paths under `/api` taken by routes of their own, which would shadow `GET /api/<url>` for pastes with these URLs
+pub const RESERVED_URLS: [&str; 8] = [
+    "render",
+    "quota",
+    "v1",
+    "autosave",
+    "reservations",
+    "collections",
+    "admin",
+    "mirror",
+];
+
 pub fn routes(manager: Manager) -> Router {
     Router::new()
         .route(
@@ -48,50 +85,267 @@ pub fn routes(manager: Manager) -> Router {
                 .put(update_request)
                 .delete(delete_request),
         )
-        .route("/:url", get(view_request))
-        .route("/render", post(markdown_render_request))
+        .route("/:url", get(view_request).patch(patch_request))
+        .route("/:url/publish", post(publish_request))
+        .route("/:url/ipfs", post(ipfs_request))
+        .route("/:url/append", post(append_request))
+        .route("/:url/extend", post(extend_request))
+        .route("/:url/lock", post(lock_request))
+        .route("/:url/claim", post(claim_request))
+        .route("/:url/password", post(password_request))
+        .route("/:url/referrers", get(referrers_request))
+        .route("/render", post(render_request))
+        .route("/quota", get(quota_request))
+        .route(
+            "/v1/pastes/batch",
+            post(batch_create_request).delete(batch_delete_request),
+        )
+        .route(
+            "/autosave/:token",
+            get(view_autosave_request)
+                .put(save_autosave_request)
+                .delete(delete_autosave_request),
+        )
+        .route("/reservations", post(reserve_request))
+        .route("/collections", post(create_collection_request))
+        .route("/collections/:name", get(view_collection_request))
+        .route(
+            "/collections/:name/pastes",
+            post(add_to_collection_request).delete(remove_from_collection_request),
+        )
         .fallback(pages::not_found_handler)
-        .with_state(manager)
+        .with_state(manager.clone())
+        .nest("/admin", admin::routes(manager))
+}
+
+/// A paste to create, sent as JSON, a form, or a multipart form with the content uploaded as `file`
+/// An uploaded file's name is used as the paste's URL and its extension sets the format, unless they are given as well
+pub struct NewPasteForm(pub NewPasteData);
+
+#[async_trait]
+impl FromRequest<Manager> for NewPasteForm {
+    type Rejection = Response;
+
+    async fn from_request(req: Request, manager: &Manager) -> Result<Self, Response> {
+        let is_multipart = req
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.starts_with("multipart/form-data"));
+        if !is_multipart {
+            return match JsonOrForm::<NewPasteData>::from_request(req, manager).await {
+                Ok(JsonOrForm(paste)) => Ok(NewPasteForm(paste)),
+                Err(rejection) => Err(rejection),
+            };
+        }
+        let mut multipart = match Multipart::from_request(req, manager).await {
+            Ok(multipart) => multipart,
+            Err(rejection) => return Err(rejection.into_response()),
+        };
+        // The other fields are read like those of a regular form, so both accept the same values
+        let mut fields: Vec<(String, String)> = Vec::new();
+        let mut file_name = None;
+        loop {
+            let field = match multipart.next_field().await {
+                Ok(Some(field)) => field,
+                Ok(None) => break,
+                Err(rejection) => return Err(rejection.into_response()),
+            };
+            let name = field.name().unwrap_or_default().to_string();
+            if name == "file" {
+                file_name = Some(field.file_name().unwrap_or_default().to_string());
+            }
+            let text = match field.text().await {
+                Ok(text) => text,
+                Err(_) => return Err(PasteError::InvalidContent.into_response()),
+            };
+            match name.as_str() {
+                // An uploaded file takes the place of the content field
+                "file" => {
+                    fields.retain(|(name, _)| name != "content");
+                    fields.push(("content".to_string(), text));
+                }
+                "content" if file_name.is_some() => {}
+                _ => fields.push((name, text)),
+            }
+        }
+        // Serializing pairs of strings can't fail
+        let fields = serde_urlencoded::to_string(&fields).unwrap();
+        let mut paste = match serde_urlencoded::from_str::<NewPasteData>(&fields) {
+            Ok(paste) => paste,
+            Err(e) => return Err((StatusCode::UNPROCESSABLE_ENTITY, e.to_string()).into_response()),
+        };
+        let Some(file_name) = file_name else {
+            return Ok(NewPasteForm(paste));
+        };
+        let stem = file_name
+            .rsplit_once('.')
+            .map_or(file_name.as_str(), |(stem, _)| stem);
+        if paste.url.is_empty() {
+            if let Ok(url) = validation::PasteUrl::new(stem.to_string()) {
+                paste.url = url.into_string();
+            }
+        }
+        if paste.format.is_empty() {
+            if let Some(format) = detect::format_of_file_name(&file_name)
+                .filter(|format| manager.formats().contains(format))
+            {
+                paste.format = format.to_string();
+            }
+        }
+        Ok(NewPasteForm(paste))
+    }
 }
 
+/// Creates a paste
+/// Requests with an `Idempotency-Key` header can be retried safely, a retry responds like the original request instead of creating another paste
+/// If the content looks like it contains credentials, the paste is still created, but the response carries a warning
 async fn create_request(
     State(manager): State<Manager>,
-    Form(paste_to_create): Form<NewPasteData>,
+    headers: HeaderMap,
+    NewPasteForm(paste_to_create): NewPasteForm,
 ) -> Result<Response, PasteError> {
-    let redirect_url = paste_to_create.url.clone();
     let redirect_secret = paste_to_create.password.clone();
-    let res = manager.create_paste(paste_to_create).await;
-    match res {
-        Ok(()) => Ok(ApiReturn {
-            status:        StatusCode::CREATED,
-            body:          "Paste created successfully".to_string(),
-            htmx_redirect: Some(format!("/{}?secret={}", redirect_url, redirect_secret)),
-        }
-        .into_response()),
-        Err(err) => Err(err),
+    let warning = secrets::warning(&paste_to_create.content);
+    let res = match headers.get(IDEMPOTENCY_KEY_HEADER) {
+        Some(key) => match key.to_str() {
+            Ok(key) => {
+                manager
+                    .create_paste_idempotently(key, paste_to_create)
+                    .await
+            }
+            Err(_) => Err(PasteError::InvalidIdempotencyKey),
+        },
+        None => manager.create_paste(paste_to_create).await,
+    };
+    // The URL may have been normalized or generated, so the one the paste was created under is used
+    let url = res?;
+    let mut redirect = format!("/{}?secret={}", url, redirect_secret);
+    let mut body = "Paste created successfully".to_string();
+    if let Some(warning) = &warning {
+        redirect.push_str(&format!(
+            "&message={}",
+            utf8_percent_encode(warning, NON_ALPHANUMERIC)
+        ));
+        body.push_str(&format!("\nWarning: {warning}"));
+    }
+    let mut response = ApiReturn {
+        status: StatusCode::CREATED,
+        body,
+        htmx_redirect: Some(redirect),
+    }
+    .into_response();
+    if let Ok(location) = HeaderValue::from_str(&format!("/{url}")) {
+        response.headers_mut().insert(header::LOCATION, location);
+    }
+    if let Some(warning) = warning.and_then(|warning| HeaderValue::from_str(&warning).ok()) {
+        response
+            .headers_mut()
+            .insert(SECRET_WARNING_HEADER, warning);
     }
+    Ok(response)
+}
+
+/// The most pastes that can be created or deleted in one batch
+const MAX_BATCH_SIZE: usize = 100;
+
+/// The outcome of creating one paste of a batch
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum BatchResult {
+    Created(CreatedPaste),
+    Failed { error: String },
+}
+
+/// Creates every paste in a JSON array, responding with one result per paste in the same order
+async fn batch_create_request(
+    State(manager): State<Manager>,
+    Json(pastes): Json<Vec<NewPasteData>>,
+) -> Response {
+    if pastes.len() > MAX_BATCH_SIZE {
+        return (
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!("At most {MAX_BATCH_SIZE} pastes can be created at once"),
+        )
+            .into_response();
+    }
+    let results: Vec<BatchResult> = manager
+        .create_pastes(pastes)
+        .await
+        .into_iter()
+        .map(|result| match result {
+            Ok(created) => BatchResult::Created(created),
+            Err(e) => BatchResult::Failed {
+                error: e.public_message(),
+            },
+        })
+        .collect();
+    Json(results).into_response()
+}
+
+/// The outcome of deleting one paste of a batch
+#[derive(Serialize)]
+pub struct BatchDeleteResult {
+    url:     String,
+    deleted: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error:   Option<String>,
+}
+
+/// Deletes every paste in a JSON array of `{url, password}` credentials, responding with one result per paste in the same order
+async fn batch_delete_request(
+    State(manager): State<Manager>,
+    Json(mut pastes): Json<Vec<PasteCredentials>>,
+) -> Response {
+    if pastes.len() > MAX_BATCH_SIZE {
+        return (
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!("At most {MAX_BATCH_SIZE} pastes can be deleted at once"),
+        )
+            .into_response();
+    }
+    let urls: Vec<String> = pastes.iter().map(|paste| paste.url.clone()).collect();
+    for paste in &mut pastes {
+        paste.url = manager.resolve_url(std::mem::take(&mut paste.url)).await;
+    }
+    let results: Vec<BatchDeleteResult> = manager
+        .delete_pastes(pastes)
+        .await
+        .into_iter()
+        .zip(urls)
+        .map(|(result, url)| BatchDeleteResult {
+            url,
+            deleted: result.is_ok(),
+            error: result.err().map(|e| e.public_message()),
+        })
+        .collect();
+    Json(results).into_response()
 }
 
 async fn update_request(
     State(manager): State<Manager>,
-    Form(paste): Form<UpdateForm>,
+    JsonOrForm(paste): JsonOrForm<UpdateForm>,
 ) -> Result<Response, PasteError> {
     let credentials = PasteCredentials {
-        url:      paste.url,
+        url:      manager.resolve_url(paste.url).await,
         password: paste.password,
     };
     let update = NewPasteData {
-        url:      paste.new_url,
-        password: paste.new_password,
-        content:  paste.content,
-    };
-    let redirect_url = match update.url.is_empty() {
-        true => credentials.url.clone(),
-        false => update.url.clone(),
+        url:        paste.new_url,
+        password:   paste.new_password,
+        content:    paste.content,
+        format:     paste.format,
+        draft:      false,
+        max_views:  None,
+        expires_in: None,
+        font:       paste.font,
+        width:      paste.width,
+        wrap:       paste.wrap,
     };
     let redirect_secret = update.password.clone();
+    // Renamed pastes are redirected to under the URL they were stored with, which includes the namespace
     match manager.update_paste(credentials, update).await {
-        Ok(_) => Ok(ApiReturn {
+        Ok(redirect_url) => Ok(ApiReturn {
             status:        StatusCode::OK,
             body:          "Paste updated successfully".to_string(),
             htmx_redirect: Some(format!("/{}?updated={}", redirect_url, redirect_secret)),
@@ -101,36 +355,447 @@ async fn update_request(
     }
 }
 
+#[derive(Deserialize)]
+pub struct PatchForm {
+    pub password:     String,
+    pub content:      Option<String>,
+    pub new_url:      Option<String>,
+    pub new_password: Option<String>,
+    pub format:       Option<String>,
+    #[serde(default, deserialize_with = "utility::empty_as_none")]
+    pub font:         Option<Font>,
+    #[serde(default, deserialize_with = "utility::empty_as_none")]
+    pub width:        Option<Width>,
+    #[serde(default, deserialize_with = "utility::empty_as_none")]
+    pub wrap:         Option<bool>,
+}
+
+/// Updates only the fields present in the form, the paste's current password is always required
+async fn patch_request(
+    State(manager): State<Manager>,
+    ResolvedUrl(url): ResolvedUrl,
+    JsonOrForm(form): JsonOrForm<PatchForm>,
+) -> Result<Response, PasteError> {
+    let credentials = PasteCredentials {
+        url,
+        password: form.password,
+    };
+    let changes = PasteChanges {
+        url:      form.new_url,
+        content:  form.content,
+        password: form.new_password,
+        format:   form.format,
+        font:     form.font,
+        width:    form.width,
+        wrap:     form.wrap,
+    };
+    match manager.patch_paste(credentials, changes).await {
+        Ok(_) => Ok(ApiReturn {
+            status:        StatusCode::OK,
+            body:          "Paste updated successfully".to_string(),
+            htmx_redirect: None,
+        }
+        .into_response()),
+        Err(e) => Err(e),
+    }
+}
+
 async fn delete_request(
     State(manager): State<Manager>,
-    Form(paste_to_delete): Form<PasteCredentials>,
+    JsonOrForm(mut paste_to_delete): JsonOrForm<PasteCredentials>,
 ) -> Result<Response, PasteError> {
+    paste_to_delete.url = manager.resolve_url(paste_to_delete.url).await;
     match manager.delete_paste(paste_to_delete).await {
         Ok(_) => Ok(ApiReturn {
             status:        StatusCode::OK,
             body:          "".to_string(),
-            htmx_redirect: Some(format!("/?deleted")),
+            htmx_redirect: Some("/?deleted".to_string()),
+        }
+        .into_response()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Returns the editor contents saved under a token as JSON
+async fn view_autosave_request(
+    State(manager): State<Manager>,
+    Path(token): Path<String>,
+) -> Result<Response, PasteError> {
+    match manager.retrieve_autosave(&token).await {
+        Ok(autosave) => Ok(Json(autosave).into_response()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Saves the editor contents under a token, which the client generates and keeps to itself
+async fn save_autosave_request(
+    State(manager): State<Manager>,
+    Path(token): Path<String>,
+    JsonOrForm(autosave): JsonOrForm<Autosave>,
+) -> Result<Response, PasteError> {
+    match manager.save_autosave(&token, autosave).await {
+        Ok(_) => Ok(StatusCode::NO_CONTENT.into_response()),
+        Err(e) => Err(e),
+    }
+}
+
+async fn delete_autosave_request(
+    State(manager): State<Manager>,
+    Path(token): Path<String>,
+) -> Result<Response, PasteError> {
+    match manager.delete_autosave(&token).await {
+        Ok(_) => Ok(StatusCode::NO_CONTENT.into_response()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Reserves a URL, responding with the password the paste has to be created with and when the reservation expires
+async fn reserve_request(
+    State(manager): State<Manager>,
+    JsonOrForm(reservation): JsonOrForm<ReservationRequest>,
+) -> Result<Response, PasteError> {
+    match manager.reserve_url(reservation).await {
+        Ok(reserved) => Ok((StatusCode::CREATED, Json(reserved)).into_response()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Creates a collection, responding with its name and password, both random unless chosen
+async fn create_collection_request(
+    State(manager): State<Manager>,
+    JsonOrForm(collection): JsonOrForm<CollectionCredentials>,
+) -> Result<Response, PasteError> {
+    match manager.create_collection(collection).await {
+        Ok(created) => Ok((StatusCode::CREATED, Json(created)).into_response()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Returns how much of the namespace's quota is used, and how much is left
+async fn quota_request(State(manager): State<Manager>) -> Result<Json<Quota>, PasteError> {
+    match manager.quota().await {
+        Ok(quota) => Ok(Json(quota)),
+        Err(e) => Err(e),
+    }
+}
+
+/// Returns a collection and its pastes as JSON
+async fn view_collection_request(
+    State(manager): State<Manager>,
+    Path(name): Path<String>,
+) -> Result<Response, PasteError> {
+    match manager.retrieve_collection(name).await {
+        Ok(collection) => Ok(Json(collection).into_response()),
+        Err(e) => Err(e),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct CollectionPasteForm {
+    pub password: String,
+    pub url:      String,
+}
+
+/// Adds a paste to the end of a collection, the collection's password is required
+async fn add_to_collection_request(
+    State(manager): State<Manager>,
+    Path(name): Path<String>,
+    JsonOrForm(form): JsonOrForm<CollectionPasteForm>,
+) -> Result<Response, PasteError> {
+    let credentials = CollectionCredentials {
+        name,
+        password: form.password,
+    };
+    let url = manager.resolve_url(form.url).await;
+    match manager.add_to_collection(credentials, url).await {
+        Ok(_) => Ok((StatusCode::OK, "Paste added to the collection").into_response()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Removes a paste from a collection, the collection's password is required
+async fn remove_from_collection_request(
+    State(manager): State<Manager>,
+    Path(name): Path<String>,
+    JsonOrForm(form): JsonOrForm<CollectionPasteForm>,
+) -> Result<Response, PasteError> {
+    let credentials = CollectionCredentials {
+        name,
+        password: form.password,
+    };
+    let url = manager.resolve_url(form.url).await;
+    match manager.remove_from_collection(credentials, url).await {
+        Ok(_) => Ok((StatusCode::OK, "Paste removed from the collection").into_response()),
+        Err(e) => Err(e),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct AppendForm {
+    pub password: String,
+    pub content:  String,
+}
+
+/// Appends to a paste's content, for scripts that stream their output into one paste
+async fn append_request(
+    State(manager): State<Manager>,
+    ResolvedUrl(url): ResolvedUrl,
+    JsonOrForm(form): JsonOrForm<AppendForm>,
+) -> Result<Response, PasteError> {
+    let credentials = PasteCredentials {
+        url,
+        password: form.password,
+    };
+    match manager.append_to_paste(credentials, form.content).await {
+        Ok(_) => Ok((StatusCode::OK, "Content appended successfully").into_response()),
+        Err(e) => Err(e),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct PublishForm {
+    pub password: String,
+}
+
+/// Publishes a draft
+async fn publish_request(
+    State(manager): State<Manager>,
+    ResolvedUrl(url): ResolvedUrl,
+    JsonOrForm(form): JsonOrForm<PublishForm>,
+) -> Result<Response, PasteError> {
+    let credentials = PasteCredentials {
+        url:      url.clone(),
+        password: form.password,
+    };
+    match manager.publish_paste(credentials).await {
+        Ok(_) => Ok(ApiReturn {
+            status:        StatusCode::OK,
+            body:          "Paste published successfully".to_string(),
+            htmx_redirect: Some(format!("/{url}")),
         }
         .into_response()),
         Err(e) => Err(e),
     }
 }
 
+/// Transfers a paste to the signed-in user, taking the password like `publish_request`
+async fn claim_request(
+    State(manager): State<Manager>,
+    ResolvedUrl(url): ResolvedUrl,
+    JsonOrForm(form): JsonOrForm<PublishForm>,
+) -> Result<Response, PasteError> {
+    let credentials = PasteCredentials {
+        url:      url.clone(),
+        password: form.password,
+    };
+    match manager.claim_paste(credentials).await {
+        Ok(_) => Ok(ApiReturn {
+            status:        StatusCode::OK,
+            body:          "Paste claimed successfully".to_string(),
+            htmx_redirect: Some(format!("/{url}")),
+        }
+        .into_response()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Locks a paste, so its content can't change anymore, taking the password like `publish_request`
+async fn lock_request(
+    State(manager): State<Manager>,
+    ResolvedUrl(url): ResolvedUrl,
+    JsonOrForm(form): JsonOrForm<PublishForm>,
+) -> Result<Response, PasteError> {
+    let credentials = PasteCredentials {
+        url,
+        password: form.password,
+    };
+    match manager.lock_paste(credentials).await {
+        Ok(_) => Ok((StatusCode::OK, "Paste locked successfully").into_response()),
+        Err(e) => Err(e),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct PasswordForm {
+    pub password:     String,
+    /// A random password is used if empty
+    #[serde(default)]
+    pub new_password: String,
+}
+
+/// Changes only a paste's password, responding with the URL and the new password
+/// Anything that authenticated with the old password, like WebDAV mounts and `?secret=` links, stops working
+async fn password_request(
+    State(manager): State<Manager>,
+    ResolvedUrl(url): ResolvedUrl,
+    JsonOrForm(form): JsonOrForm<PasswordForm>,
+) -> Result<Response, PasteError> {
+    let credentials = PasteCredentials {
+        url,
+        password: form.password,
+    };
+    match manager
+        .change_password(credentials, form.new_password)
+        .await
+    {
+        Ok(credentials) => Ok(Json(credentials).into_response()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Where a paste was published on IPFS
+#[derive(Serialize)]
+pub struct IpfsPublication {
+    cid:         String,
+    /// The paste on the configured gateway, if there is one
+    gateway_url: Option<String>,
+}
+
+/// Publishes a paste to IPFS, taking the password like `publish_request`
+async fn ipfs_request(
+    State(manager): State<Manager>,
+    ResolvedUrl(url): ResolvedUrl,
+    JsonOrForm(form): JsonOrForm<PublishForm>,
+) -> Result<Response, PasteError> {
+    let credentials = PasteCredentials {
+        url,
+        password: form.password,
+    };
+    match manager.publish_to_ipfs(credentials).await {
+        Ok(cid) => Ok(Json(IpfsPublication {
+            gateway_url: manager.ipfs().and_then(|ipfs| ipfs.gateway_url(&cid)),
+            cid,
+        })
+        .into_response()),
+        Err(e) => Err(e),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ExtendForm {
+    pub password: String,
+    /// Seconds to push the expiry back by
+    #[serde(default, deserialize_with = "utility::empty_as_none")]
+    pub by:       Option<i64>,
+}
+
+/// When a paste expires, after it was extended
+#[derive(Serialize)]
+pub struct Expiry {
+    /// The unix timestamp the paste is deleted at
+    expires: i64,
+}
+
+/// Pushes back when an expiring paste is deleted, by a day unless `by` is given
+async fn extend_request(
+    State(manager): State<Manager>,
+    ResolvedUrl(url): ResolvedUrl,
+    JsonOrForm(form): JsonOrForm<ExtendForm>,
+) -> Result<Response, PasteError> {
+    let credentials = PasteCredentials {
+        url,
+        password: form.password,
+    };
+    match manager
+        .extend_expiry(credentials, form.by.unwrap_or(DEFAULT_EXTENSION))
+        .await
+    {
+        Ok(expires) => Ok(Json(Expiry { expires }).into_response()),
+        Err(e) => Err(e),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ViewQuery {
+    #[serde(default)]
+    pretty:   String,
+    /// Needed to view drafts
+    password: Option<String>,
+}
+
+/// Returns a paste as JSON
+/// With `?pretty=1`, the response is indented and the content of JSON and YAML pastes is pretty-printed
 pub async fn view_request(
     State(manager): State<Manager>,
-    Path(url): Path<String>,
-) -> Result<Json<PasteReturn>, PasteError> {
-    match manager.retrieve_paste(url).await {
-        Ok(p) => Ok(Json(p)),
+    ResolvedUrl(url): ResolvedUrl,
+    Query(query): Query<ViewQuery>,
+) -> Result<Response, PasteError> {
+    let mut paste = match manager.retrieve_paste_or_draft(url, query.password).await {
+        Ok(p) => p,
+        Err(e) => return Err(e),
+    };
+    if !matches!(query.pretty.as_str(), "1" | "true") {
+        return Ok(Json(paste).into_response());
+    }
+    if let Some(content) = Syntax::of_format(&paste.format)
+        .and_then(|syntax| structured::pretty_print(syntax, &paste.content))
+    {
+        paste.content = content;
+    }
+    match serde_json::to_string_pretty(&paste) {
+        Ok(body) => Ok(([(header::CONTENT_TYPE, "application/json")], body).into_response()),
+        Err(_) => Ok(Json(paste).into_response()),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ReferrersQuery {
+    #[serde(default)]
+    password: String,
+}
+
+/// Returns the sites a paste was opened from the most, with `?password=` unless the signed-in user owns the paste
+async fn referrers_request(
+    State(manager): State<Manager>,
+    ResolvedUrl(url): ResolvedUrl,
+    Query(query): Query<ReferrersQuery>,
+) -> Result<Response, PasteError> {
+    let credentials = PasteCredentials {
+        url,
+        password: query.password,
+    };
+    match manager.referrers(credentials).await {
+        Ok(referrers) => Ok(Json(referrers).into_response()),
         Err(e) => Err(e),
     }
 }
 
 #[derive(Deserialize)]
-pub struct StringForm {
+pub struct RenderForm {
     content: String,
+    #[serde(default)]
+    format:  String,
 }
 
-pub async fn markdown_render_request(Form(markdown): Form<StringForm>) -> Html<String> {
-    Html(render_markdown(markdown.content))
+pub async fn render_request(
+    State(manager): State<Manager>,
+    JsonOrForm(mut form): JsonOrForm<RenderForm>,
+) -> Html<String> {
+    // The editor leaves the format empty for it to be detected, like it is when the paste is created
+    if form.format.is_empty() {
+        form.format = manager.detect_format(&form.content).to_string();
+    }
+    Html(
+        manager
+            .render(&PasteReturn {
+                url:             String::new(),
+                id:              String::new(),
+                uuid:            String::new(),
+                content:         form.content,
+                format:          form.format,
+                date_published:  0,
+                date_edited:     0,
+                content_sha256:  String::new(),
+                stats:           ContentStats::default(),
+                excerpt:         String::new(),
+                draft:           false,
+                views_remaining: None,
+                style:           PasteStyle::default(),
+                quarantined:     false,
+                ipfs_cid:        None,
+                date_locked:     None,
+                rendered_html:   None,
+            })
+            .await,
+    )
 }
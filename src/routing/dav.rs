@@ -0,0 +1,190 @@
+//! `routing::dav` exposes pastes as text files over WebDAV at `/dav/<url>.txt`, so their owners can mount the instance and edit them in any editor
+//! Every request is authenticated with the paste's password, as the basic auth password or a bearer token. The folder itself lists nothing, as paste URLs aren't public
+//! Private and read-only instances expect their own credentials in the same header, so WebDAV can't be served on them
+
+use axum::{
+    extract::{Path, State},
+    http::{header, HeaderMap, Method, StatusCode},
+    response::{IntoResponse, Response},
+    routing::any,
+    Router,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+use crate::{
+    config::Config,
+    model::{Manager, PasteChanges, PasteCredentials, PasteError, PasteReturn},
+    request_id, utility,
+};
+
+const COLLECTION_METHODS: &str = "OPTIONS, PROPFIND";
+const FILE_METHODS: &str = "OPTIONS, PROPFIND, GET, HEAD, PUT";
+
+/// Checks that WebDAV isn't turned on where the instance's own credentials would take the place of paste passwords
+pub fn check_config(config: &Config) -> Result<(), String> {
+    match config.webdav == Some(true) && (config.private.is_some() || config.read_only) {
+        true => Err("WebDAV clients send paste passwords in the Authorization header, which private and read-only instances check for their own credentials, so webdav can't be enabled on them".to_string()),
+        false => Ok(()),
+    }
+}
+
+pub fn routes(manager: Manager) -> Router {
+    Router::new()
+        .route("/dav", any(collection))
+        .route("/dav/", any(collection))
+        .route("/dav/:file", any(file))
+        .with_state(manager)
+}
+
+/// The folder pastes are served in, which clients look at before opening a file
+async fn collection(State(manager): State<Manager>, method: Method) -> Response {
+    match method.as_str() {
+        "OPTIONS" => options(COLLECTION_METHODS),
+        "PROPFIND" => {
+            let href = format!("{}/dav/", manager.base_path());
+            let properties = "<D:resourcetype><D:collection/></D:resourcetype>";
+            multistatus(&href, properties)
+        }
+        _ => method_not_allowed(COLLECTION_METHODS),
+    }
+}
+
+/// A paste as a text file
+async fn file(
+    State(manager): State<Manager>,
+    method: Method,
+    Path(file): Path<String>,
+    headers: HeaderMap,
+    body: String,
+) -> Response {
+    if method == Method::OPTIONS {
+        return options(FILE_METHODS);
+    }
+    let Some(url) = file.strip_suffix(".txt") else {
+        return PasteError::NotFound.into_response();
+    };
+    let Some(password) = password(&headers) else {
+        return unauthorized();
+    };
+    let credentials = PasteCredentials {
+        url: manager.resolve_url(url.to_string()).await,
+        password,
+    };
+    let result = match method.as_str() {
+        "GET" | "HEAD" | "PROPFIND" => match read(&manager, credentials).await {
+            Ok(paste) if method.as_str() == "PROPFIND" => {
+                let href = format!("{}/dav/{file}", manager.base_path());
+                Ok(multistatus(&href, &file_properties(&paste)))
+            }
+            Ok(paste) => Ok((
+                [
+                    (
+                        header::CONTENT_TYPE,
+                        "text/plain; charset=utf-8".to_string(),
+                    ),
+                    (header::ETAG, format!("\"{}\"", paste.content_sha256)),
+                    (header::LAST_MODIFIED, utility::http_date(paste.date_edited)),
+                ],
+                paste.content,
+            )
+                .into_response()),
+            Err(e) => Err(e),
+        },
+        "PUT" => {
+            let changes = PasteChanges {
+                content: Some(body),
+                ..PasteChanges::default()
+            };
+            match manager.patch_paste(credentials, changes).await {
+                Ok(()) => Ok(StatusCode::NO_CONTENT.into_response()),
+                Err(e) => Err(e),
+            }
+        }
+        _ => Ok(method_not_allowed(FILE_METHODS)),
+    };
+    match result {
+        Ok(response) => response,
+        Err(PasteError::IncorrectPassword) => unauthorized(),
+        Err(e) => e.into_response(),
+    }
+}
+
+/// Retrieves a paste for its owner, which doesn't count as a view
+async fn read(manager: &Manager, credentials: PasteCredentials) -> Result<PasteReturn, PasteError> {
+    manager.check_password(&credentials).await?;
+    manager
+        .retrieve_paste_or_draft(credentials.url, Some(credentials.password))
+        .await
+}
+
+/// The password sent as the basic auth password, with any username, or as a bearer token
+fn password(headers: &HeaderMap) -> Option<String> {
+    let authorization = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())?;
+    if let Some(token) = authorization.strip_prefix("Bearer ") {
+        return Some(token.to_string());
+    }
+    let credentials = authorization
+        .strip_prefix("Basic ")
+        .and_then(|credentials| STANDARD.decode(credentials).ok())
+        .and_then(|credentials| String::from_utf8(credentials).ok())?;
+    credentials
+        .split_once(':')
+        .map(|(_, password)| password.to_string())
+}
+
+fn file_properties(paste: &PasteReturn) -> String {
+    format!(
+        "<D:resourcetype/>\
+        <D:getcontenttype>text/plain; charset=utf-8</D:getcontenttype>\
+        <D:getcontentlength>{}</D:getcontentlength>\
+        <D:getlastmodified>{}</D:getlastmodified>\
+        <D:getetag>\"{}\"</D:getetag>",
+        paste.content.len(),
+        utility::http_date(paste.date_edited),
+        paste.content_sha256
+    )
+}
+
+/// A `207 Multi-Status` response describing a single resource
+fn multistatus(href: &str, properties: &str) -> Response {
+    let body = format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+        <D:multistatus xmlns:D=\"DAV:\"><D:response>\
+        <D:href>{}</D:href>\
+        <D:propstat><D:prop>{properties}</D:prop><D:status>HTTP/1.1 200 OK</D:status></D:propstat>\
+        </D:response></D:multistatus>\n",
+        utility::escape_html(href)
+    );
+    (
+        StatusCode::MULTI_STATUS,
+        [(header::CONTENT_TYPE, "application/xml; charset=utf-8")],
+        body,
+    )
+        .into_response()
+}
+
+fn options(methods: &'static str) -> Response {
+    (
+        StatusCode::OK,
+        [("DAV", "1"), (header::ALLOW.as_str(), methods)],
+    )
+        .into_response()
+}
+
+fn method_not_allowed(methods: &'static str) -> Response {
+    (StatusCode::METHOD_NOT_ALLOWED, [(header::ALLOW, methods)]).into_response()
+}
+
+fn unauthorized() -> Response {
+    (
+        StatusCode::UNAUTHORIZED,
+        [(
+            header::WWW_AUTHENTICATE,
+            "Basic realm=\"pasties\", charset=\"UTF-8\"",
+        )],
+        request_id::annotate(PasteError::IncorrectPassword.public_message()),
+    )
+        .into_response()
+}
@@ -0,0 +1,95 @@
+//! `routing::hastebin` implements the haste protocol, so that hastebin clients and editor plugins can create and fetch pastes
+//! Pastes created this way get a random URL and password, and their format is detected from their content
+
+use axum::{
+    extract::State,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use serde::Serialize;
+
+use crate::{
+    model::{Manager, NewPasteData, PasteError},
+    routing::{pages::CONTENT_SHA256_HEADER, ResolvedUrl},
+    utility,
+};
+
+pub fn routes(manager: Manager) -> Router {
+    Router::new()
+        .route("/documents", post(create_document))
+        .route("/documents/:key", get(view_document))
+        .route("/raw/:key", get(raw_document))
+        .with_state(manager)
+}
+
+#[derive(Serialize)]
+struct CreatedDocument {
+    key:      String,
+    /// Not part of the haste protocol, but without it the paste could never be edited or deleted
+    password: String,
+}
+
+#[derive(Serialize)]
+struct Document {
+    key:  String,
+    data: String,
+}
+
+/// Creates a paste from the raw request body
+async fn create_document(
+    State(manager): State<Manager>,
+    body: String,
+) -> Result<Response, PasteError> {
+    let password = utility::random_string();
+    let paste = NewPasteData {
+        url:        String::new(),
+        content:    body,
+        password:   password.clone(),
+        format:     String::new(),
+        draft:      false,
+        max_views:  None,
+        expires_in: None,
+        font:       None,
+        width:      None,
+        wrap:       None,
+    };
+    match manager.create_paste(paste).await {
+        Ok(key) => Ok((StatusCode::OK, Json(CreatedDocument { key, password })).into_response()),
+        Err(e) => Err(e),
+    }
+}
+
+async fn view_document(
+    State(manager): State<Manager>,
+    ResolvedUrl(key): ResolvedUrl,
+) -> Result<Json<Document>, PasteError> {
+    match manager.retrieve_paste(key).await {
+        Ok(paste) => Ok(Json(Document {
+            key:  paste.url,
+            data: paste.content,
+        })),
+        Err(e) => Err(e),
+    }
+}
+
+async fn raw_document(
+    State(manager): State<Manager>,
+    ResolvedUrl(key): ResolvedUrl,
+) -> Result<Response, PasteError> {
+    match manager.retrieve_paste(key).await {
+        Ok(paste) => Ok((
+            [
+                (
+                    header::CONTENT_TYPE,
+                    "text/plain; charset=utf-8".to_string(),
+                ),
+                (CONTENT_SHA256_HEADER, paste.content_sha256),
+            ],
+            paste.content,
+        )
+            .into_response()),
+        Err(e) => Err(e),
+    }
+}
@@ -1,2 +1,103 @@
+pub mod admin;
 pub mod api;
+pub mod dav;
+pub mod hastebin;
 pub mod pages;
+pub mod pastebin;
+pub mod sprunge;
+
+use axum::{
+    async_trait,
+    extract::{FromRequest, FromRequestParts, Path, Request},
+    http::{header, request::Parts, HeaderMap},
+    response::{IntoResponse, Response},
+    Form, Json,
+};
+use serde::de::DeserializeOwned;
+
+use crate::{model::Manager, utility};
+
+/// The first path segments of pasties' own routes, which namespaces and pastes can't be named after
+pub const RESERVED_PATHS: [&str; 12] = [
+    "api",
+    "auth",
+    "assets",
+    "dav",
+    "meta",
+    "c",
+    "p",
+    "out",
+    "proxy",
+    "documents",
+    "raw",
+    "favicon.ico",
+];
+
+/// Checks that a namespace can be served under `/<name>`
+/// Fails if the name isn't URL-safe or is taken by one of pasties' own routes
+pub fn check_namespace_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || !utility::is_url_safe(name) {
+        return Err(format!(
+            "Namespace names have to be URL-safe, {name:?} isn't"
+        ));
+    }
+    match RESERVED_PATHS.contains(&name) {
+        true => Err(format!(
+            "The namespace {name:?} would shadow pasties' own /{name} routes"
+        )),
+        false => Ok(()),
+    }
+}
+
+/// A paste's URL taken from the request path, where the paste's UUID is accepted in its place
+pub struct ResolvedUrl(pub String);
+
+#[async_trait]
+impl FromRequestParts<Manager> for ResolvedUrl {
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, manager: &Manager) -> Result<Self, Response> {
+        match Path::<String>::from_request_parts(parts, manager).await {
+            Ok(Path(url)) => Ok(ResolvedUrl(manager.resolve_url(url).await)),
+            Err(rejection) => Err(rejection.into_response()),
+        }
+    }
+}
+
+/// A request body sent as JSON or as a form, told apart by its content type
+/// Programmatic clients can send JSON to the same routes the HTMX forms post to
+pub struct JsonOrForm<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for JsonOrForm<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Response> {
+        match is_json(req.headers()) {
+            true => match Json::<T>::from_request(req, state).await {
+                Ok(Json(value)) => Ok(JsonOrForm(value)),
+                Err(rejection) => Err(rejection.into_response()),
+            },
+            false => match Form::<T>::from_request(req, state).await {
+                Ok(Form(value)) => Ok(JsonOrForm(value)),
+                Err(rejection) => Err(rejection.into_response()),
+            },
+        }
+    }
+}
+
+/// Whether the request body is JSON, including types like `application/merge-patch+json`
+pub fn is_json(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .is_some_and(|mime| {
+            let mime = mime.trim();
+            mime == "application/json" || mime.ends_with("+json")
+        })
+}
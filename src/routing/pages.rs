@@ -1,52 +1,157 @@
 //! `routing::pages` responds to requests that should return rendered HTML (or other website assets) to the client
-use std::fs;
+use std::{
+    collections::HashMap,
+    path::{Component, PathBuf},
+    sync::{Arc, RwLock},
+};
 
 use askama_axum::Template;
 use axum::{
-    extract::{Path, Query, State},
-    http::{header, StatusCode},
-    response::{Html, IntoResponse},
+    extract::{Path, Query, RawQuery, State},
+    http::{header, HeaderMap, HeaderName, StatusCode},
+    response::{Html, IntoResponse, Redirect, Response},
     routing::get,
-    Router,
+    Json, Router,
 };
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    markdown::render_markdown,
-    model::{Manager, PasteReturn},
+    excerpt, links, markdown,
+    model::{Collection, Manager, PasteCredentials, PasteError, PasteReturn, Referrer},
+    negotiate::{self, Representation},
+    render, request_id,
+    sso::SsoRequirement,
+    style::PasteStyle,
 };
+use super::ResolvedUrl;
+
+/// Header carrying the SHA256 hash of raw paste content
+pub const CONTENT_SHA256_HEADER: HeaderName = HeaderName::from_static("x-content-sha256");
+
+/// How long clients and caches may keep a revision of a paste's raw content, in seconds, which never changes under its URL
+const REVISION_MAX_AGE: i64 = 365 * 24 * 60 * 60;
 
-pub fn routes(manager: Manager) -> Router {
+/// The directory static assets are served from
+pub const ASSET_DIR: &str = "./assets";
+
+pub fn routes(manager: Manager, assets: Assets) -> Router {
     Router::new()
         .route("/", get(root))
+        .route("/favicon.ico", get(favicon).with_state(assets))
         .route("/:url", get(view_paste_by_url))
         .route("/:url/edit", get(edit_paste_by_url))
+        .route("/:url/raw", get(raw_paste_by_url))
+        .route("/:url/raw/:revision", get(raw_revision))
+        .route("/:url/fragment", get(paste_fragment))
+        .route("/:url/export", get(export_paste))
+        .route("/c/:name", get(view_collection))
+        .route("/p/:id", get(paste_by_id))
+        .route("/out", get(outbound_link))
+        .route("/proxy/:signed", get(proxy_image))
         .with_state(manager)
 }
 
-pub fn reserved_routes() -> Router {
-    Router::new().route(
-        "/",
-        get(|| async { "This is a route reserved for pasties.".to_string() }),
-    )
+/// Pages about the instance itself, under `/meta`
+pub fn meta_routes(manager: Manager) -> Router {
+    Router::new()
+        .route(
+            "/",
+            get(|| async { "This is a route reserved for pasties.".to_string() }),
+        )
+        .route("/about", get(about_page))
+        .route("/rules", get(rules_page))
+        .route("/terms", get(terms_page))
+        .route("/privacy", get(privacy_page))
+        .route("/version", get(version))
+        .with_state(manager)
 }
 
-pub fn asset_routes() -> Router {
+pub fn asset_routes(assets: Assets) -> Router {
     Router::new()
         .route(
             "/",
             get(|| async { "This is a route reserved for pasties assets.".to_string() }),
         )
-        .route(
-            "/style.css",
-            get(|| async {
-                let stylesheet = fs::read_to_string("./assets/style.css");
-                match stylesheet {
-                    Err(e) => panic!("FATAL: Reading the stylesheet failed: {e}"),
-                    Ok(s) => (StatusCode::OK, [(header::CONTENT_TYPE, "text/css")], s),
+        .route("/*path", get(asset))
+        .with_state(assets)
+}
+
+/// Serves files from the assets directory
+/// Outside of development mode, files are cached in memory after their first read and marked as cacheable for clients
+#[derive(Clone, Default)]
+pub struct Assets {
+    dev:   bool,
+    cache: Arc<RwLock<HashMap<PathBuf, CachedAsset>>>,
+}
+
+#[derive(Clone)]
+struct CachedAsset {
+    mime:     String,
+    contents: Vec<u8>,
+}
+
+impl Assets {
+    pub fn new(dev: bool) -> Self {
+        Self {
+            dev,
+            cache: Arc::default(),
+        }
+    }
+
+    /// Serves a file with a content type inferred from its extension
+    /// Paths that would leave the assets directory are treated as missing
+    async fn serve(&self, path: &str) -> Response {
+        let relative = PathBuf::from(path);
+        if !relative
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+        {
+            return not_found_handler(HeaderMap::new()).await;
+        }
+        let cached = match self.cache.read() {
+            Ok(cache) => cache.get(&relative).cloned(),
+            Err(_) => None,
+        };
+        let CachedAsset { mime, contents } = match cached {
+            Some(asset) => asset,
+            None => match tokio::fs::read(PathBuf::from(ASSET_DIR).join(&relative)).await {
+                Ok(contents) => {
+                    let mime = mime_guess::from_path(&relative)
+                        .first_or_octet_stream()
+                        .to_string();
+                    let asset = CachedAsset { mime, contents };
+                    if !self.dev {
+                        if let Ok(mut cache) = self.cache.write() {
+                            cache.insert(relative, asset.clone());
+                        }
+                    }
+                    asset
                 }
-            }),
+                Err(_) => return not_found_handler(HeaderMap::new()).await,
+            },
+        };
+        let cache_control = match self.dev {
+            true => "no-store",
+            false => "public, max-age=3600",
+        };
+        (
+            StatusCode::OK,
+            [
+                (header::CONTENT_TYPE, mime),
+                (header::CACHE_CONTROL, cache_control.to_string()),
+            ],
+            contents,
         )
+            .into_response()
+    }
+}
+
+async fn asset(State(assets): State<Assets>, Path(path): Path<String>) -> Response {
+    assets.serve(&path).await
+}
+
+async fn favicon(State(assets): State<Assets>) -> Response {
+    assets.serve("images/logo.png").await
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -54,55 +159,195 @@ struct Modal {
     secret:  Option<String>,
     updated: Option<String>,
     message: Option<String>,
+    /// Set by the quarantine warning's link, to show the paste after all
+    proceed: Option<String>,
 }
 
 #[derive(Template)]
 #[template(path = "paste.html")]
 struct PasteView {
-    title: String,
+    title:     String,
+    /// Where the copy published to IPFS can be viewed, if there is one
+    ipfs_link: Option<String>,
+    paste:     PasteReturn,
+    modal:     Modal,
+}
+
+/// A paste rendered on its own, without the rest of the page, for embedding it on other sites
+#[derive(Template)]
+#[template(path = "fragment.html")]
+struct FragmentView {
+    /// The absolute URL of the paste's page
+    link:  String,
     paste: PasteReturn,
-    modal: Modal,
+}
+
+/// A rendered paste as served to `embed.js`
+#[derive(Serialize)]
+struct Fragment {
+    url:  String,
+    link: String,
+    /// The paste wrapped in its container and footer, with links relative to `link`
+    html: String,
+}
+
+/// A paste rendered as a page of its own, with the styles inlined so it can be kept offline
+#[derive(Template)]
+#[template(path = "export.html")]
+struct ExportView {
+    /// The address of the instance, which links in the paste are relative to
+    base_url: String,
+    paste:    PasteReturn,
+}
+
+/// The warning shown instead of a quarantined paste
+#[derive(Template)]
+#[template(path = "quarantine.html")]
+struct QuarantineView {
+    title: String,
+}
+
+#[derive(Template)]
+#[template(path = "collection.html")]
+struct CollectionView {
+    title:      String,
+    collection: Collection,
+}
+
+#[derive(Template)]
+#[template(path = "dashboard.html")]
+struct DashboardView {
+    title:  String,
+    /// Each paste along with the sites it was opened from the most
+    pastes: Vec<(PasteReturn, Vec<Referrer>)>,
 }
 
 #[derive(Template)]
 #[template(path = "editor.html")]
 struct EditorView {
-    title: String,
-    paste: Option<PasteReturn>,
+    title:           String,
+    paste:           Option<PasteReturn>,
+    /// Pastes pinned by the admins, with their content rendered. Only shown on the homepage
+    pinned:          Vec<PasteReturn>,
+    formats:         Vec<&'static str>,
+    selected_format: String,
+    /// The path of the namespace the editor creates pastes in, empty outside of namespaces
+    base_path:       String,
+}
+
+impl EditorView {
+    fn is_selected(&self, format: &str) -> bool {
+        self.selected_format == format
+    }
+
+    /// The style options the editor starts out with, the paste's own ones when editing it
+    fn style(&self) -> PasteStyle {
+        self.paste
+            .as_ref()
+            .map_or_else(PasteStyle::default, |paste| paste.style)
+    }
 }
 
 #[derive(Template)]
-#[template(path = "infoview.html")]
-struct InfoView {
+#[template(path = "out.html")]
+struct OutboundView {
+    title:  String,
+    target: String,
+}
+
+#[derive(Deserialize)]
+struct OutboundQuery {
+    url: Option<String>,
+}
+
+#[derive(Template)]
+#[template(path = "meta.html")]
+struct MetaView {
     title:   String,
     content: String,
 }
 
-pub async fn root() -> impl IntoResponse {
+#[derive(Serialize)]
+struct Version {
+    version: &'static str,
+    commit:  &'static str,
+    /// Seconds since the instance was started
+    uptime:  i64,
+}
+
+#[derive(Template)]
+#[template(path = "infoview.html")]
+struct InfoView {
+    title:      String,
+    content:    String,
+    request_id: Option<String>,
+}
+
+/// The homepage for command-line clients, `{base}` is replaced with the instance's URL
+const USAGE: &str = "pasties, a small pastebin
+
+create a paste, its url is printed back:
+    cat file | curl --data-urlencode api_paste_code@- -d api_option=paste {base}/api/api_post.php
+
+create a paste with a chosen url, format and password:
+    curl -d url=name -d format=markdown -d password=secret --data-urlencode content@file {base}/api
+
+view a paste:
+    curl {base}/<url>
+";
+
+pub async fn root(State(manager): State<Manager>, headers: HeaderMap) -> Response {
+    if negotiate::negotiate(&headers) == Representation::PlainText {
+        let host = headers
+            .get(header::HOST)
+            .and_then(|host| host.to_str().ok());
+        let usage = USAGE.replace("{base}", &manager.config().base_url(host));
+        return ([(header::VARY, "Accept, User-Agent")], usage).into_response();
+    }
+    // The homepage is still usable without the pinned pastes, so errors only hide them
+    let pinned = manager.pinned_pastes().await.unwrap_or_default();
     let editor = EditorView {
         title: "".to_string(),
         paste: None,
+        pinned,
+        formats: manager.formats(),
+        // New pastes have their format detected unless one is picked
+        selected_format: String::new(),
+        base_path: manager.base_path(),
     };
-    Html(editor.render().unwrap())
+    (
+        [(header::VARY, "Accept, User-Agent")],
+        Html(editor.render().unwrap()),
+    )
+        .into_response()
 }
 
 async fn edit_paste_by_url(
-    Path(url): Path<String>,
+    ResolvedUrl(url): ResolvedUrl,
+    Query(modal_query): Query<Modal>,
     State(manager): State<Manager>,
 ) -> impl IntoResponse {
-    match manager.retrieve_paste(url).await {
+    match manager
+        .retrieve_paste_or_draft(url, modal_query.secret)
+        .await
+    {
         Ok(paste) => Html(
             EditorView {
-                title: paste.url.clone(),
-                paste: Some(paste),
+                title:           paste.url.clone(),
+                selected_format: paste.format.clone(),
+                paste:           Some(paste),
+                pinned:          Vec::new(),
+                formats:         manager.formats(),
+                base_path:       manager.base_path(),
             }
             .render()
             .unwrap(),
         ),
         Err(e) => Html(
             InfoView {
-                title:   "Error".to_string(),
-                content: e.to_string(),
+                title:      "Error".to_string(),
+                content:    e.to_string(),
+                request_id: request_id::current(),
             }
             .render()
             .unwrap(),
@@ -110,38 +355,450 @@ async fn edit_paste_by_url(
     }
 }
 
+/// Serves a paste as a rendered page, JSON or plain text, depending on the request's `Accept` header
 async fn view_paste_by_url(
-    Path(url): Path<String>,
+    ResolvedUrl(url): ResolvedUrl,
     Query(modal_query): Query<Modal>,
     State(manager): State<Manager>,
-) -> impl IntoResponse {
-    match manager.retrieve_paste(url).await {
+    headers: HeaderMap,
+) -> Response {
+    let response = match negotiate::negotiate(&headers) {
+        Representation::Html => view_paste_page(url, modal_query, manager, &headers).await,
+        Representation::Json => match manager.retrieve_paste(url).await {
+            Ok(paste) => Json(paste).into_response(),
+            Err(e) => e.into_response(),
+        },
+        Representation::PlainText => raw_paste_by_url(ResolvedUrl(url), State(manager)).await,
+    };
+    ([(header::VARY, "Accept, User-Agent")], response).into_response()
+}
+
+async fn view_paste_page(
+    url: String,
+    modal_query: Modal,
+    manager: Manager,
+    headers: &HeaderMap,
+) -> Response {
+    let paste = match manager
+        .retrieve_paste_or_draft(url.clone(), modal_query.secret.clone())
+        .await
+    {
+        Err(PasteError::Quarantined) if modal_query.proceed.is_some() => {
+            manager.retrieve_quarantined_paste(url).await
+        }
+        Err(PasteError::Quarantined) => {
+            let warning = QuarantineView { title: url };
+            return Html(warning.render().unwrap()).into_response();
+        }
+        paste => paste,
+    };
+    match paste {
         Ok(mut paste) => {
-            paste.content = render_markdown(paste.content);
+            // Views with the password are the owner's own
+            let referrer = headers
+                .get(header::REFERER)
+                .and_then(|referrer| referrer.to_str().ok());
+            if let (Some(referrer), None) = (referrer, &modal_query.secret) {
+                let host = headers
+                    .get(header::HOST)
+                    .and_then(|host| host.to_str().ok());
+                let base_url = manager.config().base_url(host);
+                manager
+                    .record_referrer(&paste.url, referrer, &base_url)
+                    .await;
+            }
+            paste.content = manager.render(&paste).await;
             let paste_render = PasteView {
                 title: paste.url.to_string(),
+                ipfs_link: paste.ipfs_cid.as_ref().map(|cid| {
+                    manager
+                        .ipfs()
+                        .and_then(|ipfs| ipfs.gateway_url(cid))
+                        .unwrap_or_else(|| format!("ipfs://{cid}"))
+                }),
                 paste,
                 modal: modal_query,
             };
-            Html(paste_render.render().unwrap())
+            Html(paste_render.render().unwrap()).into_response()
         }
         Err(e) => {
             let paste_render = InfoView {
-                title:   "Error".to_string(),
-                content: e.to_string(),
+                title:      "Error".to_string(),
+                content:    e.to_string(),
+                request_id: request_id::current(),
             };
-            Html(paste_render.render().unwrap())
+            Html(paste_render.render().unwrap()).into_response()
         }
     }
 }
 
-pub async fn not_found_handler() -> impl IntoResponse {
+/// Lists a collection's pastes in order, as links
+async fn view_collection(Path(name): Path<String>, State(manager): State<Manager>) -> Response {
+    match manager.retrieve_collection(name).await {
+        Ok(collection) => Html(
+            CollectionView {
+                title: collection.name.clone(),
+                collection,
+            }
+            .render()
+            .unwrap(),
+        )
+        .into_response(),
+        Err(e) => Html(
+            InfoView {
+                title:      "Error".to_string(),
+                content:    e.to_string(),
+                request_id: request_id::current(),
+            }
+            .render()
+            .unwrap(),
+        )
+        .into_response(),
+    }
+}
+
+/// Lists the pastes the signed-in user claimed, as a page or as JSON. Browsers that aren't signed in are sent to sign in first
+pub async fn claimed_pastes(State(manager): State<Manager>, headers: HeaderMap) -> Response {
+    let representation = negotiate::negotiate(&headers);
+    let pastes = match manager.claimed_pastes().await {
+        Ok(pastes) => pastes,
+        Err(PasteError::SignInRequired) if representation == Representation::Html => {
+            return Redirect::to("/auth/login?return_to=/auth/pastes").into_response()
+        }
+        Err(e) => return e.into_response(),
+    };
+    if representation == Representation::Json {
+        return Json(pastes).into_response();
+    }
+    let mut with_referrers = Vec::new();
+    for paste in pastes {
+        // The signed-in user owns the paste, so no password is needed
+        let credentials = PasteCredentials {
+            url:      paste.url.clone(),
+            password: String::new(),
+        };
+        let referrers = manager.referrers(credentials).await.unwrap_or_default();
+        with_referrers.push((paste, referrers));
+    }
     Html(
-        InfoView {
-            title:   "Error 404".to_string(),
-            content: "The requested resource could not be found".to_string(),
+        DashboardView {
+            title:  "Your pastes".to_string(),
+            pastes: with_referrers,
         }
         .render()
         .unwrap(),
     )
+    .into_response()
+}
+
+/// Redirects to a paste by its base62 ID, so links built from IDs keep working after the paste is renamed
+/// The query string is kept, so e.g. `?secret=` still reaches the paste
+async fn paste_by_id(
+    Path(id): Path<String>,
+    RawQuery(query): RawQuery,
+    State(manager): State<Manager>,
+    headers: HeaderMap,
+) -> Response {
+    match manager.url_of_id(&id).await {
+        Ok(url) => {
+            let location = match query {
+                Some(query) => format!("/{url}?{query}"),
+                None => format!("/{url}"),
+            };
+            Redirect::temporary(&location).into_response()
+        }
+        Err(_) => not_found_handler(headers).await,
+    }
+}
+
+async fn about_page(State(manager): State<Manager>, headers: HeaderMap) -> Response {
+    let path = manager.config().meta.about.clone();
+    meta_page("About", path, headers).await
+}
+
+async fn rules_page(State(manager): State<Manager>, headers: HeaderMap) -> Response {
+    let path = manager.config().meta.rules.clone();
+    meta_page("Rules", path, headers).await
+}
+
+async fn terms_page(State(manager): State<Manager>, headers: HeaderMap) -> Response {
+    let path = manager.config().meta.terms.clone();
+    meta_page("Terms of service", path, headers).await
+}
+
+async fn privacy_page(State(manager): State<Manager>, headers: HeaderMap) -> Response {
+    let path = manager.config().meta.privacy.clone();
+    meta_page("Privacy policy", path, headers).await
+}
+
+/// Renders an operator-provided markdown file, which is read on every request so it can be edited without a restart
+/// Pages without a configured file are missing, pages whose file can't be read are logged and shown as missing
+async fn meta_page(title: &str, path: Option<String>, headers: HeaderMap) -> Response {
+    let Some(path) = path else {
+        return not_found_handler(headers).await;
+    };
+    match tokio::fs::read_to_string(&path).await {
+        Ok(markdown) => Html(
+            MetaView {
+                title:   title.to_string(),
+                content: markdown::render_markdown(&markdown),
+            }
+            .render()
+            .unwrap(),
+        )
+        .into_response(),
+        Err(e) => {
+            tracing::error!("Failed to read {path} for the {title} page: {e}");
+            not_found_handler(headers).await
+        }
+    }
+}
+
+/// Returns the running version of pasties as JSON
+async fn version(State(manager): State<Manager>) -> Json<Version> {
+    Json(Version {
+        version: env!("CARGO_PKG_VERSION"),
+        commit:  env!("PASTIES_COMMIT"),
+        uptime:  manager.uptime(),
+    })
+}
+
+/// Shows where an external link from a paste leads, before the reader follows it
+/// Only `http` and `https` targets are linked, anything else is treated as missing
+async fn outbound_link(Query(query): Query<OutboundQuery>, headers: HeaderMap) -> Response {
+    match query.url.filter(|url| links::is_web_url(url)) {
+        Some(target) => Html(
+            OutboundView {
+                title: "Leaving Pasties".to_string(),
+                target,
+            }
+            .render()
+            .unwrap(),
+        )
+        .into_response(),
+        None => not_found_handler(headers).await,
+    }
+}
+
+/// Serves an image from a rendered paste through the image proxy
+async fn proxy_image(
+    Path(signed): Path<String>,
+    State(manager): State<Manager>,
+    headers: HeaderMap,
+) -> Response {
+    match manager.image_proxy() {
+        Some(image_proxy) => image_proxy.serve(&signed).await,
+        None => not_found_handler(headers).await,
+    }
+}
+
+/// Serves a paste's content unrendered, as plain text
+async fn raw_paste_by_url(
+    ResolvedUrl(url): ResolvedUrl,
+    State(manager): State<Manager>,
+) -> Response {
+    match manager.retrieve_paste(url).await {
+        Ok(paste) => (
+            [
+                (
+                    header::CONTENT_TYPE,
+                    "text/plain; charset=utf-8".to_string(),
+                ),
+                (CONTENT_SHA256_HEADER, paste.content_sha256),
+            ],
+            paste.content,
+        )
+            .into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+/// Serves a paste's content unrendered, if its SHA256 hash is still `revision`
+/// Each revision has a URL of its own, so it can be cached for good. Shared caches are kept out on instances that require signing in,
+/// and pastes with a view limit aren't cached at all, so every view is still counted
+async fn raw_revision(
+    Path((url, revision)): Path<(String, String)>,
+    State(manager): State<Manager>,
+) -> Response {
+    let url = manager.resolve_url(url).await;
+    let paste = match manager.retrieve_revision(url, &revision).await {
+        Ok(paste) => paste,
+        Err(e) => return e.into_response(),
+    };
+    let config = manager.config();
+    let signed_in_only = config.private.is_some()
+        || config
+            .sso
+            .as_ref()
+            .is_some_and(|sso| sso.require == SsoRequirement::View);
+    let cache_control = match (paste.views_remaining, signed_in_only) {
+        (Some(_), _) => "no-store".to_string(),
+        (None, true) => format!("private, max-age={REVISION_MAX_AGE}, immutable"),
+        (None, false) => format!("public, max-age={REVISION_MAX_AGE}, immutable"),
+    };
+    (
+        [
+            (
+                header::CONTENT_TYPE,
+                "text/plain; charset=utf-8".to_string(),
+            ),
+            (header::CACHE_CONTROL, cache_control),
+            (CONTENT_SHA256_HEADER, paste.content_sha256),
+        ],
+        paste.content,
+    )
+        .into_response()
+}
+
+/// Serves a paste rendered for embedding on other sites, as used by `/assets/embed.js`
+/// Browsers get the HTML on its own, anything else gets JSON. Any site may fetch it, like it could link to the paste
+/// The paste's HTML is sanitized, as it ends up in the embedding page's document
+async fn paste_fragment(
+    ResolvedUrl(url): ResolvedUrl,
+    State(manager): State<Manager>,
+    headers: HeaderMap,
+) -> Response {
+    let mut paste = match manager.retrieve_paste(url).await {
+        Ok(paste) => paste,
+        Err(e) => return ([(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")], e).into_response(),
+    };
+    // Embedding pages don't send pasties' Content-Security-Policy, and shadow roots don't isolate scripts
+    paste.content = render::sanitize(&manager.render(&paste).await);
+    let host = headers
+        .get(header::HOST)
+        .and_then(|host| host.to_str().ok());
+    let link = format!("{}/{}", manager.config().base_url(host), paste.url);
+    let url = paste.url.clone();
+    let html = FragmentView {
+        link: link.clone(),
+        paste,
+    }
+    .render()
+    .unwrap();
+    let response = match negotiate::negotiate(&headers) {
+        Representation::Html => Html(html).into_response(),
+        _ => Json(Fragment { url, link, html }).into_response(),
+    };
+    (
+        [
+            (header::ACCESS_CONTROL_ALLOW_ORIGIN, "*"),
+            (header::VARY, "Accept, User-Agent"),
+        ],
+        response,
+    )
+        .into_response()
+}
+
+#[derive(Deserialize)]
+struct ExportQuery {
+    #[serde(default)]
+    format: String,
+}
+
+/// The representations a paste can be exported as
+enum ExportFormat {
+    /// The original source
+    Markdown,
+    Text,
+    /// A standalone page
+    Html,
+    /// The source with all of the paste's metadata
+    Json,
+}
+
+impl ExportFormat {
+    fn parse(format: &str) -> Option<Self> {
+        match format {
+            "md" => Some(ExportFormat::Markdown),
+            "txt" => Some(ExportFormat::Text),
+            "html" => Some(ExportFormat::Html),
+            "json" => Some(ExportFormat::Json),
+            _ => None,
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Markdown => "md",
+            ExportFormat::Text => "txt",
+            ExportFormat::Html => "html",
+            ExportFormat::Json => "json",
+        }
+    }
+
+    fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Markdown => "text/markdown; charset=utf-8",
+            ExportFormat::Text => "text/plain; charset=utf-8",
+            ExportFormat::Html => "text/html; charset=utf-8",
+            ExportFormat::Json => "application/json",
+        }
+    }
+}
+
+/// Downloads a paste for archiving, as `?format=md`, `txt`, `html` or `json`
+async fn export_paste(
+    ResolvedUrl(url): ResolvedUrl,
+    State(manager): State<Manager>,
+    Query(query): Query<ExportQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let Some(format) = ExportFormat::parse(&query.format) else {
+        return PasteError::InvalidFormat.into_response();
+    };
+    let mut paste = match manager.retrieve_paste(url).await {
+        Ok(paste) => paste,
+        Err(e) => return e.into_response(),
+    };
+    // Pastes in namespaces are named after their URL within the namespace
+    let file_name = format!(
+        "{}.{}",
+        paste.url.rsplit('/').next().unwrap_or(&paste.url),
+        format.extension()
+    );
+    let body = match format {
+        ExportFormat::Markdown => paste.content,
+        ExportFormat::Text => excerpt::plain_text(&paste.format, &paste.content),
+        ExportFormat::Html => {
+            paste.content = manager.render(&paste).await;
+            let host = headers
+                .get(header::HOST)
+                .and_then(|host| host.to_str().ok());
+            ExportView {
+                base_url: manager.config().base_url(host),
+                paste,
+            }
+            .render()
+            .unwrap()
+        }
+        ExportFormat::Json => serde_json::to_string_pretty(&paste).unwrap(),
+    };
+    (
+        [
+            (header::CONTENT_TYPE, format.content_type().to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{file_name}\""),
+            ),
+        ],
+        body,
+    )
+        .into_response()
+}
+
+pub async fn not_found_handler(headers: HeaderMap) -> Response {
+    if negotiate::negotiate(&headers) == Representation::PlainText {
+        let message = request_id::annotate("The requested resource could not be found".to_string());
+        return (StatusCode::NOT_FOUND, format!("{message}\n")).into_response();
+    }
+    let page = Html(
+        InfoView {
+            title:      "Error 404".to_string(),
+            content:    "The requested resource could not be found".to_string(),
+            request_id: request_id::current(),
+        }
+        .render()
+        .unwrap(),
+    );
+    (StatusCode::NOT_FOUND, page).into_response()
 }
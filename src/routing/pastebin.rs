@@ -0,0 +1,110 @@
+//! `routing::pastebin` accepts pastes in the shape of pastebin.com's `api_post.php`, so scripts written for it only need a URL change
+//! Only `api_option=paste` is supported. The developer key and privacy fields are accepted but ignored, and
+//! `api_paste_name` is ignored as well, since pastes have no titles
+
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::post,
+    Form, Router,
+};
+use serde::Deserialize;
+
+use crate::{
+    config::Config,
+    model::{Manager, NewPasteData},
+    utility,
+};
+
+#[derive(Clone)]
+struct PastebinState {
+    manager: Manager,
+    config:  Config,
+}
+
+pub fn routes(manager: Manager, config: Config) -> Router {
+    Router::new()
+        .route("/api/api_post.php", post(api_post))
+        .with_state(PastebinState { manager, config })
+}
+
+#[derive(Deserialize)]
+struct PastebinForm {
+    #[serde(default)]
+    api_option:            String,
+    #[serde(default)]
+    api_paste_code:        String,
+    #[serde(default)]
+    api_paste_format:      String,
+    #[serde(default)]
+    api_paste_expire_date: String,
+}
+
+/// Maps pastebin.com's syntax names onto formats. Names that are also formats are used as-is, anything else is detected from the content
+fn format_of(pastebin_format: &str) -> String {
+    match pastebin_format {
+        "text" => "plain".to_string(),
+        format => format.to_string(),
+    }
+}
+
+/// Maps pastebin.com's expiry codes onto seconds, months are counted as 30 days. `N` and unknown codes never expire
+fn expiry_of(pastebin_expiry: &str) -> Option<i64> {
+    const DAY: i64 = 24 * 60 * 60;
+    match pastebin_expiry {
+        "10M" => Some(10 * 60),
+        "1H" => Some(60 * 60),
+        "1D" => Some(DAY),
+        "1W" => Some(7 * DAY),
+        "2W" => Some(14 * DAY),
+        "1M" => Some(30 * DAY),
+        "6M" => Some(180 * DAY),
+        "1Y" => Some(365 * DAY),
+        _ => None,
+    }
+}
+
+/// Creates a paste and returns its URL as plain text, errors are reported as `Bad API request, ...` like pastebin.com does
+async fn api_post(
+    State(state): State<PastebinState>,
+    headers: HeaderMap,
+    Form(form): Form<PastebinForm>,
+) -> Response {
+    if form.api_option != "paste" {
+        return bad_request("invalid api_option");
+    }
+    let mut format = format_of(&form.api_paste_format);
+    if !state.manager.formats().contains(&format.as_str()) {
+        format = String::new();
+    }
+    let paste = NewPasteData {
+        url: String::new(),
+        content: form.api_paste_code,
+        password: utility::random_string(),
+        format,
+        draft: false,
+        max_views: None,
+        expires_in: expiry_of(&form.api_paste_expire_date),
+        font: None,
+        width: None,
+        wrap: None,
+    };
+    match state.manager.create_paste(paste).await {
+        Ok(url) => {
+            let host = headers
+                .get(header::HOST)
+                .and_then(|host| host.to_str().ok());
+            format!("{}/{url}", state.config.base_url(host)).into_response()
+        }
+        Err(e) => bad_request(&e.to_string().to_lowercase()),
+    }
+}
+
+fn bad_request(reason: &str) -> Response {
+    (
+        StatusCode::BAD_REQUEST,
+        format!("Bad API request, {reason}"),
+    )
+        .into_response()
+}
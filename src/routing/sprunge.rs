@@ -0,0 +1,57 @@
+//! `routing::sprunge` creates pastes from a plain request body posted to `/`, like sprunge.us, so `curl --data-binary @file` is all it takes
+//! Pastes created this way get a random URL and password, the password isn't returned, and their format is detected from their content
+
+use axum::{
+    extract::State,
+    http::{header, HeaderMap},
+    response::{IntoResponse, Response},
+    routing::post,
+    Router,
+};
+
+use crate::{
+    config::Config,
+    model::{Manager, NewPasteData},
+    utility,
+};
+
+#[derive(Clone)]
+struct SprungeState {
+    manager: Manager,
+    config:  Config,
+}
+
+pub fn routes(manager: Manager, config: Config) -> Router {
+    Router::new()
+        .route("/", post(create_paste))
+        .with_state(SprungeState { manager, config })
+}
+
+/// Creates a paste from the raw request body, whatever its content type, and returns its URL as plain text
+async fn create_paste(
+    State(state): State<SprungeState>,
+    headers: HeaderMap,
+    body: String,
+) -> Response {
+    let paste = NewPasteData {
+        url:        String::new(),
+        content:    body,
+        password:   utility::random_string(),
+        format:     String::new(),
+        draft:      false,
+        max_views:  None,
+        expires_in: None,
+        font:       None,
+        width:      None,
+        wrap:       None,
+    };
+    match state.manager.create_paste(paste).await {
+        Ok(url) => {
+            let host = headers
+                .get(header::HOST)
+                .and_then(|host| host.to_str().ok());
+            format!("{}/{url}\n", state.config.base_url(host)).into_response()
+        }
+        Err(e) => e.into_response(),
+    }
+}
@@ -0,0 +1,76 @@
+//! `secrets` looks for credentials in paste content, so people are warned before they publish one by accident
+//! The checks only match well-known token shapes, so they are cheap enough to run on every paste, but won't catch everything
+
+/// Characters tokens are made of, anything else separates them
+fn is_token_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '_' | '-')
+}
+
+fn is_aws_access_key(token: &str) -> bool {
+    (token.starts_with("AKIA") || token.starts_with("ASIA"))
+        && token.len() == 20
+        && token
+            .bytes()
+            .all(|b| b.is_ascii_uppercase() || b.is_ascii_digit())
+}
+
+fn is_github_token(token: &str) -> bool {
+    ["ghp_", "gho_", "ghu_", "ghs_", "ghr_", "github_pat_"]
+        .iter()
+        .any(|prefix| token.starts_with(prefix) && token.len() >= prefix.len() + 30)
+}
+
+fn is_slack_token(token: &str) -> bool {
+    ["xoxb-", "xoxa-", "xoxp-", "xoxr-", "xoxs-"]
+        .iter()
+        .any(|prefix| token.starts_with(prefix) && token.len() >= prefix.len() + 20)
+}
+
+/// Whether a bearer token follows `Bearer `, like in a copied `Authorization` header
+fn has_bearer_token(content: &str) -> bool {
+    content.match_indices("Bearer ").any(|(index, _)| {
+        content[index + 7..]
+            .chars()
+            .take_while(|c| is_token_char(*c) || matches!(c, '.' | '~' | '+' | '/' | '='))
+            .count()
+            >= 20
+    })
+}
+
+/// Returns a description of every kind of credential the content seems to contain
+pub fn scan(content: &str) -> Vec<&'static str> {
+    let mut found = Vec::new();
+    if content.contains("-----BEGIN") && content.contains("PRIVATE KEY-----") {
+        found.push("a private key");
+    }
+    let tokens: Vec<&str> = content
+        .split(|c: char| !is_token_char(c))
+        .filter(|token| token.len() >= 20)
+        .collect();
+    if tokens.iter().any(|token| is_aws_access_key(token)) {
+        found.push("an AWS access key");
+    }
+    if tokens.iter().any(|token| is_github_token(token)) {
+        found.push("a GitHub token");
+    }
+    if tokens.iter().any(|token| is_slack_token(token)) {
+        found.push("a Slack token");
+    }
+    if has_bearer_token(content) {
+        found.push("a bearer token");
+    }
+    found
+}
+
+/// Describes what was found in a sentence, or returns `None` if the content looks clean
+pub fn warning(content: &str) -> Option<String> {
+    let found = scan(content);
+    match found.as_slice() {
+        [] => None,
+        [kind] => Some(format!("The content looks like it contains {kind}")),
+        [kinds @ .., last] => Some(format!(
+            "The content looks like it contains {} and {last}",
+            kinds.join(", ")
+        )),
+    }
+}
@@ -0,0 +1,341 @@
+//! `server` binds every configured listener, TCP or unix socket, and serves the same router on each of them
+
+use std::{
+    fs, io,
+    net::SocketAddr,
+    path::PathBuf,
+    pin::pin,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{extract::ConnectInfo, Router};
+use hyper::body::Incoming;
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo, TokioTimer},
+    server::{conn::auto::Builder, graceful::GracefulConnection},
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::{TcpListener, UnixListener},
+    sync::{OwnedSemaphorePermit, Semaphore},
+    task::JoinSet,
+};
+use tower::Service;
+
+use crate::{
+    acme,
+    config::{AcmeConfig, Config, ServerConfig},
+    systemd::{self, InheritedSocket},
+};
+
+/// hyper doesn't accept smaller limits for the headers of HTTP/1 requests, as it reads them into a buffer of at least this size
+const MIN_HEADER_BYTES: usize = 8192;
+
+/// Checks that the `[server]` limits can be applied
+pub fn check_config(config: &Config) -> Result<(), String> {
+    match (
+        config.server.max_connections,
+        config.server.max_header_bytes,
+    ) {
+        (Some(0), _) => Err("[server] max_connections has to be at least 1".to_string()),
+        (Some(connections), _) if connections > Semaphore::MAX_PERMITS => Err(format!(
+            "[server] max_connections can be at most {}",
+            Semaphore::MAX_PERMITS
+        )),
+        (_, Some(bytes)) if bytes < MIN_HEADER_BYTES => Err(format!(
+            "[server] max_header_bytes has to be at least {MIN_HEADER_BYTES}"
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// How connections are served, following the `[server]` settings. Every listener shares one, so the connection limit applies to all of them together
+#[derive(Clone)]
+pub struct Transport {
+    builder:      Builder<TokioExecutor>,
+    http2:        bool,
+    /// Held by every open connection, if the number of connections is limited
+    connections:  Option<Arc<Semaphore>>,
+    /// How long a connection may go without a request before it is closed
+    idle_timeout: Duration,
+}
+
+/// The requests a connection is serving, and when it last started or finished one
+struct Activity {
+    in_flight: usize,
+    since:     Instant,
+}
+
+/// Counts a request as in flight until it is dropped, once its response was produced
+struct InFlight(Arc<Mutex<Activity>>);
+
+impl InFlight {
+    fn start(activity: &Arc<Mutex<Activity>>) -> Self {
+        let mut current = activity.lock().unwrap();
+        current.in_flight += 1;
+        current.since = Instant::now();
+        Self(activity.clone())
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        let mut current = self.0.lock().unwrap();
+        current.in_flight -= 1;
+        current.since = Instant::now();
+    }
+}
+
+impl Transport {
+    pub fn new(config: &ServerConfig) -> Self {
+        let mut builder = Builder::new(TokioExecutor::new());
+        if !config.http2 {
+            builder = builder.http1_only();
+        }
+        let mut http1 = builder.http1();
+        http1.timer(TokioTimer::new());
+        match config.keep_alive_secs {
+            0 => http1.keep_alive(false),
+            secs => http1
+                .keep_alive(true)
+                .header_read_timeout(Duration::from_secs(secs)),
+        };
+        if let Some(bytes) = config.max_header_bytes {
+            http1.max_buf_size(bytes);
+        }
+        // HTTP/2 can't close connections after every response, so they are closed once they were idle for a second instead
+        let idle_timeout = Duration::from_secs(config.keep_alive_secs.max(1));
+        let mut http2 = builder.http2();
+        // Pings find peers that went away while a request is being served, idle connections are closed by `drive`
+        http2
+            .timer(TokioTimer::new())
+            .keep_alive_interval(idle_timeout)
+            .keep_alive_timeout(idle_timeout);
+        if let Some(bytes) = config.max_header_bytes {
+            http2.max_header_list_size(bytes.try_into().unwrap_or(u32::MAX));
+        }
+        Self {
+            builder,
+            http2: config.http2,
+            connections: config
+                .max_connections
+                .map(|limit| Arc::new(Semaphore::new(limit))),
+            idle_timeout,
+        }
+    }
+
+    /// Whether connections may speak HTTP/2
+    pub fn http2(&self) -> bool {
+        self.http2
+    }
+
+    /// Waits until another connection may be opened, the returned permit is held until it closes
+    pub async fn admit(&self) -> Option<OwnedSemaphorePermit> {
+        match &self.connections {
+            // The semaphore is never closed
+            Some(connections) => Some(connections.clone().acquire_owned().await.unwrap()),
+            None => None,
+        }
+    }
+
+    /// Serves a single connection on its own task
+    /// The peer's address is made available to handlers as `ConnectInfo`, if the connection has one
+    pub fn serve_connection<I>(
+        &self,
+        io: I,
+        address: Option<SocketAddr>,
+        app: Router,
+        permit: Option<OwnedSemaphorePermit>,
+    ) where
+        I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let activity = Arc::new(Mutex::new(Activity {
+            in_flight: 0,
+            since:     Instant::now(),
+        }));
+        let service = {
+            let activity = activity.clone();
+            hyper::service::service_fn(move |mut request: hyper::Request<Incoming>| {
+                if let Some(address) = address {
+                    request.extensions_mut().insert(ConnectInfo(address));
+                }
+                let in_flight = InFlight::start(&activity);
+                let response = app.clone().call(request);
+                async move {
+                    let response = response.await;
+                    drop(in_flight);
+                    response
+                }
+            })
+        };
+        let builder = self.builder.clone();
+        let http2 = self.http2;
+        let idle_timeout = self.idle_timeout;
+        tokio::spawn(async move {
+            let io = TokioIo::new(io);
+            // hyper only keeps connections to HTTP/1 if they can't be upgraded, which nothing in pasties needs
+            let served = match http2 {
+                true => {
+                    let connection = builder.serve_connection_with_upgrades(io, service);
+                    drive(connection, &activity, idle_timeout).await
+                }
+                false => {
+                    let connection = builder.serve_connection(io, service);
+                    drive(connection, &activity, idle_timeout).await
+                }
+            };
+            if let Err(e) = served {
+                tracing::debug!("Connection closed with an error: {e}");
+            }
+            drop(permit);
+        });
+    }
+}
+
+/// Runs a connection until it closes, shutting it down gracefully once it went without requests for `idle_timeout`
+/// hyper only has an idle timeout for HTTP/1, HTTP/2 connections would otherwise be kept open, and hold on to their permit, for as long as their clients like
+async fn drive<C: GracefulConnection>(
+    connection: C,
+    activity: &Mutex<Activity>,
+    idle_timeout: Duration,
+) -> Result<(), C::Error> {
+    let mut connection = pin!(connection);
+    loop {
+        let since = activity.lock().unwrap().since;
+        tokio::select! {
+            served = connection.as_mut() => return served,
+            _ = tokio::time::sleep_until((since + idle_timeout).into()) => {}
+        }
+        let is_idle = {
+            let current = activity.lock().unwrap();
+            current.in_flight == 0 && current.since.elapsed() >= idle_timeout
+        };
+        if is_idle {
+            connection.as_mut().graceful_shutdown();
+            return connection.await;
+        }
+    }
+}
+
+pub enum Listener {
+    Tcp(TcpListener),
+    Unix(UnixListener, PathBuf),
+    /// A TCP listener serving TLS with certificates provisioned through ACME
+    Acme(TcpListener, AcmeConfig),
+}
+
+impl Listener {
+    fn describe(&self) -> String {
+        match self {
+            Self::Tcp(listener) => match listener.local_addr() {
+                Ok(address) => format!("http://{address}"),
+                Err(_) => "a TCP socket".to_string(),
+            },
+            // Abstract sockets passed in by systemd have no path
+            Self::Unix(_, path) if path.as_os_str().is_empty() => "a unix socket".to_string(),
+            Self::Unix(_, path) => format!("unix:{}", path.display()),
+            Self::Acme(_, config) => match config.domains.first() {
+                Some(domain) => format!("https://{domain}"),
+                None => "a TLS socket".to_string(),
+            },
+        }
+    }
+}
+
+/// Binds the listeners given in the `listen` setting, or takes over the sockets passed in by systemd (`inherited`) if there are any
+/// The TLS listener from the `[acme]` section is bound as well, if it is present, even when systemd passed in sockets
+/// Entries are either socket addresses like `127.0.0.1:7878` and `[::1]:7878`, or unix socket paths prefixed with `unix:`
+/// **Panics** if an entry is malformed or cannot be bound, as the server would otherwise be unreachable
+pub async fn bind(config: &Config, inherited: Vec<InheritedSocket>) -> Vec<Listener> {
+    let mut listeners: Vec<Listener> = inherited
+        .into_iter()
+        .map(|socket| match socket {
+            InheritedSocket::Tcp(listener) => {
+                listener.set_nonblocking(true).unwrap();
+                Listener::Tcp(TcpListener::from_std(listener).unwrap())
+            }
+            InheritedSocket::Unix(listener) => {
+                listener.set_nonblocking(true).unwrap();
+                let path = listener
+                    .local_addr()
+                    .ok()
+                    .and_then(|address| address.as_pathname().map(PathBuf::from))
+                    .unwrap_or_default();
+                Listener::Unix(UnixListener::from_std(listener).unwrap(), path)
+            }
+        })
+        .collect();
+    if listeners.is_empty() {
+        for entry in &config.listen {
+            let listener = match entry.strip_prefix("unix:") {
+                Some(path) => {
+                    // A socket file left behind by a previous run would make binding fail
+                    let _ = fs::remove_file(path);
+                    match UnixListener::bind(path) {
+                        Ok(listener) => Listener::Unix(listener, PathBuf::from(path)),
+                        Err(e) => panic!("Failed to bind to {entry}:\n    {e}"),
+                    }
+                }
+                None => {
+                    let address = match entry.parse::<SocketAddr>() {
+                        Ok(address) => address,
+                        Err(e) => panic!("Invalid listen address {entry}:\n    {e}"),
+                    };
+                    match TcpListener::bind(address).await {
+                        Ok(listener) => Listener::Tcp(listener),
+                        Err(e) => panic!("Failed to bind to {entry}:\n    {e}"),
+                    }
+                }
+            };
+            listeners.push(listener);
+        }
+    }
+    // Socket units usually only pass the plain listeners, so the TLS listener is bound either way
+    if let Some(acme) = &config.acme {
+        match TcpListener::bind(&acme.listen).await {
+            Ok(listener) => listeners.push(Listener::Acme(listener, acme.clone())),
+            Err(e) => panic!("Failed to bind to {}:\n    {e}", acme.listen),
+        }
+    }
+    listeners
+}
+
+/// Serves `app` on every listener until the process is stopped
+pub async fn serve(listeners: Vec<Listener>, app: Router, config: &ServerConfig) {
+    let transport = Transport::new(config);
+    let mut servers = JoinSet::new();
+    for listener in listeners {
+        tracing::info!("Starting server at {}!", listener.describe());
+        servers.spawn(accept_loop(listener, app.clone(), transport.clone()));
+    }
+    systemd::notify_ready();
+    while servers.join_next().await.is_some() {}
+}
+
+async fn accept_loop(listener: Listener, app: Router, transport: Transport) {
+    if let Listener::Acme(listener, config) = listener {
+        return acme::serve(listener, config, app, transport).await;
+    }
+    loop {
+        let permit = transport.admit().await;
+        let accepted = match &listener {
+            Listener::Tcp(listener) => listener.accept().await.map(|(stream, address)| {
+                transport.serve_connection(stream, Some(address), app.clone(), permit);
+            }),
+            Listener::Unix(listener, _) => listener.accept().await.map(|(stream, _)| {
+                transport.serve_connection(stream, None, app.clone(), permit);
+            }),
+            Listener::Acme(..) => unreachable!(),
+        };
+        if let Err(e) = accepted {
+            accept_error(e).await;
+        }
+    }
+}
+
+/// Errors like running out of file descriptors are temporary, so accepting is retried after a short pause instead of taking the listener down
+async fn accept_error(e: io::Error) {
+    tracing::warn!("Failed to accept a connection: {e}");
+    tokio::time::sleep(Duration::from_secs(1)).await;
+}
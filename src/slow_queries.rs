@@ -0,0 +1,52 @@
+//! `slow_queries` times database calls, logging the ones that take at least `[logging] slow_query_ms` and counting them for `/api/admin/metrics`
+//! A steady stream of slow calls usually means the SQLite file needs a `vacuum`, or a query is missing an index
+
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Instant,
+};
+
+/// The threshold in milliseconds, 0 if slow calls aren't logged
+static THRESHOLD: AtomicU64 = AtomicU64::new(0);
+/// How many calls have been slower than the threshold since the instance started
+static COUNT: AtomicU64 = AtomicU64::new(0);
+
+/// Sets the threshold above which database calls are logged, 0 turns logging them off
+pub fn init(threshold_ms: u64) {
+    THRESHOLD.store(threshold_ms, Ordering::Relaxed);
+}
+
+/// How many database calls have been slow since the instance started
+pub fn count() -> u64 {
+    COUNT.load(Ordering::Relaxed)
+}
+
+/// Times a database call from its creation until it is dropped, at the end of the call
+pub struct Timer {
+    /// The name of the `database` function, which identifies the query
+    name:    &'static str,
+    started: Instant,
+}
+
+impl Timer {
+    pub fn start(name: &'static str) -> Self {
+        Self {
+            name,
+            started: Instant::now(),
+        }
+    }
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        let threshold = THRESHOLD.load(Ordering::Relaxed);
+        let elapsed = self.started.elapsed().as_millis() as u64;
+        if threshold > 0 && elapsed >= threshold {
+            COUNT.fetch_add(1, Ordering::Relaxed);
+            tracing::warn!(
+                "The database call {} took {elapsed}ms, the slow query threshold is {threshold}ms",
+                self.name
+            );
+        }
+    }
+}
@@ -0,0 +1,592 @@
+//! `sso` signs users in through an OpenID Connect provider, like Keycloak or Authentik, so instances can require SSO for creating or viewing pastes
+//! Signing in uses the authorization code flow with PKCE. Sessions are kept in a signed cookie, so the only thing pasties stores about its users is the subject of the pastes they claim
+//! Members of the configured admin groups can use the admin endpoints without the admin token
+
+use std::{sync::Arc, time::Duration};
+
+use axum::{
+    extract::{Query, Request, State},
+    http::{header, HeaderMap, Method, StatusCode},
+    middleware::Next,
+    response::{AppendHeaders, IntoResponse, Redirect, Response},
+    routing::get,
+    Router,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use tokio::sync::OnceCell;
+use url::Url;
+
+use crate::{
+    config::{Config, SsoConfig},
+    mirror,
+    model::Manager,
+    negotiate::{self, Representation},
+    read_only, request_id,
+    routing::pages,
+    utility,
+};
+
+/// The cookie holding a signed-in user's session
+const SESSION_COOKIE: &str = "pasties_session";
+
+/// The cookie holding the state of a sign-in in progress, until the provider redirects back
+const LOGIN_COOKIE: &str = "pasties_login";
+
+/// How long a session lasts, in seconds
+const SESSION_LIFETIME: i64 = 12 * 60 * 60;
+
+/// How long a user has to finish signing in at the provider, in seconds
+const LOGIN_LIFETIME: i64 = 10 * 60;
+
+/// How long requests to the provider may take
+const PROVIDER_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Which requests require signing in
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SsoRequirement {
+    /// Creating and changing pastes, reading them stays public
+    #[default]
+    Create,
+    /// Everything except static assets and the instance's `/meta` pages
+    View,
+}
+
+/// The endpoints of the provider, from its discovery document
+#[derive(Deserialize, Debug, Clone)]
+struct Discovery {
+    issuer:                 String,
+    authorization_endpoint: String,
+    token_endpoint:         String,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+/// A signed-in user
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Session {
+    /// The user's subject identifier at the provider
+    pub sub:   String,
+    pub name:  String,
+    /// Whether the user is in one of the admin groups
+    pub admin: bool,
+    expires:   i64,
+}
+
+/// A sign-in in progress
+#[derive(Serialize, Deserialize)]
+struct Login {
+    state:     String,
+    nonce:     String,
+    verifier:  String,
+    return_to: String,
+    expires:   i64,
+}
+
+#[derive(Clone)]
+pub struct Sso {
+    config:       SsoConfig,
+    redirect_uri: String,
+    /// Cookies are only sent over HTTPS when pasties is served over it
+    secure:       bool,
+    key:          Vec<u8>,
+    client:       reqwest::Client,
+    discovery:    Arc<OnceCell<Discovery>>,
+}
+
+impl Sso {
+    pub fn new(config: &Config, sso: &SsoConfig) -> Self {
+        let base_url = config.base_url(None);
+        let key = match &sso.session_key {
+            Some(key) => key.as_bytes().to_vec(),
+            None => rand::thread_rng().gen::<[u8; 32]>().to_vec(),
+        };
+        Self {
+            config: sso.clone(),
+            redirect_uri: format!("{base_url}/auth/callback"),
+            secure: base_url.starts_with("https://"),
+            key,
+            client: reqwest::Client::new(),
+            discovery: Arc::default(),
+        }
+    }
+
+    /// Fetches the provider's discovery document, once
+    async fn discovery(&self) -> Result<&Discovery, String> {
+        self.discovery
+            .get_or_try_init(|| async {
+                let url = format!(
+                    "{}/.well-known/openid-configuration",
+                    self.config.issuer.trim_end_matches('/')
+                );
+                self.client
+                    .get(url)
+                    .timeout(PROVIDER_TIMEOUT)
+                    .send()
+                    .await
+                    .and_then(|response| response.error_for_status())
+                    .map_err(|e| e.to_string())?
+                    .json::<Discovery>()
+                    .await
+                    .map_err(|e| e.to_string())
+            })
+            .await
+    }
+
+    fn mac(&self) -> Hmac<Sha256> {
+        // HMAC accepts keys of any length
+        Hmac::<Sha256>::new_from_slice(&self.key).unwrap()
+    }
+
+    /// Serializes and signs a cookie's value, as `<payload>.<signature>`
+    fn sign<T: Serialize>(&self, value: &T) -> String {
+        // Sessions and logins are plain structs, serializing them can't fail
+        let payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(value).unwrap());
+        let mut mac = self.mac();
+        mac.update(payload.as_bytes());
+        let signature = URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes());
+        format!("{payload}.{signature}")
+    }
+
+    /// Returns a signed cookie's value, if its signature is valid
+    fn verify<T: for<'de> Deserialize<'de>>(&self, signed: &str) -> Option<T> {
+        let (payload, signature) = signed.split_once('.')?;
+        let mut mac = self.mac();
+        mac.update(payload.as_bytes());
+        mac.verify_slice(&URL_SAFE_NO_PAD.decode(signature).ok()?)
+            .ok()?;
+        serde_json::from_slice(&URL_SAFE_NO_PAD.decode(payload).ok()?).ok()
+    }
+
+    fn cookie(&self, name: &str, value: &str, max_age: i64) -> String {
+        let secure = match self.secure {
+            true => "; Secure",
+            false => "",
+        };
+        format!("{name}={value}; Path=/; Max-Age={max_age}; HttpOnly; SameSite=Lax{secure}")
+    }
+
+    /// The signed-in user, if the request carries a valid session
+    pub fn session(&self, headers: &HeaderMap) -> Option<Session> {
+        let session: Session = self.verify(&cookie(headers, SESSION_COOKIE)?)?;
+        match session.expires > utility::unix_timestamp() {
+            true => Some(session),
+            false => None,
+        }
+    }
+
+    /// Whether a request can only be made by signed-in users
+    fn requires_session(&self, method: &Method, path: &str) -> bool {
+        if path.starts_with("/auth/") || path.starts_with("/assets/") || path == "/favicon.ico" {
+            return false;
+        }
+        // Mirrored changes are authenticated by their signature instead
+        if path == mirror::PATH {
+            return false;
+        }
+        match self.config.require {
+            SsoRequirement::Create => !matches!(*method, Method::GET | Method::HEAD),
+            SsoRequirement::View => !path.starts_with("/meta/"),
+        }
+    }
+
+    /// Sends the user to the provider, with a new sign-in's state kept in a cookie
+    async fn login(&self, return_to: String) -> Result<Response, String> {
+        let discovery = self.discovery().await?;
+        let login = Login {
+            state: random_token(),
+            nonce: random_token(),
+            verifier: random_token(),
+            return_to,
+            expires: utility::unix_timestamp() + LOGIN_LIFETIME,
+        };
+        let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(login.verifier.as_bytes()));
+        let mut url = Url::parse(&discovery.authorization_endpoint).map_err(|e| e.to_string())?;
+        url.query_pairs_mut()
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &self.config.client_id)
+            .append_pair("redirect_uri", &self.redirect_uri)
+            .append_pair("scope", &self.config.scopes)
+            .append_pair("state", &login.state)
+            .append_pair("nonce", &login.nonce)
+            .append_pair("code_challenge", &challenge)
+            .append_pair("code_challenge_method", "S256");
+        let cookie = self.cookie(LOGIN_COOKIE, &self.sign(&login), LOGIN_LIFETIME);
+        Ok(([(header::SET_COOKIE, cookie)], Redirect::to(url.as_str())).into_response())
+    }
+
+    /// Finishes a sign-in, exchanging the code for an ID token and starting a session from its claims
+    async fn callback(
+        &self,
+        headers: &HeaderMap,
+        query: CallbackQuery,
+    ) -> Result<Response, String> {
+        let login: Login = cookie(headers, LOGIN_COOKIE)
+            .and_then(|login| self.verify(&login))
+            .ok_or("the sign-in has no state, it may have been started in another browser")?;
+        if login.expires < utility::unix_timestamp() {
+            return Err("the sign-in took too long".to_string());
+        }
+        if query.state != login.state {
+            return Err("the sign-in's state doesn't match".to_string());
+        }
+        let discovery = self.discovery().await?;
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", query.code.as_str()),
+            ("redirect_uri", self.redirect_uri.as_str()),
+            ("client_id", self.config.client_id.as_str()),
+            ("code_verifier", login.verifier.as_str()),
+        ];
+        if let Some(secret) = &self.config.client_secret {
+            form.push(("client_secret", secret));
+        }
+        let tokens: TokenResponse = self
+            .client
+            .post(&discovery.token_endpoint)
+            .form(&form)
+            .timeout(PROVIDER_TIMEOUT)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| e.to_string())?
+            .json()
+            .await
+            .map_err(|e| e.to_string())?;
+        let claims = self.claims(&tokens.id_token, discovery, &login.nonce)?;
+        let session = Session {
+            name:    ["preferred_username", "name", "email", "sub"]
+                .iter()
+                .find_map(|claim| claims.get(claim).and_then(Value::as_str))
+                .unwrap_or_default()
+                .to_string(),
+            sub:     claims["sub"].as_str().unwrap_or_default().to_string(),
+            admin:   self.is_admin(&claims),
+            expires: utility::unix_timestamp() + SESSION_LIFETIME,
+        };
+        tracing::info!("{} signed in", session.name);
+        let cookies = AppendHeaders([
+            (
+                header::SET_COOKIE,
+                self.cookie(SESSION_COOKIE, &self.sign(&session), SESSION_LIFETIME),
+            ),
+            (header::SET_COOKIE, self.cookie(LOGIN_COOKIE, "", 0)),
+        ]);
+        Ok((cookies, Redirect::to(&login.return_to)).into_response())
+    }
+
+    /// Reads and checks the claims of an ID token
+    /// The token's signature isn't checked: it was received straight from the token endpoint, whose TLS certificate vouches for the issuer,
+    /// which OpenID Connect Core 1.0, section 3.1.3.7, allows in place of validating the signature
+    fn claims(&self, id_token: &str, discovery: &Discovery, nonce: &str) -> Result<Value, String> {
+        let payload = id_token
+            .split('.')
+            .nth(1)
+            .ok_or("the ID token is malformed")?;
+        let claims: Value = URL_SAFE_NO_PAD
+            .decode(payload.trim_end_matches('='))
+            .ok()
+            .and_then(|payload| serde_json::from_slice(&payload).ok())
+            .ok_or("the ID token is malformed")?;
+        let audience_matches = match &claims["aud"] {
+            Value::String(audience) => *audience == self.config.client_id,
+            Value::Array(audiences) => audiences
+                .iter()
+                .any(|audience| *audience == *self.config.client_id),
+            _ => false,
+        };
+        if claims["iss"] != *discovery.issuer {
+            return Err("the ID token was issued by someone else".to_string());
+        }
+        if !audience_matches {
+            return Err("the ID token was issued for someone else".to_string());
+        }
+        if claims["exp"].as_i64().unwrap_or_default() < utility::unix_timestamp() {
+            return Err("the ID token has expired".to_string());
+        }
+        if claims["nonce"] != *nonce {
+            return Err("the ID token's nonce doesn't match".to_string());
+        }
+        if !claims["sub"].is_string() {
+            return Err("the ID token has no subject".to_string());
+        }
+        Ok(claims)
+    }
+
+    /// Whether the groups claim contains one of the admin groups. Some providers send a single group as a string
+    fn is_admin(&self, claims: &Value) -> bool {
+        let in_group = |group: &Value| {
+            group
+                .as_str()
+                .is_some_and(|group| self.config.admin_groups.iter().any(|admin| admin == group))
+        };
+        match &claims[&self.config.groups_claim] {
+            Value::Array(groups) => groups.iter().any(in_group),
+            group => in_group(group),
+        }
+    }
+}
+
+/// 32 random bytes, URL-safe. Also long enough for a PKCE verifier, which needs at least 43 characters
+fn random_token() -> String {
+    URL_SAFE_NO_PAD.encode(rand::thread_rng().gen::<[u8; 32]>())
+}
+
+/// Reads a cookie from the request's `Cookie` headers
+fn cookie(headers: &HeaderMap, name: &str) -> Option<String> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value.to_string())
+}
+
+/// Only paths on pasties itself are returned to after signing in, so the sign-in can't be used to redirect elsewhere
+fn local_path(path: Option<String>) -> String {
+    match path {
+        Some(path) if path.starts_with('/') && !path.starts_with("//") && !path.contains('\\') => {
+            path
+        }
+        _ => "/".to_string(),
+    }
+}
+
+pub fn routes(manager: Manager) -> Router {
+    Router::new()
+        .route("/login", get(login))
+        .route("/callback", get(callback))
+        .route("/logout", get(logout))
+        .route("/pastes", get(pages::claimed_pastes))
+        .with_state(manager)
+}
+
+#[derive(Deserialize)]
+struct LoginQuery {
+    return_to: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct CallbackQuery {
+    #[serde(default)]
+    code:  String,
+    #[serde(default)]
+    state: String,
+    /// Set by the provider if the user didn't sign in
+    error: Option<String>,
+}
+
+async fn login(State(manager): State<Manager>, Query(query): Query<LoginQuery>) -> Response {
+    let Some(sso) = manager.sso() else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    match sso.login(local_path(query.return_to)).await {
+        Ok(response) => response,
+        Err(e) => {
+            tracing::warn!("Failed to start signing in: {e}");
+            failure(
+                StatusCode::BAD_GATEWAY,
+                "The sign-in provider can't be reached",
+            )
+        }
+    }
+}
+
+async fn callback(
+    State(manager): State<Manager>,
+    headers: HeaderMap,
+    Query(query): Query<CallbackQuery>,
+) -> Response {
+    let Some(sso) = manager.sso() else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    if let Some(error) = &query.error {
+        tracing::debug!("The sign-in provider returned an error: {error}");
+        return failure(
+            StatusCode::UNAUTHORIZED,
+            "Signing in was cancelled or refused",
+        );
+    }
+    match sso.callback(&headers, query).await {
+        Ok(response) => response,
+        Err(e) => {
+            tracing::warn!("Failed to sign a user in: {e}");
+            failure(
+                StatusCode::UNAUTHORIZED,
+                "Signing in failed, please try again",
+            )
+        }
+    }
+}
+
+async fn logout(State(manager): State<Manager>) -> Response {
+    let Some(sso) = manager.sso() else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    (
+        [(header::SET_COOKIE, sso.cookie(SESSION_COOKIE, "", 0))],
+        Redirect::to("/"),
+    )
+        .into_response()
+}
+
+fn failure(status: StatusCode, message: &str) -> Response {
+    (status, request_id::annotate(message.to_string())).into_response()
+}
+
+tokio::task_local! {
+    static USER: Option<String>;
+}
+
+/// The subject of the user signed in for the request currently being handled, if there is one
+pub fn current_user() -> Option<String> {
+    USER.try_with(|user| user.clone()).ok().flatten()
+}
+
+/// Middleware that makes the signed-in user available to the rest of the request, so the pastes they claimed can be changed without passwords
+pub async fn provide_user(State(manager): State<Manager>, req: Request, next: Next) -> Response {
+    let user = manager
+        .sso()
+        .and_then(|sso| sso.session(req.headers()))
+        .map(|session| session.sub);
+    USER.scope(user, next.run(req)).await
+}
+
+/// Middleware that turns away requests that require signing in, unless they carry a session
+/// Browsers are sent to sign in and brought back afterwards, other clients are refused
+pub async fn require_session(State(manager): State<Manager>, req: Request, next: Next) -> Response {
+    let Some(sso) = manager.sso() else {
+        return next.run(req).await;
+    };
+    // Scripts on read-only instances write with API keys instead of sessions
+    let has_api_key = manager.config().read_only && read_only::may_write(&manager, req.headers());
+    if !sso.requires_session(req.method(), req.uri().path())
+        || sso.session(req.headers()).is_some()
+        || has_api_key
+    {
+        return next.run(req).await;
+    }
+    // htmx requests come from the editor, which is reloaded on the sign-in page
+    let current_page = req
+        .headers()
+        .get("HX-Current-URL")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| Url::parse(value).ok());
+    if let Some(current_page) = current_page {
+        let login = format!("/auth/login?return_to={}", utf8_encode(current_page.path()));
+        return (StatusCode::UNAUTHORIZED, [("HX-Redirect", login)]).into_response();
+    }
+    if *req.method() == Method::GET && negotiate::negotiate(req.headers()) == Representation::Html {
+        let return_to = req.uri().path_and_query().map_or("/", |path| path.as_str());
+        return Redirect::to(&format!("/auth/login?return_to={}", utf8_encode(return_to)))
+            .into_response();
+    }
+    failure(
+        StatusCode::UNAUTHORIZED,
+        "Signing in is required, at /auth/login",
+    )
+}
+
+fn utf8_encode(value: &str) -> String {
+    percent_encoding::utf8_percent_encode(value, percent_encoding::NON_ALPHANUMERIC).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+    use serde_json::{json, Value};
+
+    use super::{local_path, Discovery, Sso, SsoRequirement};
+    use crate::{
+        config::{Config, SsoConfig},
+        utility,
+    };
+
+    fn sso() -> Sso {
+        let config = SsoConfig {
+            issuer:        "https://id.example.com".to_string(),
+            client_id:     "pasties".to_string(),
+            client_secret: None,
+            require:       SsoRequirement::default(),
+            scopes:        "openid".to_string(),
+            groups_claim:  "groups".to_string(),
+            admin_groups:  Vec::new(),
+            session_key:   None,
+        };
+        Sso::new(&Config::default(), &config)
+    }
+
+    fn discovery() -> Discovery {
+        Discovery {
+            issuer:                 "https://id.example.com".to_string(),
+            authorization_endpoint: "https://id.example.com/authorize".to_string(),
+            token_endpoint:         "https://id.example.com/token".to_string(),
+        }
+    }
+
+    /// An unsigned ID token with the given claims, over valid ones
+    fn id_token(overrides: Value) -> String {
+        let mut claims = json!({
+            "iss": "https://id.example.com",
+            "aud": "pasties",
+            "exp": utility::unix_timestamp() + 60,
+            "nonce": "nonce",
+            "sub": "user",
+        });
+        for (claim, value) in overrides.as_object().unwrap() {
+            claims[claim] = value.clone();
+        }
+        format!(
+            "{}.{}.signature",
+            URL_SAFE_NO_PAD.encode(r#"{"alg":"RS256"}"#),
+            URL_SAFE_NO_PAD.encode(claims.to_string())
+        )
+    }
+
+    #[test]
+    fn returns_to_local_paths_only() {
+        assert_eq!(local_path(Some("/abc?x=1".to_string())), "/abc?x=1");
+        assert_eq!(local_path(Some("//evil.example".to_string())), "/");
+        assert_eq!(local_path(Some("/\\evil.example".to_string())), "/");
+        assert_eq!(local_path(Some("https://evil.example".to_string())), "/");
+        assert_eq!(local_path(None), "/");
+    }
+
+    #[test]
+    fn accepts_valid_claims() {
+        let claims = sso().claims(&id_token(json!({})), &discovery(), "nonce");
+        assert_eq!(claims.unwrap()["sub"], "user");
+        let token = id_token(json!({ "aud": ["other", "pasties"] }));
+        assert!(sso().claims(&token, &discovery(), "nonce").is_ok());
+    }
+
+    #[test]
+    fn rejects_mismatched_claims() {
+        for overrides in [
+            json!({ "aud": "other" }),
+            json!({ "aud": ["other"] }),
+            json!({ "iss": "https://evil.example" }),
+            json!({ "exp": utility::unix_timestamp() - 60 }),
+            json!({ "sub": null }),
+        ] {
+            let token = id_token(overrides.clone());
+            assert!(
+                sso().claims(&token, &discovery(), "nonce").is_err(),
+                "{overrides}"
+            );
+        }
+        let token = id_token(json!({}));
+        assert!(sso().claims(&token, &discovery(), "other").is_err());
+        assert!(sso().claims("malformed", &discovery(), "nonce").is_err());
+    }
+}
@@ -0,0 +1,156 @@
+//! `structured` validates and pretty-prints JSON and YAML pastes, rendering them as a colored tree with collapsible objects and arrays
+
+use serde_json::Value;
+
+use crate::{render::Renderer, utility};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Syntax {
+    Json,
+    Yaml,
+}
+
+impl Syntax {
+    /// The syntax pastes of the given format are written in, if they are structured data
+    pub fn of_format(format: &str) -> Option<Self> {
+        match format {
+            "json" => Some(Self::Json),
+            "yaml" => Some(Self::Yaml),
+            _ => None,
+        }
+    }
+
+    fn parse(self, content: &str) -> Option<Value> {
+        match self {
+            Self::Json => serde_json::from_str(content).ok(),
+            Self::Yaml => serde_yaml::from_str(content).ok(),
+        }
+    }
+}
+
+/// Re-serializes structured content with consistent indentation, or returns `None` if it is invalid
+pub fn pretty_print(syntax: Syntax, content: &str) -> Option<String> {
+    let value = syntax.parse(content)?;
+    match syntax {
+        Syntax::Json => serde_json::to_string_pretty(&value).ok(),
+        Syntax::Yaml => serde_yaml::to_string(&value).ok(),
+    }
+}
+
+pub struct StructuredRenderer {
+    pub syntax: Syntax,
+}
+
+impl Renderer for StructuredRenderer {
+    fn render(&self, content: &str) -> String {
+        match self.syntax.parse(content) {
+            Some(value) => {
+                let mut html = String::from("<div class=\"tree\">");
+                match (self.syntax, children(&value).is_empty()) {
+                    // YAML documents don't wrap their top level in brackets, so there is nothing to collapse it by
+                    (Syntax::Yaml, false) => write_children(&mut html, self.syntax, &value),
+                    (Syntax::Yaml, true) => html.push_str(&format!("<div>{}</div>", scalar(self.syntax, &value))),
+                    _ => write_value(&mut html, self.syntax, None, &value, true),
+                }
+                html.push_str("</div>");
+                html
+            }
+            // Invalid content is still worth showing as-is, but with a hint as to why it isn't pretty-printed
+            None => format!(
+                "<p class=\"faint\">This paste could not be parsed, so it is shown as-is.</p><pre>{}</pre>",
+                utility::escape_html(content)
+            ),
+        }
+    }
+}
+
+/// The key a value is nested under in its parent, which is a name in objects and nothing in arrays
+type Key<'a> = Option<&'a str>;
+
+/// The values nested in an object or array, along with their keys
+fn children(value: &Value) -> Vec<(Key<'_>, &Value)> {
+    match value {
+        Value::Object(object) => object.iter().map(|(k, v)| (Some(k.as_str()), v)).collect(),
+        Value::Array(array) => array.iter().map(|v| (None, v)).collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn write_children(html: &mut String, syntax: Syntax, value: &Value) {
+    let children = children(value);
+    let count = children.len();
+    for (index, (key, child)) in children.into_iter().enumerate() {
+        write_value(html, syntax, key, child, index + 1 == count);
+    }
+}
+
+fn write_value(html: &mut String, syntax: Syntax, key: Key, value: &Value, last: bool) {
+    let (open, close) = match value {
+        Value::Object(_) => ("{", "}"),
+        _ => ("[", "]"),
+    };
+    let comma = match syntax == Syntax::Json && !last {
+        true => ",",
+        false => "",
+    };
+
+    if children(value).is_empty() {
+        html.push_str(&format!(
+            "<div>{}{}{comma}</div>",
+            key_prefix(syntax, key),
+            scalar(syntax, value)
+        ));
+        return;
+    }
+    let summary = match syntax {
+        Syntax::Json => format!("{}{open}", key_prefix(syntax, key)),
+        Syntax::Yaml => match key {
+            Some(key) => format!("<span class=\"key\">{}</span>:", yaml_string(key)),
+            None => "-".to_string(),
+        },
+    };
+    html.push_str(&format!(
+        "<details open><summary>{summary}</summary><div class=\"children\">"
+    ));
+    write_children(html, syntax, value);
+    html.push_str("</div>");
+    if syntax == Syntax::Json {
+        html.push_str(&format!("<div>{close}{comma}</div>"));
+    }
+    html.push_str("</details>");
+}
+
+/// What precedes a value on its line, e.g. `"name": ` in JSON or `- ` for YAML list items
+fn key_prefix(syntax: Syntax, key: Key) -> String {
+    match (syntax, key) {
+        (Syntax::Json, Some(key)) => format!(
+            "<span class=\"key\">{}</span>: ",
+            utility::escape_html(&Value::from(key).to_string())
+        ),
+        (Syntax::Yaml, Some(key)) => format!("<span class=\"key\">{}</span>: ", yaml_string(key)),
+        (Syntax::Json, None) => String::new(),
+        (Syntax::Yaml, None) => "- ".to_string(),
+    }
+}
+
+/// Renders a value without children, including empty objects and arrays
+fn scalar(syntax: Syntax, value: &Value) -> String {
+    let (class, text) = match value {
+        Value::Null => ("null", "null".to_string()),
+        Value::Bool(bool) => ("bool", bool.to_string()),
+        Value::Number(number) => ("number", number.to_string()),
+        Value::String(string) => match syntax {
+            Syntax::Json => ("string", utility::escape_html(&value.to_string())),
+            Syntax::Yaml => ("string", yaml_string(string)),
+        },
+        Value::Object(_) => ("punctuation", "{}".to_string()),
+        Value::Array(_) => ("punctuation", "[]".to_string()),
+    };
+    format!("<span class=\"{class}\">{text}</span>")
+}
+
+/// Formats a string the way it would be written in YAML, quoted only when necessary
+fn yaml_string(string: &str) -> String {
+    let yaml = serde_yaml::to_string(string).unwrap_or_else(|_| string.to_string());
+    utility::escape_html(yaml.trim_end())
+}
@@ -0,0 +1,105 @@
+//! `style` lets authors choose how their paste is presented, from a fixed set of options
+//! Options are stored with the paste and turned into CSS classes when it is rendered, so no CSS written by users ever ends up on a page
+
+use std::{fmt::Display, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Font {
+    #[default]
+    Sans,
+    Serif,
+    Mono,
+}
+
+impl Font {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Font::Sans => "sans",
+            Font::Serif => "serif",
+            Font::Mono => "mono",
+        }
+    }
+}
+
+impl FromStr for Font {
+    type Err = StyleError;
+
+    fn from_str(font: &str) -> Result<Self, Self::Err> {
+        match font {
+            "sans" => Ok(Font::Sans),
+            "serif" => Ok(Font::Serif),
+            "mono" => Ok(Font::Mono),
+            _ => Err(StyleError("font")),
+        }
+    }
+}
+
+/// How wide the paste is shown, `Normal` keeps it as narrow as the editor
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Width {
+    #[default]
+    Normal,
+    Wide,
+    Full,
+}
+
+impl Width {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Width::Normal => "normal",
+            Width::Wide => "wide",
+            Width::Full => "full",
+        }
+    }
+}
+
+impl FromStr for Width {
+    type Err = StyleError;
+
+    fn from_str(width: &str) -> Result<Self, Self::Err> {
+        match width {
+            "normal" => Ok(Width::Normal),
+            "wide" => Ok(Width::Wide),
+            "full" => Ok(Width::Full),
+            _ => Err(StyleError("width")),
+        }
+    }
+}
+
+/// A style option that isn't one of the allowed values, naming the option
+#[derive(Debug)]
+pub struct StyleError(&'static str);
+
+impl Display for StyleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "unknown {}", self.0)
+    }
+}
+
+/// How a paste is presented
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PasteStyle {
+    pub font:  Font,
+    pub width: Width,
+    /// Whether long lines in code blocks wrap instead of scrolling
+    pub wrap:  bool,
+}
+
+impl PasteStyle {
+    /// The CSS classes applied to the paste's container
+    pub fn classes(&self) -> String {
+        let mut classes = format!(
+            "paste-font-{} paste-width-{}",
+            self.font.as_str(),
+            self.width.as_str()
+        );
+        if self.wrap {
+            classes.push_str(" paste-wrap");
+        }
+        classes
+    }
+}
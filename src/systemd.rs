@@ -0,0 +1,69 @@
+//! `systemd` implements the parts of systemd's service protocol pasties uses: socket activation and readiness notification
+
+use std::{
+    env,
+    net::TcpListener,
+    os::{
+        fd::{FromRawFd, RawFd},
+        linux::net::SocketAddrExt,
+        unix::net::{SocketAddr, UnixDatagram, UnixListener},
+    },
+};
+
+use socket2::Socket;
+
+/// The first file descriptor systemd passes to activated services
+const LISTEN_FDS_START: RawFd = 3;
+
+/// A listening socket passed in by systemd, as configured by `ListenStream=` in the socket unit
+pub enum InheritedSocket {
+    Tcp(TcpListener),
+    Unix(UnixListener),
+}
+
+/// Takes ownership of the sockets passed in by systemd socket activation, if there are any meant for this process
+/// The environment variables are cleared afterwards, so they aren't inherited by child processes
+/// **Has to be called before any other threads are started**, as changing the environment isn't thread-safe
+pub fn listen_fds() -> Vec<InheritedSocket> {
+    let is_for_us = env::var("LISTEN_PID")
+        .ok()
+        .and_then(|pid| pid.parse::<u32>().ok())
+        .is_some_and(|pid| pid == std::process::id());
+    let count = env::var("LISTEN_FDS")
+        .ok()
+        .and_then(|count| count.parse::<RawFd>().ok())
+        .unwrap_or(0);
+    env::remove_var("LISTEN_PID");
+    env::remove_var("LISTEN_FDS");
+    env::remove_var("LISTEN_FDNAMES");
+    if !is_for_us {
+        return Vec::new();
+    }
+    (LISTEN_FDS_START..LISTEN_FDS_START + count)
+        .map(|fd| {
+            // SAFETY: systemd guarantees these descriptors are open sockets handed over to this process
+            let socket = unsafe { Socket::from_raw_fd(fd) };
+            match socket.local_addr() {
+                Ok(address) if address.is_unix() => InheritedSocket::Unix(socket.into()),
+                _ => InheritedSocket::Tcp(socket.into()),
+            }
+        })
+        .collect()
+}
+
+/// Tells systemd the service has finished starting up, for units with `Type=notify`
+/// Does nothing if pasties wasn't started by systemd
+pub fn notify_ready() {
+    let Ok(path) = env::var("NOTIFY_SOCKET") else {
+        return;
+    };
+    let address = match path.strip_prefix('@') {
+        Some(name) => SocketAddr::from_abstract_name(name),
+        None => SocketAddr::from_pathname(&path),
+    };
+    let result =
+        address.and_then(|address| UnixDatagram::unbound()?.send_to_addr(b"READY=1", &address));
+    if let Err(e) = result {
+        tracing::warn!("Failed to notify systemd of readiness via {path}: {e}");
+    }
+}
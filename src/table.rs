@@ -0,0 +1,53 @@
+//! `table` renders CSV and TSV pastes as an HTML table, with the first row as the header
+
+use csv::ReaderBuilder;
+
+use crate::{render::Renderer, utility};
+
+/// The most rows rendered into a table, as browsers struggle with huge tables. The raw paste still has all of them
+const MAX_ROWS: usize = 1000;
+
+/// Renders delimiter-separated values, e.g. `b','` for CSV and `b'\t'` for TSV
+pub struct TableRenderer {
+    pub delimiter: u8,
+}
+
+impl Renderer for TableRenderer {
+    fn render(&self, content: &str) -> String {
+        let mut reader = ReaderBuilder::new()
+            .delimiter(self.delimiter)
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(content.as_bytes());
+
+        let mut html = String::from("<div class=\"table-container\"><table>");
+        let mut rows = 0;
+        for record in reader.records() {
+            let record = match record {
+                Ok(record) => record,
+                // Content that fails to parse is still worth showing as-is
+                Err(_) => return format!("<pre>{}</pre>", utility::escape_html(content)),
+            };
+            rows += 1;
+            if rows > MAX_ROWS {
+                continue;
+            }
+            let cell = match rows {
+                1 => "th",
+                _ => "td",
+            };
+            html.push_str("<tr>");
+            for field in record.iter() {
+                html.push_str(&format!("<{cell}>{}</{cell}>", utility::escape_html(field)));
+            }
+            html.push_str("</tr>");
+        }
+        html.push_str("</table></div>");
+        if rows > MAX_ROWS {
+            html.push_str(&format!(
+                "<p>Only the first {MAX_ROWS} of {rows} rows are shown, the raw paste has all of them.</p>"
+            ));
+        }
+        html
+    }
+}
@@ -0,0 +1,107 @@
+//! `termbin` accepts pastes over plain TCP, so that `cat file | nc host 9999` creates a paste and prints its URL
+//! Clients like `nc` often don't close their side of the connection, so a paste is complete once the client goes quiet
+
+use std::{sync::Arc, time::Duration};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::Semaphore,
+    time::{self, Instant},
+};
+
+use crate::{
+    config::TermbinConfig,
+    model::{Manager, NewPasteData, MAX_CONTENT_LENGTH},
+    utility,
+};
+
+/// How long a client may stay silent before its paste is considered complete
+const IDLE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long a client may take to send its whole paste, so clients trickling bytes can't hold on to a connection
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The most clients sending pastes at once, further connections wait to be accepted
+const MAX_CONNECTIONS: usize = 64;
+
+/// The most bytes read from a client, which is just past the longest allowed paste
+const MAX_LENGTH: usize = MAX_CONTENT_LENGTH + 1;
+
+/// Binds the termbin listener
+/// **Panics** if it cannot be bound, like the HTTP listeners
+pub async fn bind(config: &TermbinConfig) -> TcpListener {
+    match TcpListener::bind(&config.listen).await {
+        Ok(listener) => listener,
+        Err(e) => panic!("Failed to bind to {}:\n    {e}", config.listen),
+    }
+}
+
+/// Accepts connections until the process is stopped, links to pastes are built from `base_url`
+pub async fn serve(listener: TcpListener, manager: Manager, base_url: String) {
+    if let Ok(address) = listener.local_addr() {
+        tracing::info!("Accepting termbin pastes at {address}!");
+    }
+    let connections = Arc::new(Semaphore::new(MAX_CONNECTIONS));
+    loop {
+        // The semaphore is never closed
+        let permit = connections.clone().acquire_owned().await.unwrap();
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                let (manager, base_url) = (manager.clone(), base_url.clone());
+                tokio::spawn(async move {
+                    handle(stream, manager, base_url).await;
+                    drop(permit);
+                });
+            }
+            Err(e) => {
+                tracing::warn!("Failed to accept a termbin connection: {e}");
+                time::sleep(Duration::from_secs(1)).await;
+            }
+        }
+    }
+}
+
+async fn handle(mut stream: TcpStream, manager: Manager, base_url: String) {
+    let mut content = Vec::new();
+    let mut buffer = [0; 8192];
+    let deadline = Instant::now() + READ_TIMEOUT;
+    while content.len() < MAX_LENGTH {
+        if Instant::now() >= deadline {
+            let _ = stream.write_all(b"The paste took too long to send\n").await;
+            return;
+        }
+        let timeout = IDLE_TIMEOUT.min(deadline - Instant::now());
+        match time::timeout(timeout, stream.read(&mut buffer)).await {
+            Ok(Ok(0)) => break,
+            // Going quiet completes the paste, running out of time doesn't
+            Err(_) if Instant::now() < deadline => break,
+            Err(_) => {}
+            Ok(Ok(read)) => content.extend_from_slice(&buffer[..read]),
+            Ok(Err(_)) => return,
+        }
+    }
+    let reply = match String::from_utf8(content) {
+        Ok(content) => {
+            let paste = NewPasteData {
+                url: String::new(),
+                content,
+                password: utility::random_string(),
+                format: String::new(),
+                draft: false,
+                max_views: None,
+                expires_in: None,
+                font: None,
+                width: None,
+                wrap: None,
+            };
+            match manager.create_paste(paste).await {
+                Ok(url) => format!("{base_url}/{url}\n"),
+                Err(e) => format!("{e}\n"),
+            }
+        }
+        Err(_) => "Pastes have to be valid UTF-8\n".to_string(),
+    };
+    let _ = stream.write_all(reply.as_bytes()).await;
+    let _ = stream.shutdown().await;
+}
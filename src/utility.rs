@@ -1,6 +1,10 @@
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::{
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 use rand::Rng;
+use serde::{de, Deserialize, Deserializer};
 use sha2::{Digest, Sha256};
 
 /// Retrieves the current time as a Unix timestamp.
@@ -12,6 +16,59 @@ pub fn unix_timestamp() -> i64 {
     since_epoch.as_secs().try_into().unwrap()
 }
 
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Formats a Unix timestamp in the Common Log Format, e.g. `10/Oct/2000:13:55:36 +0000`
+pub fn clf_timestamp(timestamp: i64) -> String {
+    let (year, month, day, seconds) = civil_date(timestamp);
+    format!(
+        "{day:02}/{}/{year}:{:02}:{:02}:{:02} +0000",
+        MONTHS[(month - 1) as usize],
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
+
+/// Formats a Unix timestamp as an HTTP date, e.g. `Tue, 10 Oct 2000 13:55:36 GMT`
+pub fn http_date(timestamp: i64) -> String {
+    const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+    let (year, month, day, seconds) = civil_date(timestamp);
+    // The epoch was a Thursday
+    let weekday = (timestamp.div_euclid(86_400) + 4).rem_euclid(7);
+    format!(
+        "{}, {day:02} {} {year} {:02}:{:02}:{:02} GMT",
+        WEEKDAYS[weekday as usize],
+        MONTHS[(month - 1) as usize],
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
+
+/// Splits a Unix timestamp into its year, month, day and the seconds into that day
+fn civil_date(timestamp: i64) -> (i64, i64, i64, i64) {
+    let (days, seconds) = (timestamp.div_euclid(86_400), timestamp.rem_euclid(86_400));
+    // Converts days since the epoch to a civil date, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day, seconds)
+}
+
 /// Computes the SHA256 hash of the provided string
 pub fn hash_string(input: String) -> String {
     let mut hasher = Sha256::new();
@@ -25,6 +82,37 @@ pub fn pseudoid() -> i64 {
     rand::thread_rng().gen::<i64>()
 }
 
+/// The digits of paste IDs in their short form
+const BASE62_DIGITS: &[u8; 62] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+/// Encodes an ID as base62, reading its bits as unsigned so negative IDs don't need a sign
+pub fn to_base62(id: i64) -> String {
+    let mut value = id as u64;
+    let mut digits = Vec::new();
+    loop {
+        digits.push(BASE62_DIGITS[(value % 62) as usize]);
+        value /= 62;
+        if value == 0 {
+            break;
+        }
+    }
+    digits.reverse();
+    String::from_utf8(digits).unwrap()
+}
+
+/// Decodes an ID encoded by `to_base62`, or returns `None` if it isn't valid base62 or too large
+pub fn from_base62(encoded: &str) -> Option<i64> {
+    if encoded.is_empty() {
+        return None;
+    }
+    let mut value: u64 = 0;
+    for digit in encoded.bytes() {
+        let digit = BASE62_DIGITS.iter().position(|&d| d == digit)? as u64;
+        value = value.checked_mul(62)?.checked_add(digit)?;
+    }
+    Some(value as i64)
+}
+
 pub fn random_string() -> String {
     let mut string = format!("{:X}", rand::thread_rng().gen::<u32>());
     let string_pad_len = 8 - string.len();
@@ -34,8 +122,91 @@ pub fn random_string() -> String {
     hash_string(string)
 }
 
+/// Escapes the characters that have a special meaning in HTML
+pub fn escape_html(input: &str) -> String {
+    let mut escaped = String::with_capacity(input.len());
+    for c in input.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Tidies up a user-supplied paste URL before it is validated
+/// Surrounding whitespace is trimmed, inner whitespace becomes `-`, runs of separators are collapsed into their first one, and leading and trailing `-` are stripped
+pub fn normalize_url(url: &str, lowercase: bool) -> String {
+    let mut normalized = String::with_capacity(url.len());
+    let mut previous_separator = false;
+    for c in url.trim().chars() {
+        let c = match c.is_whitespace() {
+            true => '-',
+            false => c,
+        };
+        let separator = c == '-' || c == '_';
+        if !(separator && previous_separator) {
+            normalized.push(c);
+        }
+        previous_separator = separator;
+    }
+    let normalized = normalized.trim_matches('-');
+    match lowercase {
+        true => normalized.to_lowercase(),
+        false => normalized.to_string(),
+    }
+}
+
 pub fn is_url_safe(string: &str) -> bool {
     string
         .bytes()
         .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
 }
+
+/// Deserializes an optional field that forms submit as an empty string when left blank
+/// JSON may send the value itself, or as a string like forms do
+pub fn empty_as_none<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr + Deserialize<'de>,
+    T::Err: std::fmt::Display,
+{
+    match Option::<StringOr<T>>::deserialize(deserializer)? {
+        Some(StringOr::String(value)) if !value.is_empty() => {
+            value.parse().map(Some).map_err(de::Error::custom)
+        }
+        Some(StringOr::Value(value)) => Ok(Some(value)),
+        _ => Ok(None),
+    }
+}
+
+/// A value sent as a string, as in forms, or as itself, as in JSON
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StringOr<T> {
+    String(String),
+    Value(T),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::normalize_url;
+
+    #[test]
+    fn tidies_up_urls() {
+        assert_eq!(normalize_url(" my  notes-- ", false), "my-notes");
+        assert_eq!(normalize_url("a__b-_c", false), "a_b-c");
+        assert_eq!(normalize_url("-_x_-", false), "x_");
+        assert_eq!(normalize_url("\tMy Notes\n", false), "My-Notes");
+    }
+
+    #[test]
+    fn lowercases_urls_if_asked_to() {
+        assert_eq!(normalize_url("My Notes", true), "my-notes");
+        assert_eq!(normalize_url("My Notes", false), "My-Notes");
+    }
+}
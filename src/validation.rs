@@ -0,0 +1,104 @@
+//! `validation` holds the parts of a paste whose rules are checked once, when they are constructed
+//! Holding a `PasteUrl`, `PastePassword` or `PasteContent` means the value has passed its checks, so creating and changing pastes don't repeat them
+
+use std::fmt::{self, Display};
+
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::{
+    model::PasteError,
+    utility::{self, hash_string, is_url_safe},
+};
+
+/// The longest a URL or password can be, in bytes
+const MAX_LENGTH: usize = 250;
+
+/// A paste's URL within its namespace
+/// It is URL-safe, at most 250 bytes long, and doesn't look like a UUID, as it would be looked up as another paste's UUID
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PasteUrl(String);
+
+impl PasteUrl {
+    pub fn new(url: String) -> Result<Self, PasteError> {
+        match !url.is_empty()
+            && is_url_safe(&url)
+            && url.len() <= MAX_LENGTH
+            && Uuid::parse_str(&url).is_err()
+        {
+            true => Ok(Self(url)),
+            false => Err(PasteError::InvalidUrl),
+        }
+    }
+
+    /// A random URL, used when none is given
+    pub fn random() -> Self {
+        Self(utility::random_string())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn into_string(self) -> String {
+        self.0
+    }
+}
+
+impl Display for PasteUrl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// A paste's or collection's edit password, at most 250 bytes long
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PastePassword(String);
+
+impl PastePassword {
+    pub fn new(password: String) -> Result<Self, PasteError> {
+        match !password.is_empty() && password.len() <= MAX_LENGTH {
+            true => Ok(Self(password)),
+            false => Err(PasteError::InvalidPassword),
+        }
+    }
+
+    /// A random password, used when none is given
+    pub fn random() -> Self {
+        Self(utility::random_string())
+    }
+
+    /// The hash the password is stored as
+    pub fn hash(&self) -> String {
+        hash_string(self.0.clone())
+    }
+
+    pub fn into_string(self) -> String {
+        self.0
+    }
+}
+
+/// A paste's content, or a chunk appended to it. It isn't empty, and is at most as long as the instance or namespace allows
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct PasteContent(String);
+
+impl PasteContent {
+    /// **Arguments**
+    /// * `content`: the content to check
+    /// * `max_length`: the longest the content can be, in bytes
+    pub fn new(content: String, max_length: usize) -> Result<Self, PasteError> {
+        match !content.is_empty() && content.len() <= max_length {
+            true => Ok(Self(content)),
+            false => Err(PasteError::InvalidContent),
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn into_string(self) -> String {
+        self.0
+    }
+}
@@ -0,0 +1,191 @@
+//! `webhooks` posts messages about new pastes to chat services, so teams running an internal instance see its activity
+//! Every `[[webhooks]]` entry in the configuration is a target. Messages are written to an outbox table first and delivered from there in the background,
+//! with failed deliveries retried with exponential backoff, so messages survive unreachable targets and restarts
+
+use std::{sync::Arc, time::Duration};
+
+use axum::http::header;
+use futures_util::future::join_all;
+use serde::Deserialize;
+use serde_json::json;
+use sqlx::SqlitePool;
+use tokio::sync::{
+    broadcast::{error::RecvError, Receiver},
+    Notify,
+};
+
+use crate::{
+    config::{Config, WebhookConfig},
+    database::{self, OutboxEntry},
+    events::PasteEvent,
+    utility,
+};
+
+/// How many times delivering a message is attempted before it is given up on
+const MAX_ATTEMPTS: i64 = 10;
+
+/// How long to wait before the first retry, in seconds. The delay doubles after every failed attempt
+const RETRY_DELAY: i64 = 10;
+
+/// The longest delay between two attempts, in seconds
+const MAX_RETRY_DELAY: i64 = 60 * 60;
+
+/// How often the outbox is checked for messages due for a retry
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How long a single delivery may take
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The most messages attempted at once
+const BATCH_SIZE: i64 = 50;
+
+/// The payload shape a webhook target expects
+#[derive(Deserialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookKind {
+    /// Discord's incoming webhooks, `{"content": ...}`
+    #[default]
+    Discord,
+    /// Slack's incoming webhooks, `{"text": ...}`
+    Slack,
+    /// Generic webhooks of matrix-hookshot, `{"text": ..., "username": ...}`
+    Matrix,
+}
+
+#[derive(Clone)]
+pub struct Notifier {
+    targets:  Vec<WebhookConfig>,
+    base_url: String,
+    client:   reqwest::Client,
+    pool:     SqlitePool,
+    /// Wakes the delivery task when new messages were queued
+    queued:   Arc<Notify>,
+}
+
+impl Notifier {
+    pub fn new(config: &Config, pool: SqlitePool) -> Self {
+        Self {
+            targets: config.webhooks.clone(),
+            base_url: config.base_url(None),
+            client: reqwest::Client::new(),
+            pool,
+            queued: Arc::default(),
+        }
+    }
+
+    /// The message about an event, if it is worth telling the chat about. Only pastes becoming public are
+    fn message(&self, event: &PasteEvent) -> Option<String> {
+        match event {
+            PasteEvent::Created { url, hidden: false } | PasteEvent::Published { url } => {
+                Some(format!("New paste: {}/{url}", self.base_url))
+            }
+            _ => None,
+        }
+    }
+
+    /// Notifies the targets about paste events from `events` in the background, until the event bus is gone
+    /// Messages still in the outbox from before a restart are delivered as well
+    pub fn listen(self, mut events: Receiver<PasteEvent>) {
+        if self.targets.is_empty() {
+            return;
+        }
+        tokio::spawn(self.clone().deliver());
+        tokio::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) => self.enqueue(&event).await,
+                    Err(RecvError::Lagged(skipped)) => {
+                        tracing::warn!("Webhooks fell behind, {skipped} events were not delivered")
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+
+    /// Queues a message about the event for every target
+    async fn enqueue(&self, event: &PasteEvent) {
+        let Some(message) = self.message(event) else {
+            return;
+        };
+        let now = utility::unix_timestamp();
+        for target in &self.targets {
+            let body = match target.kind {
+                WebhookKind::Discord => json!({ "content": message }),
+                WebhookKind::Slack => json!({ "text": message }),
+                WebhookKind::Matrix => json!({ "text": message, "username": "pasties" }),
+            };
+            let queued =
+                database::insert_outbox_entry(&self.pool, &target.url, &body.to_string(), now)
+                    .await;
+            if let Err(e) = queued {
+                tracing::error!("Failed to queue a webhook for {}: {e:?}", target.url);
+            }
+        }
+        self.queued.notify_one();
+    }
+
+    /// Delivers due messages from the outbox, whenever new ones are queued and every `POLL_INTERVAL` for retries
+    async fn deliver(self) {
+        loop {
+            let due = database::retrieve_due_outbox_entries(
+                &self.pool,
+                utility::unix_timestamp(),
+                BATCH_SIZE,
+            )
+            .await;
+            let batch_size = match due {
+                Ok(entries) => {
+                    let batch_size = entries.len() as i64;
+                    join_all(entries.into_iter().map(|entry| self.attempt(entry))).await;
+                    batch_size
+                }
+                Err(e) => {
+                    tracing::error!("Failed to read the webhook outbox: {e:?}");
+                    0
+                }
+            };
+            // A full batch means more messages may be due already
+            if batch_size < BATCH_SIZE {
+                let _ = tokio::time::timeout(POLL_INTERVAL, self.queued.notified()).await;
+            }
+        }
+    }
+
+    /// Attempts to deliver a message, removing it from the outbox once it was delivered or has failed too often
+    async fn attempt(&self, entry: OutboxEntry) {
+        let result = self
+            .client
+            .post(&entry.target)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(entry.body)
+            .timeout(DELIVERY_TIMEOUT)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        let attempts = entry.attempts + 1;
+        let outcome = match result {
+            Ok(_) => database::delete_outbox_entry(&self.pool, entry.id).await,
+            Err(e) if attempts >= MAX_ATTEMPTS => {
+                tracing::warn!(
+                    "Gave up delivering a webhook to {} after {attempts} attempts: {e}",
+                    entry.target
+                );
+                database::delete_outbox_entry(&self.pool, entry.id).await
+            }
+            Err(e) => {
+                let delay = (RETRY_DELAY << (attempts - 1)).min(MAX_RETRY_DELAY);
+                tracing::debug!(
+                    "Failed to deliver a webhook to {}, retrying in {delay}s: {e}",
+                    entry.target
+                );
+                let next_attempt = utility::unix_timestamp() + delay;
+                database::reschedule_outbox_entry(&self.pool, entry.id, attempts, next_attempt)
+                    .await
+            }
+        };
+        if let Err(e) = outcome {
+            tracing::error!("Failed to update the webhook outbox: {e:?}");
+        }
+    }
+}